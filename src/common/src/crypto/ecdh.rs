@@ -21,9 +21,15 @@ pub fn agree(secret: &Secret, public: &Public) -> Result<Secret, Error> {
     Secret::import_key(&shared[0..32]).map_err(|_| Error::Secp256k1(secp256k1::Error::InvalidSecretKey))
 }
 
-/// Recovers the public key from the signature for the message
+/// Recovers the public key from the signature for the message.
+/// The recovery id can be 0-3, or the legacy 27/28.
 pub fn recover(signature: &H520, message: &H256) -> Result<Public, Error> {
-    let rsig = RecoverableSignature::from_compact(&signature[0..64], RecoveryId::from_i32(signature[64] as i32)?)?;
+    let rec_id = match signature[64] {
+        v @ 0..=3 => v,
+        v @ 27..=28 => v - 27,
+        _ => return Err(Error::InvalidRecoveryId),
+    };
+    let rsig = RecoverableSignature::from_compact(&signature[0..64], RecoveryId::from_i32(rec_id as i32)?)?;
 
     let pubkey = &SECP256K1.recover_ecdsa(&Message::from_slice(&message[..])?, &rsig)?;
     let serialized = pubkey.serialize_uncompressed();
@@ -34,8 +40,26 @@ pub fn recover(signature: &H520, message: &H256) -> Result<Public, Error> {
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
-    use crate::crypto::ecdh::agree;
-    use crate::{Public, Secret};
+    use crate::crypto::ecdh::{agree, recover};
+    use crate::{sign, Error, KeyPair, Public, Secret, H256, H520};
+
+    #[test]
+    fn recover_works() {
+        let key_pair = KeyPair::random();
+        let message = H256::random();
+        let mut signature = sign(key_pair.secret(), &message).unwrap();
+        assert!(signature[64] <= 1);
+        assert_eq!(recover(&H520::from(signature), &message).unwrap(), *key_pair.public());
+
+        // legacy v
+        signature[64] += 27;
+        assert_eq!(recover(&H520::from(signature), &message).unwrap(), *key_pair.public());
+
+        for v in [4, 26, 29, 255] {
+            signature[64] = v;
+            assert!(matches!(recover(&H520::from(signature), &message), Err(Error::InvalidRecoveryId)));
+        }
+    }
 
     #[test]
     fn test_agree() {
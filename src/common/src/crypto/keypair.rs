@@ -1,28 +1,44 @@
 //! Secret key implementation.
 
+use std::fmt;
 use std::str::FromStr;
+use std::sync::OnceLock;
 use hex::{FromHexError, ToHex};
 use secp256k1::constants::SECRET_KEY_SIZE as SECP256K1_SECRET_KEY_SIZE;
 use secp256k1::{Message, PublicKey, SecretKey};
 // Why do we need this? http://www.daemonology.net/blog/2014-09-04-how-to-zero-a-buffer.html
 use zeroize::Zeroize;
 use crate::error::Error;
-use crate::{H256, H512, SECP256K1};
+use crate::{keccak, Address, H256, H512, SECP256K1};
 
 use secp256k1::rand::rngs::OsRng;
 
 pub type Public = H512;
 
+/// The address of a public key, the last 20 bytes of its keccak hash
+pub fn public_to_address(public: &Public) -> Address {
+    Address::from_slice(&keccak(public.as_bytes())[12..])
+}
+
 impl From<FromHexError> for Error {
     fn from(_: FromHexError) -> Self {
         Error::CannotParseHexString
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct KeyPair {
     secret: Secret,
     public: Public,
+    /// The address of `public`, derived on first use
+    address: OnceLock<Address>,
+}
+
+impl PartialEq for KeyPair {
+    fn eq(&self, other: &Self) -> bool {
+        // whether the address is derived yet does not matter
+        self.secret == other.secret && self.public == other.public
+    }
 }
 
 impl KeyPair {
@@ -35,7 +51,11 @@ impl KeyPair {
     pub fn from_secret_key(secret_key: SecretKey) -> Self {
         let public_key = PublicKey::from_secret_key(&SECP256K1, &secret_key);
         let serialized = public_key.serialize_uncompressed();
-        Self { secret: Secret::from(secret_key), public: Public::from_slice(&serialized[1..65]) }
+        Self {
+            secret: Secret::from(secret_key),
+            public: Public::from_slice(&serialized[1..65]),
+            address: OnceLock::new(),
+        }
     }
 
     pub fn public(&self) -> &Public {
@@ -45,14 +65,37 @@ impl KeyPair {
     pub fn secret(&self) -> &Secret {
         &self.secret
     }
+
+    /// The address of the public key, computed once and cached
+    pub fn address(&self) -> Address {
+        *self.address.get_or_init(|| public_to_address(&self.public))
+    }
 }
 
-/// Represents secret key
-#[derive(Debug, PartialEq, Clone)]
+/// Represents secret key. `Debug` does not print the key and comparison takes
+/// the same time wherever the keys differ.
+#[derive(Clone)]
 pub struct Secret {
     inner: H256,
 }
 
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner
+            .as_bytes()
+            .iter()
+            .zip(other.inner.as_bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
 impl Drop for Secret {
     fn drop(&mut self) {
         self.inner.as_bytes_mut().zeroize()
@@ -181,7 +224,8 @@ pub fn sign(secret: &Secret, message: &H256) -> Result<[u8;65], Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{H256, Secret, sign};
+    use crate::{public_to_address, Address, H256, KeyPair, Secret, sign};
+    use std::str::FromStr;
 
     #[test]
     fn test_sign() {
@@ -193,6 +237,23 @@ mod tests {
         assert_eq!(s, [182, 182, 244, 193, 65, 89, 128, 178, 40, 121, 127, 32, 179, 105, 30, 133, 208, 112, 255, 162, 45, 171, 138, 47, 71, 75, 182, 177, 36, 223, 7, 174, 101, 191, 217, 45, 254, 26, 10, 67, 76, 22, 29, 43, 57, 71, 4, 67, 127, 138, 165, 169, 203, 93, 61, 18, 76, 208, 229, 96, 14, 85, 252, 29, 0]);
     }
 
+    #[test]
+    fn address_works() {
+        let secret =
+            Secret::copy_from_str("0000000000000000000000000000000000000000000000000000000000000001").unwrap();
+        let key_pair = KeyPair::from_secret_key(secret.to_secp256k1_secret().unwrap());
+        let expected = Address::from_str("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+
+        let other = key_pair.clone();
+        assert_eq!(key_pair.address(), expected);
+        assert_eq!(key_pair.address(), expected);
+        assert_eq!(public_to_address(key_pair.public()), expected);
+
+        // the clone has not derived its address yet but is still equal
+        assert_eq!(other, key_pair);
+        assert_eq!(other.address(), key_pair.address());
+    }
+
     #[test]
     fn test_xor() {
         // Just some random values for secret/public to check we agree with previous implementation.
@@ -205,6 +266,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn secret_debug_and_eq_works() {
+        let hex = "b71c71a67e1177ad4e901695e1b4b9ee17ae16c6668d313eac2f96dbcda3f291";
+        let secret = Secret::copy_from_str(hex).unwrap();
+        let key_pair = KeyPair::from_secret_key(secret.to_secp256k1_secret().unwrap());
+        assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+        assert!(!format!("{:?}", key_pair).contains(&hex[..16]));
+
+        assert_eq!(secret, Secret::copy_from_str(hex).unwrap());
+        assert_ne!(secret, Secret::zero());
+        let mut last_byte = secret.as_bytes().to_vec();
+        last_byte[31] ^= 1;
+        assert_ne!(secret, Secret::copy_from_slice(&last_byte).unwrap());
+    }
+
     #[test]
     fn test_secret_as_ref() {
         // Just some random values for secret/public to check we agree with previous implementation.
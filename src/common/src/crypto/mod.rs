@@ -1,3 +1,4 @@
 pub mod keypair;
 pub mod ecies;
 pub mod ecdh;
+pub mod random;
@@ -0,0 +1,25 @@
+//! Random values for protocol use.
+
+use crate::H256;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+/// Generate a 32 byte nonce from the operating system CSPRNG
+pub fn random_nonce() -> H256 {
+    let mut nonce = H256::zero();
+    OsRng.fill_bytes(nonce.as_bytes_mut());
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::random_nonce;
+
+    #[test]
+    fn random_nonce_works() {
+        let a = random_nonce();
+        let b = random_nonce();
+        assert_eq!(a.as_bytes().len(), 32);
+        assert_ne!(a, b);
+    }
+}
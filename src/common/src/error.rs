@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug)]
 pub enum Error {
     Secp256k1(secp256k1::Error),
@@ -6,5 +8,53 @@ pub enum Error {
     InvalidLength,
     CannotParseHexString,
     /// Invalid message for decryption
-    InvalidMessage
-}
\ No newline at end of file
+    InvalidMessage,
+    /// The recovery id of a signature is neither 0-3 nor a legacy 27/28
+    InvalidRecoveryId,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // secp256k1 errors are already prefixed with "secp: "
+            Error::Secp256k1(e) => write!(f, "{}", e),
+            Error::FromHexError(e) => write!(f, "invalid hex: {}", e),
+            Error::InvalidLength => write!(f, "invalid length"),
+            Error::CannotParseHexString => write!(f, "cannot parse hex string"),
+            Error::InvalidMessage => write!(f, "invalid message for decryption"),
+            Error::InvalidRecoveryId => write!(f, "invalid recovery id"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Secp256k1(e) => Some(e),
+            Error::FromHexError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, H256, Secret};
+    use std::str::FromStr;
+
+    #[test]
+    fn display_works() {
+        assert_eq!(Error::InvalidLength.to_string(), "invalid length");
+        assert_eq!(Error::InvalidMessage.to_string(), "invalid message for decryption");
+
+        let e: Error = H256::from_str("zz").unwrap_err().into();
+        assert!(e.to_string().starts_with("invalid hex: "));
+
+        let e = Secret::import_key(&[0u8; 32]).unwrap_err();
+        assert_eq!(e.to_string(), "secp: malformed or out-of-range secret key");
+
+        // usable behind a boxed std error
+        let boxed: Box<dyn std::error::Error> = Box::new(e);
+        assert!(boxed.source().is_some());
+    }
+}
@@ -1,4 +1,5 @@
 use hmac::{Hmac, Mac};
+use ripemd::Ripemd160;
 use sha2::{Digest, Sha256};
 use tiny_keccak::{Hasher as KeccakHasherTrait, Keccak};
 use fixed_hash::construct_fixed_hash;
@@ -32,14 +33,8 @@ macro_rules! impl_uint_conversions {
 	};
 }
 
-construct_fixed_hash! {
-    #[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
-    pub struct H160(20);
-}
-construct_fixed_hash! {
-    #[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
-    pub struct H256(32);
-}
+construct_fixed_hash! { pub struct H160(20); }
+construct_fixed_hash! { pub struct H256(32); }
 
 construct_fixed_hash! { pub struct H520(65); }
 construct_fixed_hash! { pub struct H512(64); }
@@ -59,7 +54,7 @@ macro_rules! impl_fixed_hash_rlp {
 		impl rlp::Decodable for $name {
 			fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::Error> {
 				rlp.decoder().decode_value(|bytes| match bytes.len().cmp(&$size) {
-					core::cmp::Ordering::Less => Err(rlp::Error::RlpIsTooShort),
+					core::cmp::Ordering::Less => Err(rlp::Error::RlpIsTooShort { at: rlp.offset() }),
 					core::cmp::Ordering::Greater => Err(rlp::Error::RlpIsTooBig),
 					core::cmp::Ordering::Equal => {
 						let mut t = [0u8; $size];
@@ -75,6 +70,99 @@ macro_rules! impl_fixed_hash_rlp {
 impl_fixed_hash_rlp!(H256, 32);
 impl_fixed_hash_rlp!(H512, 64);
 
+/// Add a checked `try_from_slice` to a fixed-sized hash type, `from_slice` panics on a length mismatch.
+macro_rules! impl_fixed_hash_try_from_slice {
+	($name: ident, $size: expr) => {
+		impl $name {
+			/// Build the hash from a slice of exactly its length, `Error::InvalidLength` otherwise
+			pub fn try_from_slice(bytes: &[u8]) -> Result<Self, Error> {
+				if bytes.len() != $size {
+					return Err(Error::InvalidLength);
+				}
+				Ok($name::from_slice(bytes))
+			}
+		}
+	}
+}
+
+impl_fixed_hash_try_from_slice!(H256, 32);
+impl_fixed_hash_try_from_slice!(H512, 64);
+impl_fixed_hash_try_from_slice!(H520, 65);
+
+/// The two hashes back to back, `a` first
+pub fn concat_h256(a: &H256, b: &H256) -> [u8; 64] {
+	let mut out = [0u8; 64];
+	out[..32].copy_from_slice(a.as_bytes());
+	out[32..].copy_from_slice(b.as_bytes());
+	out
+}
+
+/// Add serde support to a fixed-sized hash type created by `construct_fixed_hash!`.
+/// Human readable formats use the `0x` prefixed hex string, others use the raw bytes.
+#[cfg(feature = "serialize")]
+macro_rules! impl_fixed_hash_serde {
+	($name: ident, $size: expr) => {
+		impl serde::Serialize for $name {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				if serializer.is_human_readable() {
+					serializer.serialize_str(&crate::to_hex_prefixed(self.as_bytes()))
+				} else {
+					serializer.serialize_bytes(self.as_bytes())
+				}
+			}
+		}
+
+		impl<'de> serde::Deserialize<'de> for $name {
+			fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				struct HashVisitor;
+
+				impl<'de> serde::de::Visitor<'de> for HashVisitor {
+					type Value = $name;
+
+					fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+						write!(f, "a 0x-prefixed hex string or {} bytes", $size)
+					}
+
+					fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<$name, E> {
+						let bytes = crate::from_hex_prefixed(v).map_err(E::custom)?;
+						self.visit_bytes(&bytes)
+					}
+
+					fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<$name, E> {
+						if v.len() != $size {
+							return Err(E::invalid_length(v.len(), &self));
+						}
+						Ok($name::from_slice(v))
+					}
+
+					fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<$name, A::Error> {
+						let mut bytes = [0u8; $size];
+						for (i, b) in bytes.iter_mut().enumerate() {
+							*b = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+						}
+						Ok($name(bytes))
+					}
+				}
+
+				if deserializer.is_human_readable() {
+					deserializer.deserialize_str(HashVisitor)
+				} else {
+					deserializer.deserialize_bytes(HashVisitor)
+				}
+			}
+		}
+	}
+}
+
+#[cfg(feature = "serialize")]
+impl_fixed_hash_serde!(H160, 20);
+#[cfg(feature = "serialize")]
+impl_fixed_hash_serde!(H256, 32);
+#[cfg(feature = "serialize")]
+impl_fixed_hash_serde!(H512, 64);
+#[cfg(feature = "serialize")]
+impl_fixed_hash_serde!(H520, 65);
+
 impl_uint_conversions!(H256, U256);
 
 /// Trait describing an object that can hash a slice of bytes. Used to abstract
@@ -90,6 +178,8 @@ pub trait Hasher: Sync + Send {
 
 pub fn sha256(data: &[u8]) -> H256 { H256::from_slice(Sha256::digest(data).as_slice()) }
 
+pub fn ripemd160(data: &[u8]) -> H160 { H160::from_slice(Ripemd160::digest(data).as_slice()) }
+
 pub fn hmac_sha256(key: &H256, input: &[u8], auth_data: &[u8]) -> H256 {
     let mut hmac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("invalid key to hmac");
     hmac.update(input);
@@ -101,6 +191,17 @@ pub fn keccak(x: &[u8]) -> H256 {
     KeccakHasher::hash(x)
 }
 
+/// The keccak hash of the concatenated slices, without building the concatenation
+pub fn keccak_concat(slices: &[&[u8]]) -> H256 {
+    let mut keccak = Keccak::v256();
+    for s in slices {
+        keccak.update(s);
+    }
+    let mut out = [0u8; 32];
+    keccak.finalize(&mut out);
+    H256::from(out)
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct KeccakHasher;
 impl Hasher for KeccakHasher {
@@ -120,3 +221,76 @@ impl From<fixed_hash::rustc_hex::FromHexError> for Error{
         Error::FromHexError(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{concat_h256, keccak, keccak_concat, ripemd160, Error, H160, H256, H512, H520};
+    use std::str::FromStr;
+
+    #[test]
+    fn try_from_slice_works() {
+        let bytes = [7u8; 65];
+        assert_eq!(H256::try_from_slice(&bytes[..32]).unwrap(), H256::from([7u8; 32]));
+        assert_eq!(H512::try_from_slice(&bytes[..64]).unwrap(), H512::from([7u8; 64]));
+        assert_eq!(H520::try_from_slice(&bytes).unwrap(), H520::from([7u8; 65]));
+
+        // short
+        assert!(matches!(H256::try_from_slice(&bytes[..31]), Err(Error::InvalidLength)));
+        assert!(matches!(H512::try_from_slice(&[]), Err(Error::InvalidLength)));
+
+        // long
+        assert!(matches!(H256::try_from_slice(&bytes[..33]), Err(Error::InvalidLength)));
+        assert!(matches!(H512::try_from_slice(&bytes), Err(Error::InvalidLength)));
+    }
+
+    #[test]
+    fn concat_h256_works() {
+        let a = H256::from_low_u64_be(1);
+        let b = H256::from_low_u64_be(2);
+        let out = concat_h256(&a, &b);
+        assert_eq!(&out[..32], a.as_bytes());
+        assert_eq!(&out[32..], b.as_bytes());
+        assert_eq!(H512::from(out), H512::from_slice(&out));
+    }
+
+    #[test]
+    fn keccak_concat_works() {
+        let a: &[u8] = b"hello ";
+        let b: &[u8] = b"world";
+        assert_eq!(keccak_concat(&[a, b]), keccak(&[a, b].concat()));
+        assert_eq!(keccak_concat(&[a, &[], b]), keccak(b"hello world"));
+        assert_eq!(keccak_concat(&[]), keccak(&[]));
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serde_hex_works() {
+        let h = H256::from_low_u64_be(0xabcd);
+        let json = serde_json::to_string(&h).unwrap();
+        assert_eq!(json, "\"0x000000000000000000000000000000000000000000000000000000000000abcd\"");
+        assert_eq!(serde_json::from_str::<H256>(&json).unwrap(), h);
+
+        // too short for the hash type
+        assert!(serde_json::from_str::<H256>("\"0xabcd\"").is_err());
+
+        let h = H520::random();
+        let bytes = bincode::serialize(&h).unwrap();
+        assert_eq!(bincode::deserialize::<H520>(&bytes).unwrap(), h);
+    }
+
+    #[test]
+    fn ripemd160_works() {
+        let cases = [
+            ("", "9c1185a5c5e9fc54612808977ee8f548b2258d31"),
+            ("abc", "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc"),
+            ("message digest", "5d0689ef49d2fae572b881b123a85ffa21595f36"),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "9b752e45573d4b39f4dbd3323cab82bf63326bfb",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ripemd160(input.as_bytes()), H160::from_str(expected).unwrap());
+        }
+    }
+}
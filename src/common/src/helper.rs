@@ -9,3 +9,32 @@ macro_rules! ensure {
     }};
 }
 
+
+/// Lowercase hex of `bytes` with the `0x` prefix
+pub fn to_hex_prefixed(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Decode hex with or without the `0x` prefix
+pub fn from_hex_prefixed(s: &str) -> Result<Vec<u8>, crate::Error> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(|_| crate::Error::CannotParseHexString)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{from_hex_prefixed, to_hex_prefixed, Error};
+
+    #[test]
+    fn hex_prefixed_works() {
+        let bytes = vec![0x00, 0xab, 0x12];
+        assert_eq!(to_hex_prefixed(&bytes), "0x00ab12");
+        assert_eq!(to_hex_prefixed(&[]), "0x");
+
+        assert_eq!(from_hex_prefixed(&to_hex_prefixed(&bytes)).unwrap(), bytes);
+        assert_eq!(from_hex_prefixed("00AB12").unwrap(), bytes);
+        assert_eq!(from_hex_prefixed("0x").unwrap(), Vec::<u8>::new());
+
+        assert!(matches!(from_hex_prefixed("0xabc"), Err(Error::CannotParseHexString)));
+        assert!(matches!(from_hex_prefixed("0xzz"), Err(Error::CannotParseHexString)));
+    }
+}
@@ -1,6 +1,7 @@
 pub use crypto::keypair::*;
 pub use crypto::ecdh::*;
 pub use crypto::ecies::*;
+pub use crypto::random::*;
 
 pub use crate::error::*;
 pub use crate::hash::*;
@@ -11,7 +12,9 @@ pub use crate::uint::*;
 pub type Address = H160;
 
 #[cfg(any(feature = "std"))]
-pub use crate::serialization::{from_vec, to_vec};
+pub use crate::serialization::{
+    from_vec, from_vec_versioned, to_vec, to_vec_versioned, SerializationError,
+};
 
 mod hash;
 mod helper;
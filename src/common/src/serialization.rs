@@ -1,9 +1,17 @@
 use bincode::Error;
 use serde::{Deserialize, Serialize};
 
+/// The current format version prepended by `to_vec_versioned`.
+/// Version 2 serializes the fixed hashes as 0x-hex strings instead of raw bytes.
+pub const FORMAT_VERSION: u8 = 2;
+
 #[derive(Debug)]
 pub enum SerializationError {
-    BincodeError(bincode::Error)
+    BincodeError(bincode::Error),
+    /// The version byte does not match `FORMAT_VERSION`
+    UnsupportedVersion(u8),
+    /// The buffer is too short to contain the version byte
+    MissingVersion,
 }
 
 impl From<bincode::Error> for SerializationError {
@@ -26,4 +34,64 @@ pub fn from_vec<'a, T>(raw: &'a Vec<u8>) -> Result<T, SerializationError>
 {
     let v = bincode::deserialize(raw)?;
     Ok(v)
-}
\ No newline at end of file
+}
+
+/// Same as `to_vec`, but prepends a 1-byte `FORMAT_VERSION` to the output
+pub fn to_vec_versioned<T>(value: &T) -> Result<Vec<u8>, SerializationError>
+    where
+        T: Serialize,
+{
+    let mut v = vec![FORMAT_VERSION];
+    bincode::serialize_into(&mut v, value)?;
+    Ok(v)
+}
+
+/// Decodes a buffer produced by `to_vec_versioned`, rejecting unknown format versions
+pub fn from_vec_versioned<'a, T>(raw: &'a [u8]) -> Result<T, SerializationError>
+    where
+        T: Deserialize<'a>,
+{
+    match raw.split_first() {
+        None => Err(SerializationError::MissingVersion),
+        Some((&FORMAT_VERSION, rest)) => Ok(bincode::deserialize(rest)?),
+        Some((&version, _)) => Err(SerializationError::UnsupportedVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serialization::{from_vec_versioned, to_vec_versioned, SerializationError, FORMAT_VERSION};
+    use crate::H256;
+
+    #[test]
+    fn versioned_works() {
+        let v = to_vec_versioned(&(1u64, "abc".to_string())).unwrap();
+        assert_eq!(v[0], FORMAT_VERSION);
+
+        let decoded: (u64, String) = from_vec_versioned(&v).unwrap();
+        assert_eq!(decoded, (1, "abc".to_string()));
+    }
+
+    #[test]
+    fn versioned_unknown_version_fails() {
+        let mut v = to_vec_versioned(&1u64).unwrap();
+        v[0] = FORMAT_VERSION + 1;
+
+        match from_vec_versioned::<u64>(&v) {
+            Err(SerializationError::UnsupportedVersion(version)) => assert_eq!(version, FORMAT_VERSION + 1),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(matches!(from_vec_versioned::<u64>(&[]), Err(SerializationError::MissingVersion)));
+    }
+
+    #[test]
+    fn versioned_old_format_fails() {
+        // a hash written as raw bytes by version 1 is not read as the hex string of version 2
+        let mut v = vec![1];
+        v.extend_from_slice(H256::repeat_byte(0xab).as_bytes());
+        assert!(matches!(from_vec_versioned::<H256>(&v), Err(SerializationError::UnsupportedVersion(1))));
+
+        let v = to_vec_versioned(&H256::repeat_byte(0xab)).unwrap();
+        assert_eq!(from_vec_versioned::<H256>(&v).unwrap(), H256::repeat_byte(0xab));
+    }
+}
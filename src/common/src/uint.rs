@@ -11,4 +11,20 @@ construct_uint! {
 construct_uint! {
 	/// 512-bits unsigned integer.
 	pub struct U512(8);
-}
\ No newline at end of file
+}
+impl From<U256> for U512 {
+	fn from(value: U256) -> Self {
+		let mut words = [0u64; 8];
+		words[..4].copy_from_slice(&value.0);
+		U512(words)
+	}
+}
+
+impl U512 {
+	/// The low 256 bits, the rest is truncated
+	pub fn low_u256(&self) -> U256 {
+		let mut words = [0u64; 4];
+		words.copy_from_slice(&self.0[..4]);
+		U256(words)
+	}
+}
@@ -1,14 +1,14 @@
 pub mod vm;
 pub mod state;
 mod bytes;
-mod hash;
+pub mod hash;
 mod maybe;
-mod uint;
+pub mod uint;
 mod test;
 mod transaction;
 mod blockchain;
 mod local_tests;
-mod spec;
+pub mod spec;
 mod trie;
 
 #[cfg(test)]
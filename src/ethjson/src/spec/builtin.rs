@@ -130,6 +130,16 @@ pub struct Builtin {
     pub pricing: BTreeMap<u64, PricingAt>,
 }
 
+impl Builtin {
+    /// The pricing active at `block`, i.e. the one with the greatest activation block not above it.
+    pub fn pricing_at(&self, block: u64) -> Option<&Pricing> {
+        self.pricing
+            .range(..=block)
+            .next_back()
+            .map(|(_, p)| &p.price)
+    }
+}
+
 impl From<BuiltinCompat> for Builtin {
     fn from(legacy: BuiltinCompat) -> Self {
         let pricing = match legacy.pricing {
@@ -180,7 +190,7 @@ pub struct PricingAt {
 
 #[cfg(test)]
 mod tests {
-    use super::{ Builtin, BuiltinCompat };
+    use super::{ Builtin, BuiltinCompat, Pricing };
     use serde_json;
 
     #[test]
@@ -211,6 +221,32 @@ mod tests {
         assert_eq!(builtin.name, "ecrecover");
     }
 
+    #[test]
+    fn pricing_at_works() {
+        let s = r#"{
+			"name": "ecrecover",
+			"pricing": {
+				"100": {
+					"price": {"linear": { "base": 3000, "word": 0 }}
+				},
+				"500": {
+					"price": {"linear": { "base": 10, "word": 0 }}
+				}
+			}
+		}"#;
+        let builtin: Builtin = serde_json::from_str::<BuiltinCompat>(s).unwrap().into();
+        let base_at = |block| match builtin.pricing_at(block) {
+            Some(Pricing::Linear(l)) => Some(l.base),
+            _ => None,
+        };
+        assert_eq!(base_at(0), None);
+        assert_eq!(base_at(99), None);
+        assert_eq!(base_at(100), Some(3000));
+        assert_eq!(base_at(499), Some(3000));
+        assert_eq!(base_at(500), Some(10));
+        assert_eq!(base_at(u64::MAX), Some(10));
+    }
+
     #[test]
     fn deserialization_blake2_f_builtin() {
         let s = r#"{
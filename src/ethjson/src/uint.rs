@@ -135,6 +135,7 @@ mod test {
     use crate::uint::Uint;
     use common::U256;
     use serde_json;
+    use std::collections::BTreeMap;
 
     #[test]
     fn uint_deserialization() {
@@ -156,4 +157,20 @@ mod test {
     fn uint_into() {
         assert_eq!(U256::from(10), Uint(U256::from(10)).into());
     }
+
+    #[test]
+    fn uint_ordering() {
+        // storage keys are iterated in the order of their big endian encoding
+        let keys = [U256::MAX, U256::from(0x0100), U256::one() << 200, U256::from(0xff), U256::zero()];
+        let map: BTreeMap<Uint, ()> = keys.iter().map(|k| (Uint(*k), ())).collect();
+        let big_endian = |k: &U256| {
+            let mut bytes = [0u8; 32];
+            k.to_big_endian(&mut bytes);
+            bytes
+        };
+        let ordered: Vec<[u8; 32]> = map.keys().map(|k| big_endian(&k.0)).collect();
+        let mut expected: Vec<[u8; 32]> = keys.iter().map(big_endian).collect();
+        expected.sort();
+        assert_eq!(ordered, expected);
+    }
 }
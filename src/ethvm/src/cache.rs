@@ -1,34 +1,38 @@
-use std::collections::HashSet;
 use crate::error::Error;
 use crate::instructions::Instruction;
 
 pub(crate) struct JumpCache {
-    jump_location: HashSet<usize>
+    /// `jump_dests[i]` is true if byte `i` is a `JUMPDEST` opcode, not push data
+    jump_dests: Vec<bool>,
 }
 
 impl JumpCache {
     pub fn new(code: &[u8]) -> Self {
         Self {
-            jump_location: Self::find_jump_destination(code)
+            jump_dests: Self::find_jump_destination(code)
         }
     }
 
     pub fn valid_jump_dest(&self, dest: usize) -> Result<(), Error> {
-        self.jump_location.contains(&dest).then(|| ()).ok_or(Error::InvalidJump)
+        match self.jump_dests.get(dest) {
+            Some(true) => Ok(()),
+            _ => Err(Error::InvalidJump),
+        }
     }
 
-    fn find_jump_destination(code: &[u8]) -> HashSet<usize> {
-        let mut set = HashSet::new();
-        for pos in 0..code.len() {
+    fn find_jump_destination(code: &[u8]) -> Vec<bool> {
+        let mut dests = vec![false; code.len()];
+        let mut pos = 0;
+        while pos < code.len() {
             if let Some(instruction) = Instruction::from_u8(code[pos]) {
                 match instruction {
-                    Instruction::JUMPDEST => {
-                        set.insert(pos);
-                    },
-                    _ => {},
+                    Instruction::JUMPDEST => dests[pos] = true,
+                    // the immediate bytes of a push are data, skip them
+                    _ => pos += instruction.data_bytes().unwrap_or(0),
                 };
             }
+            pos += 1;
         }
-        set
+        dests
     }
-}
\ No newline at end of file
+}
@@ -3,5 +3,25 @@
 pub enum Error {
     OutOfGas,
     InvalidCommand,
-    InvalidJump
+    InvalidJump,
+    /// The designated `INVALID` instruction was executed
+    InvalidInstruction,
+    /// The schedule does not cover all instruction tiers
+    InvalidSchedule,
+    /// The call value does not match the call type
+    InvalidActionParams,
+    /// `RETURNDATACOPY` reads past the end of the return data
+    ReturnDataOutOfBounds,
+    /// The instruction needs more items than there are on the stack
+    StackUnderflow {
+        instruction: &'static str,
+        wanted: usize,
+        on_stack: usize,
+    },
+    /// The instruction would grow the stack past its limit
+    OutOfStack {
+        instruction: &'static str,
+        wanted: usize,
+        limit: usize,
+    },
 }
@@ -4,21 +4,11 @@ use crate::instructions::{Instruction};
 use crate::stack::{Stack, VecStack};
 
 use crate::types::{Ext, Schedule};
-use common::{U256};
+use common::{Address, BigEndianHash, H256, U256};
 use std::cmp;
 
 const WORD_BYTES_SIZE: usize = 32;
 
-macro_rules! not_overflow {
-    ($tuple: expr) => {
-        if $tuple.1 {
-            panic!("overflow");
-        } else {
-            $tuple.0
-        }
-    };
-}
-
 macro_rules! overflowing {
     ($x: expr) => {{
         let (v, overflow) = $x;
@@ -45,6 +35,8 @@ pub struct InstructionRequirements<Cost> {
 
 pub enum InstructionGasRequirement<G: CostType> {
     Default(G),
+    /// `gas` is the cost of the instruction itself, the memory expansion is charged
+    /// separately as `mem_gas` so that it is counted exactly once
     Mem { gas: G, mem_gas: G, mem_size: usize },
 }
 
@@ -72,8 +64,13 @@ impl<Gas: CostType> GasMeter<Gas> {
         }
     }
 
-    pub fn verify_gas(&self, gas_cost: &Gas) -> Result<(), Error> {
-        match &self.current_gas < gas_cost {
+    /// Whether the gas limit covers the gas charged so far plus `r`
+    pub fn verify_gas(&self, r: &InstructionGasRequirement<Gas>) -> Result<(), Error> {
+        let mut total = overflowing!(self.total_gas().overflow_add(*r.gas()));
+        if let InstructionGasRequirement::Mem { mem_gas, .. } = r {
+            total = overflowing!(total.overflow_add(*mem_gas));
+        }
+        match total > self.gas_limit {
             true => Err(Error::OutOfGas),
             false => Ok(()),
         }
@@ -90,11 +87,12 @@ impl<Gas: CostType> GasMeter<Gas> {
         // Try converting requested gas to `Gas` (`U256/u64`)
         // but in EIP150 even if we request more we should never fail from OOG
         let requested = requested.map(Gas::from_u256);
+        let gas_left = self.gas_left();
 
         match schedule.sub_gas_cap_divisor {
-            Some(cap_divisor) if self.current_gas >= needed => {
-                let gas_remaining = self.current_gas - needed;
-                // TODO: what does this do? Reserve some gas for?
+            Some(cap_divisor) if gas_left >= needed => {
+                let gas_remaining = gas_left - needed;
+                // EIP-150 keeps back a part of the gas left for the caller to carry on with
                 let max_gas_provided = match cap_divisor {
                     64 => gas_remaining - (gas_remaining >> 6),
                     cap_divisor => gas_remaining - gas_remaining / Gas::from(cap_divisor),
@@ -109,8 +107,8 @@ impl<Gas: CostType> GasMeter<Gas> {
             _ => {
                 if let Some(r) = requested {
                     r
-                } else if self.current_gas >= needed {
-                    Ok(self.current_gas - needed)
+                } else if gas_left >= needed {
+                    Ok(gas_left - needed)
                 } else {
                     Ok(0.into())
                 }
@@ -118,21 +116,16 @@ impl<Gas: CostType> GasMeter<Gas> {
         }
     }
 
-    fn mem_gas_cost(
-        &self,
-        schedule: &Schedule,
-        current_mem_size: usize,
-        mem_size: &Gas,
-    ) -> Result<(Gas, Gas, usize), Error> {
+    /// The expansion gas for touching memory up to `mem_size` bytes, the gas of the
+    /// whole memory after it and the word aligned memory size
+    fn mem_gas_cost(&self, schedule: &Schedule, mem_size: &Gas) -> Result<(Gas, Gas, usize), Error> {
         // This calculates the memory usage for gas.
         // According to the yellow paper, it is:
         //     G = Gmemory * a + a ^ 2 / 512
         // where a is the number of 256-bit words allocated, Gmemory = schedule.memory_gas
         // and Gmemory should be 3.
         let gas_for_mem = |mem_size: Gas| {
-            // TODO: This is only 2^5 = 32, not 256. Unless, memory is
-            // TODO: implemented as Vec<u8>, this would be 256 bits.
-            // TODO: Confirm above?
+            // memory is byte addressed, so a 256-bit word is 32 bytes
             let s = mem_size >> 5;
             // s * memory_gas + s * s / quad_coeff_div
             let a = overflowing!(s.overflow_mul(Gas::from(schedule.memory_gas)));
@@ -144,16 +137,19 @@ impl<Gas: CostType> GasMeter<Gas> {
             Ok(overflowing!(a.overflow_add(b)))
         };
 
-        let current_mem_size = Gas::from(current_mem_size);
         let req_mem_size_rounded = overflowing!(to_word_size(*mem_size)) << 5;
 
-        let (mem_gas_cost, new_mem_gas) = if req_mem_size_rounded > current_mem_size {
-            let new_mem_gas = gas_for_mem(req_mem_size_rounded)?;
+        // the memory gas only grows with the size, so it tells whether memory expands
+        let new_mem_gas = gas_for_mem(req_mem_size_rounded)?;
+        let (mem_gas_cost, new_mem_gas) = if new_mem_gas > self.current_mem_gas {
             (new_mem_gas - self.current_mem_gas, new_mem_gas)
         } else {
             (Gas::from(0), self.current_mem_gas)
         };
 
+        if req_mem_size_rounded.as_u256() > U256::from(usize::MAX) {
+            return Err(Error::OutOfGas);
+        }
         Ok((mem_gas_cost, new_mem_gas, req_mem_size_rounded.as_usize()))
     }
 
@@ -161,72 +157,258 @@ impl<Gas: CostType> GasMeter<Gas> {
         self.current_mem_gas + self.current_gas
     }
 
+    /// The gas not yet charged against the gas limit
+    pub fn gas_left(&self) -> Gas {
+        let total = self.total_gas();
+        if total < self.gas_limit {
+            self.gas_limit - total
+        } else {
+            Gas::from(0)
+        }
+    }
+
+    /// Charge the gas forwarded to a CALL/CREATE
+    pub fn charge(&mut self, gas: Gas) -> Result<(), Error> {
+        if gas > self.gas_left() {
+            return Err(Error::OutOfGas);
+        }
+        self.current_gas = self.current_gas + gas;
+        Ok(())
+    }
+
+    /// Hand back the gas a CALL/CREATE did not use
+    pub fn refund(&mut self, gas: &U256) {
+        let gas = match Gas::from_u256(*gas) {
+            Ok(gas) if gas < self.current_gas => gas,
+            _ => self.current_gas,
+        };
+        self.current_gas = self.current_gas - gas;
+    }
+
+    /// Charge whatever is left of the gas limit
+    pub fn consume_all(&mut self) {
+        if self.total_gas() < self.gas_limit {
+            self.current_gas = self.gas_limit - self.current_mem_gas;
+        }
+    }
+
     pub fn update(&mut self, r: &InstructionGasRequirement<Gas>) -> Result<(), Error> {
         match r {
             InstructionGasRequirement::Default(g) => {
-                self.current_gas = not_overflow!(self.current_gas.overflow_add(*g));
+                self.current_gas = overflowing!(self.current_gas.overflow_add(*g));
             },
             InstructionGasRequirement::Mem { gas, mem_gas, .. } => {
-                self.current_gas = not_overflow!(self.current_gas.overflow_add(*gas));
-                self.current_mem_gas = not_overflow!(self.current_mem_gas.overflow_add(*mem_gas));
+                self.current_gas = overflowing!(self.current_gas.overflow_add(*gas));
+                self.current_mem_gas = overflowing!(self.current_mem_gas.overflow_add(*mem_gas));
             }
         }
         Ok(())
     }
 
+    /// Requirement of an instruction costing `gas` that touches memory up to `mem_size`,
+    /// only the words past the memory already paid for are charged
+    fn mem_requirement(
+        &self,
+        schedule: &Schedule,
+        gas: Gas,
+        mem_size: usize,
+    ) -> Result<InstructionGasRequirement<Gas>, Error> {
+        let (mem_gas, _, mem_size) = self.mem_gas_cost(schedule, &Gas::from(mem_size))?;
+        Ok(InstructionGasRequirement::Mem { gas, mem_gas, mem_size })
+    }
+
     pub fn instruction_requirement(
         &self,
         instruction: &Instruction,
         ext: &dyn Ext,
-        stack: &VecStack<U256>
-    ) -> InstructionGasRequirement<Gas> {
+        stack: &VecStack<U256>,
+        address: &Address,
+    ) -> Result<InstructionGasRequirement<Gas>, Error> {
         let schedule = ext.schedule();
 
         let tier = instruction.info().tier.idx();
-        let v = schedule.tier_step_gas[tier];
-        let default_gas = Gas::from(schedule.tier_step_gas[tier]);
-
-        match instruction {
-            Instruction::MSTORE => {
-                let mem_size = mem_add_size(stack.peek(0).as_usize(), WORD_BYTES_SIZE);
-                let mem_gas = mem_size
-                    .checked_mul(schedule.memory_gas)
-                    .expect("overflown");
-                InstructionGasRequirement::Mem {
-                    gas: not_overflow!(default_gas.overflow_add(Gas::from(mem_gas))),
-                    mem_gas: Gas::from(mem_gas),
-                    mem_size,
-                }
-            },
-            Instruction::MLOAD => {
-                let mem_gas = WORD_BYTES_SIZE
-                    .checked_mul(schedule.memory_gas)
-                    .expect("overflown");
-                InstructionGasRequirement::Mem {
-                    gas: not_overflow!(default_gas.overflow_add(Gas::from(mem_gas))),
-                    mem_gas: Gas::from(mem_gas),
-                    mem_size: 0,
-                }
+        let default_gas = Gas::from(*schedule.tier_step_gas.get(tier).ok_or(Error::InvalidSchedule)?);
+
+        let requirement = match instruction {
+            Instruction::MSTORE | Instruction::MLOAD => {
+                let mem_size = mem_add_size(to_usize(stack.peek(0))?, WORD_BYTES_SIZE)?;
+                self.mem_requirement(schedule, default_gas, mem_size)?
             },
             Instruction::CODECOPY => {
-                let mem_size = mem_add_size(stack.peek(0).as_usize(), stack.peek(2).as_usize());
-                let mem_gas = mem_size
-                    .checked_mul(schedule.memory_gas)
-                    .expect("overflown");
-                InstructionGasRequirement::Mem {
-                    gas: not_overflow!(default_gas.overflow_add(Gas::from(mem_gas))),
-                    mem_gas: Gas::from(mem_gas),
-                    mem_size,
+                let mem_size = mem_region(stack.peek(0), stack.peek(2))?;
+                self.mem_requirement(schedule, default_gas, mem_size)?
+            }
+            Instruction::MCOPY => {
+                let len = to_usize(stack.peek(2))?;
+                let mem_size = cmp::max(
+                    mem_region(stack.peek(0), stack.peek(2))?,
+                    mem_region(stack.peek(1), stack.peek(2))?,
+                );
+                let words = overflowing!(to_word_size(Gas::from(len)));
+                let copy_gas = overflowing!(words.overflow_mul(Gas::from(schedule.copy_gas)));
+                let gas = overflowing!(default_gas.overflow_add(copy_gas));
+                self.mem_requirement(schedule, gas, mem_size)?
+            }
+            Instruction::CALLDATACOPY | Instruction::RETURNDATACOPY => {
+                let len = to_usize(stack.peek(2))?;
+                let mem_size = mem_region(stack.peek(0), stack.peek(2))?;
+                let words = overflowing!(to_word_size(Gas::from(len)));
+                let copy_gas = overflowing!(words.overflow_mul(Gas::from(schedule.copy_gas)));
+                let gas = overflowing!(default_gas.overflow_add(copy_gas));
+                self.mem_requirement(schedule, gas, mem_size)?
+            }
+            Instruction::CALL => {
+                let mem_size = cmp::max(
+                    mem_region(stack.peek(3), stack.peek(4))?,
+                    mem_region(stack.peek(5), stack.peek(6))?,
+                );
+                let address = Address::from_slice(&H256::from_uint(stack.peek(1))[12..]);
+                let call_gas = if schedule.eip2929 {
+                    account_access_gas(schedule, ext, &address)
+                } else {
+                    schedule.call_gas
+                };
+                let mut gas = overflowing!(default_gas.overflow_add(Gas::from(call_gas)));
+                if !stack.peek(2).is_zero() {
+                    gas = overflowing!(gas.overflow_add(Gas::from(schedule.call_value_transfer_gas)));
+                    // transferring value to an empty account brings it into existence (EIP-161)
+                    if !ext.exists_and_not_null(&address).unwrap_or(false) {
+                        gas = overflowing!(gas.overflow_add(Gas::from(schedule.call_new_account_gas)));
+                    }
+                }
+                self.mem_requirement(schedule, gas, mem_size)?
+            }
+            Instruction::CREATE => {
+                let mem_size = mem_region(stack.peek(1), stack.peek(2))?;
+                let gas = overflowing!(default_gas.overflow_add(Gas::from(schedule.create_gas)));
+                self.mem_requirement(schedule, gas, mem_size)?
+            }
+            Instruction::LOG0
+            | Instruction::LOG1
+            | Instruction::LOG2
+            | Instruction::LOG3
+            | Instruction::LOG4 => {
+                let topics = instruction.log_topics().expect("LOG* always has topics");
+                let len = to_usize(stack.peek(1))?;
+                let mem_size = mem_region(stack.peek(0), stack.peek(1))?;
+                let data_gas = overflowing!(
+                    Gas::from(schedule.log_data_gas).overflow_mul(Gas::from(len))
+                );
+                let topic_gas = Gas::from(schedule.log_gas + schedule.log_topic_gas * topics);
+                let gas = overflowing!(topic_gas.overflow_add(data_gas));
+                self.mem_requirement(schedule, gas, mem_size)?
+            }
+            Instruction::RETURN | Instruction::REVERT => {
+                let mem_size = mem_region(stack.peek(0), stack.peek(1))?;
+                self.mem_requirement(schedule, default_gas, mem_size)?
+            }
+            Instruction::BALANCE | Instruction::EXTCODEHASH if schedule.eip2929 => {
+                let target = Address::from_slice(&H256::from_uint(stack.peek(0))[12..]);
+                InstructionGasRequirement::Default(Gas::from(account_access_gas(schedule, ext, &target)))
+            }
+            Instruction::BALANCE => InstructionGasRequirement::Default(Gas::from(schedule.balance_gas)),
+            Instruction::EXTCODEHASH => InstructionGasRequirement::Default(Gas::from(schedule.extcodehash_gas)),
+            Instruction::SLOAD if schedule.eip2929 => {
+                let key = H256::from_uint(stack.peek(0));
+                let gas = if ext.al_contains_storage_key(address, &key) {
+                    schedule.warm_storage_read_cost
+                } else {
+                    schedule.cold_sload_cost
+                };
+                InstructionGasRequirement::Default(Gas::from(gas))
+            }
+            Instruction::SLOAD => InstructionGasRequirement::Default(Gas::from(schedule.sload_gas)),
+            Instruction::SSTORE => {
+                // EIP-2200 keeps a call stipend from being enough to write storage
+                if schedule.eip1283 && self.gas_left() <= Gas::from(schedule.sstore_sentry_gas) {
+                    return Err(Error::OutOfGas);
+                }
+                let key = H256::from_uint(stack.peek(0));
+                let new = stack.peek(1);
+                let current = ext.storage_at(&key)?.into_uint();
+                let mut gas = if schedule.eip1283 {
+                    let original = ext.initial_storage_at(&key)?.into_uint();
+                    eip1283_sstore_gas(schedule, &original, &current, new)
+                } else if current.is_zero() && !new.is_zero() {
+                    schedule.sstore_set_gas
+                } else {
+                    schedule.sstore_reset_gas
+                };
+                if schedule.eip2929 && !ext.al_contains_storage_key(address, &key) {
+                    gas += schedule.cold_sload_cost;
                 }
+                InstructionGasRequirement::Default(Gas::from(gas))
+            }
+            Instruction::JUMPDEST => InstructionGasRequirement::Default(Gas::from(schedule.jumpdest_gas)),
+            Instruction::EXP => {
+                let bytes = stack.peek(1).bits().div_ceil(8);
+                let byte_gas = overflowing!(
+                    Gas::from(schedule.exp_byte_gas).overflow_mul(Gas::from(bytes))
+                );
+                InstructionGasRequirement::Default(overflowing!(
+                    Gas::from(schedule.exp_gas).overflow_add(byte_gas)
+                ))
             }
             _ => InstructionGasRequirement::Default(default_gas),
-        }
+        };
+        Ok(requirement)
     }
 }
 
+/// Gas of accessing an account, cheaper once it is in the access list (EIP-2929)
+fn account_access_gas(schedule: &Schedule, ext: &dyn Ext, address: &Address) -> usize {
+    if ext.al_contains_address(address) {
+        schedule.warm_storage_read_cost
+    } else {
+        schedule.cold_account_access_cost
+    }
+}
+
+/// Net gas metering (EIP-1283, EIP-2200), `original` is the value before the transaction.
+/// Only the first write to a slot in a transaction pays for the write, later ones cost
+/// as much as an `SLOAD`.
+fn eip1283_sstore_gas(schedule: &Schedule, original: &U256, current: &U256, new: &U256) -> usize {
+    if current == new || original != current {
+        schedule.sload_gas
+    } else if original.is_zero() {
+        schedule.sstore_set_gas
+    } else {
+        schedule.sstore_reset_gas
+    }
+}
+
+/// A memory offset or size from the stack, memory past `usize` could never be paid for
+#[inline]
+fn to_usize(value: &U256) -> Result<usize, Error> {
+    if *value > U256::from(usize::MAX) {
+        return Err(Error::OutOfGas);
+    }
+    Ok(value.as_usize())
+}
+
 #[inline]
-fn mem_add_size(current: usize, to_add: usize) -> usize {
-    current.checked_add(to_add).expect("oom")
+fn mem_add_size(current: usize, to_add: usize) -> Result<usize, Error> {
+    current.checked_add(to_add).ok_or(Error::OutOfGas)
+}
+
+/// End of the memory region `offset..offset + size`, an empty region needs no memory
+#[inline]
+fn mem_region_end(offset: usize, size: usize) -> Result<usize, Error> {
+    if size == 0 {
+        Ok(0)
+    } else {
+        mem_add_size(offset, size)
+    }
+}
+
+/// Like `mem_region_end`, for an offset and a size read from the stack
+#[inline]
+fn mem_region(offset: &U256, size: &U256) -> Result<usize, Error> {
+    if size.is_zero() {
+        Ok(0)
+    } else {
+        mem_region_end(to_usize(offset)?, to_usize(size)?)
+    }
 }
 
 #[inline]
@@ -311,8 +311,8 @@ enum_with_from_u8! {
         BEGINSUB = 0x5c,
         #[doc = "Returns from a subroutine."]
         RETURNSUB = 0x5d,
-        #[doc = "Jumps to a defined BEGINSUB subroutine."]
-        JUMPSUB = 0x5e,
+        #[doc = "Copies a memory region, source and destination may overlap (EIP-5656)."]
+        MCOPY = 0x5e,
 
         #[doc = "create a new account with associated code"]
         CREATE = 0xf0,
@@ -328,6 +328,8 @@ enum_with_from_u8! {
         CREATE2 = 0xf5,
         #[doc = "stop execution and revert state changes. Return output data."]
         REVERT = 0xfd,
+        #[doc = "designated invalid instruction, aborts consuming all gas"]
+        INVALID = 0xfe,
         #[doc = "like CALL but it does not take value, nor modify the state"]
         STATICCALL = 0xfa,
         #[doc = "halt execution and register account for later deletion"]
@@ -588,7 +590,7 @@ lazy_static::lazy_static! {
         arr[LOG3 as usize] = Some(InstructionInfo::new("LOG3", 5, 0, GasPriceTier::Special));
         arr[LOG4 as usize] = Some(InstructionInfo::new("LOG4", 6, 0, GasPriceTier::Special));
         arr[BEGINSUB as usize] = Some(InstructionInfo::new("BEGINSUB", 0, 0, GasPriceTier::Base));
-        arr[JUMPSUB as usize] = Some(InstructionInfo::new("JUMPSUB", 1, 0, GasPriceTier::High));
+        arr[MCOPY as usize] = Some(InstructionInfo::new("MCOPY", 3, 0, GasPriceTier::VeryLow));
         arr[RETURNSUB as usize] = Some(InstructionInfo::new("RETURNSUB", 0, 0, GasPriceTier::Low));
         arr[CREATE as usize] = Some(InstructionInfo::new("CREATE", 3, 1, GasPriceTier::Special));
         arr[CALL as usize] = Some(InstructionInfo::new("CALL", 7, 1, GasPriceTier::Special));
@@ -599,6 +601,7 @@ lazy_static::lazy_static! {
         arr[SUICIDE as usize] = Some(InstructionInfo::new("SUICIDE", 1, 0, GasPriceTier::Special));
         arr[CREATE2 as usize] = Some(InstructionInfo::new("CREATE2", 4, 1, GasPriceTier::Special));
         arr[REVERT as usize] = Some(InstructionInfo::new("REVERT", 2, 0, GasPriceTier::Zero));
+        arr[INVALID as usize] = Some(InstructionInfo::new("INVALID", 0, 0, GasPriceTier::Zero));
         arr
     };
 }
@@ -4,14 +4,22 @@ use crate::error::Error;
 use crate::gas::{GasMeter, InstructionGasRequirement};
 use crate::instructions::Instruction;
 use crate::memory::Memory;
+use crate::precompile::{precompiles, Linear, PrecompileFn};
 use crate::stack::{Stack, VecStack};
-use crate::types::{ActionParams, ActionValue, Bytes, CallType, Exec, Ext, GasLeft, ParamsType};
+use crate::types::{
+    ActionParams, ActionValue, Bytes, CallType, ContractCreateResult, CreateContractAddress, Exec, Ext, GasLeft,
+    MessageCallResult, ParamsType, ReturnData,
+};
 
-use common::{Address, BigEndianHash, H256, keccak, U256};
+use common::{Address, BigEndianHash, H256, keccak, U256, U512};
+use std::cmp;
 use crate::cache::JumpCache;
 
 type ProgramCounter = usize;
 
+/// Max number of items on the stack
+const STACK_LIMIT: usize = 1024;
+
 struct CodeReader {
     /// The code to be executed
     code: Bytes,
@@ -73,6 +81,8 @@ struct InterpreterParams {
     pub call_type: CallType,
     /// Param types encoding
     pub params_type: ParamsType,
+    /// Number of frames above this one
+    pub depth: usize,
 }
 
 impl From<ActionParams> for InterpreterParams {
@@ -89,16 +99,21 @@ impl From<ActionParams> for InterpreterParams {
             data: params.data,
             call_type: params.call_type,
             params_type: params.params_type,
+            depth: params.depth,
         }
     }
 }
 
+/// The handler of a single opcode, see `Interpreter::JUMP_TABLE`
+type InstructionFn<M, G> =
+    fn(&mut Interpreter<M, G>, &Instruction, &mut dyn Ext) -> Result<StepResult<M>, Error>;
+
 enum StepResult<M: Memory> {
     Continue,
     Error(Error),
     Success,
-    Returned { memory: M, offset: usize, length: usize },
-    Reverted,
+    Returned { memory: M, offset: U256, length: U256 },
+    Reverted { memory: M, offset: U256, length: U256 },
 }
 
 pub struct Interpreter<M: Memory, G: CostType> {
@@ -108,17 +123,37 @@ pub struct Interpreter<M: Memory, G: CostType> {
     gas_meter: GasMeter<G>,
     params: InterpreterParams,
     jump_cache: Option<JumpCache>,
+    /// Position of the instruction being executed
+    pc: ProgramCounter,
+    /// Number of times each opcode was executed, only kept when profiling
+    opcode_counts: Option<[u64; 256]>,
+    /// Output of the last call or create, read by `RETURNDATASIZE` and `RETURNDATACOPY`
+    return_data: ReturnData,
 }
 
 impl<M: Memory, G: CostType> Exec for Interpreter<M, G> {
     fn exec(&mut self, ext: &mut dyn Ext) -> Result<GasLeft, Error> {
+        ext.schedule().validate()?;
         loop {
             match self.step(ext)? {
                 StepResult::Continue => {}
                 StepResult::Error(e) => return Err(e),
-                StepResult::Success => return Ok(GasLeft::Known(U256::zero())),
-                StepResult::Returned { .. } => return Ok(GasLeft::Known(U256::zero())),
-                _ => todo!("impl other patterns")
+                StepResult::Success => return Ok(GasLeft::Known(self.gas_meter.gas_left().as_u256())),
+                StepResult::Returned { memory, offset, length } => {
+                    return Ok(GasLeft::NeedsReturn {
+                        gas_left: self.gas_meter.gas_left().as_u256(),
+                        data: memory.into_return_data(offset, length),
+                        apply_state: true,
+                    })
+                },
+                // unlike an error, a revert hands the unused gas back
+                StepResult::Reverted { memory, offset, length } => {
+                    return Ok(GasLeft::NeedsReturn {
+                        gas_left: self.gas_meter.gas_left().as_u256(),
+                        data: memory.into_return_data(offset, length),
+                        apply_state: false,
+                    })
+                },
             };
         }
     }
@@ -130,18 +165,53 @@ impl<M: Memory, G: CostType> Interpreter<M, G> {
         let gas = G::from_u256(action_param.gas).expect("cannot parse gas");
         Self {
             reader,
-            stack: VecStack::with_capacity(1024, U256::zero()),
+            stack: VecStack::with_capacity(STACK_LIMIT, U256::zero()),
             memory: M::empty(),
             gas_meter: GasMeter::new(gas),
             params: InterpreterParams::from(action_param),
-            jump_cache: None
+            jump_cache: None,
+            pc: 0,
+            opcode_counts: None,
+            return_data: ReturnData::empty(),
         }
     }
 
+    /// Run `code` of another account, fetched with `Ext::extcode`, in the context of
+    /// `action_param.address` as DELEGATECALL and CALLCODE do. CODESIZE and CODECOPY
+    /// see the executing code.
+    pub fn new_with_code(code: Vec<u8>, action_param: ActionParams, code_address: Address) -> Self {
+        let code_hash = keccak(&code);
+        let mut interpreter = Self::new(code, action_param);
+        interpreter.params.code_address = code_address;
+        interpreter.params.code_hash = Some(code_hash);
+        interpreter
+    }
+
+    /// Like `new`, but also counts how many times each opcode is executed
+    pub fn new_with_profiling(code: Vec<u8>, action_param: ActionParams) -> Self {
+        let mut interpreter = Self::new(code, action_param);
+        interpreter.opcode_counts = Some([0; 256]);
+        interpreter
+    }
+
+    /// The executed opcode counts indexed by opcode, if created with `new_with_profiling`
+    pub fn opcode_counts(&self) -> Option<&[u64; 256]> {
+        self.opcode_counts.as_ref()
+    }
+
+    /// The gas charged so far, including memory expansion
+    pub(crate) fn gas_used(&self) -> G {
+        self.gas_meter.total_gas()
+    }
+
     fn step(&mut self, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        // reading the instruction moves the reader past it
+        self.pc = self.reader.position;
         let instruction = self.reader.instruction();
 
-        self.validate_instruction(&instruction)?;
+        if let Err(e) = self.validate_instruction(&instruction) {
+            return Ok(StepResult::Error(e));
+        }
 
         // NOTE: I think here is where Rust can handle relatively easier compared
         // NOTE: to other language. When handling some function that might involve
@@ -150,9 +220,9 @@ impl<M: Memory, G: CostType> Interpreter<M, G> {
         // NOTE: the memory, it involves similar step to parse the instruction.
         // NOTE: In this case, we can use enum to handle and return all the
         // NOTE: parameters to avoid duplicated calculations.
-        let requirement = self.gas_meter.instruction_requirement(&instruction, ext, &self.stack);
+        let requirement = self.gas_meter.instruction_requirement(&instruction, ext, &self.stack, &self.params.address)?;
+        self.gas_meter.verify_gas(&requirement)?;
         self.gas_meter.update(&requirement)?;
-        self.validate_gas()?;
 
         // expand memory to the required size
         if let InstructionGasRequirement::Mem {
@@ -160,262 +230,2002 @@ impl<M: Memory, G: CostType> Interpreter<M, G> {
             ..
         } = requirement
         {
-            self.memory.resize(mem_size + self.memory.size());
+            self.memory.expand(mem_size);
         }
 
+        if let Some(counts) = self.opcode_counts.as_mut() {
+            counts[instruction as usize] += 1;
+        }
         self.exec_instruction(&instruction, ext)
     }
 
-    fn validate_gas(&self) -> Result<(), Error> {
-        Ok(())
-    }
-
     fn validate_instruction(&self, instruction: &Instruction) -> Result<(), Error> {
+        let info = instruction.info();
+        let on_stack = self.stack.size();
+        if !self.stack.has(info.args) {
+            return Err(Error::StackUnderflow {
+                instruction: info.name,
+                wanted: info.args,
+                on_stack,
+            });
+        }
+        if on_stack - info.args + info.ret > STACK_LIMIT {
+            return Err(Error::OutOfStack {
+                instruction: info.name,
+                wanted: info.ret - info.args,
+                limit: STACK_LIMIT,
+            });
+        }
         Ok(())
     }
 
     fn exec_instruction(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
-       match instruction {
-            Instruction::PUSH1 |
-            Instruction::PUSH2 => {
-                let bytes = instruction
-                    .data_bytes()
-                    .expect("invalid push read bytes. qed");
-                let word = self.reader.read_word(bytes);
-                log::debug!("{:?}: {:?}", instruction, word);
-
-                self.stack.push(word);
-            }
-            Instruction::MSTORE => {
-                let offset = self.stack.pop();
-                let value = self.stack.pop();
-                log::debug!("{:?}: offset {:?}, value: {:?}", instruction, offset, value);
-                self.memory.write(offset, value);
-            }
-            Instruction::CALLVALUE => {
-                self.stack.push(self.params.value.value());
-                log::debug!("{:?}: value: {:?}", instruction, self.params.value.value());
-            },
-            Instruction::DUP1
-            | Instruction::DUP2
-            | Instruction::DUP3
-            | Instruction::DUP4
-            | Instruction::DUP5
-            | Instruction::DUP6
-            | Instruction::DUP7
-            | Instruction::DUP8
-            | Instruction::DUP9
-            | Instruction::DUP10
-            | Instruction::DUP11
-            | Instruction::DUP12
-            | Instruction::DUP13
-            | Instruction::DUP14
-            | Instruction::DUP15
-            | Instruction::DUP16 => {
-                let index_from_top = instruction.dup_position().expect("invalid operation.qed");
-                let v = self.stack.peek(index_from_top).clone();
-                log::debug!("{:?}: value: {:?}", instruction, v);
-
-                self.stack.push(v);
-            },
-            Instruction::ISZERO => {
-                let v = Self::bool_to_u256(self.stack.pop().is_zero());
-                log::debug!("{:?}: is_zero: {:?}", instruction, v);
-                self.stack.push(v);
-            },
-            Instruction::JUMPI => {
-                let dest = self.stack.pop().as_usize();
-                let cond = Self::u256_to_bool(self.stack.pop());
-                log::debug!("{:?}: cond: {:?}, dest: {:?}", instruction, cond, dest);
-                self.process_jump(cond, ProgramCounter::from(dest))?;
-            },
-            Instruction::JUMPDEST => {
-                log::debug!("{:?}", instruction);
-            },
-            Instruction::POP => {
-                self.stack.pop();
-                log::debug!("{:?}", instruction);
-            },
-            Instruction::MLOAD => {
-                let offset = self.stack.pop();
-                let v = self.memory.read(offset);
-                log::debug!("{:?}, offset: {:?}, v: {:?}", instruction, offset, v);
-                self.stack.push(v);
-            },
-            Instruction::CODESIZE => {
-                log::debug!("{:?}, codelen: {:?}", instruction, self.reader.len());
-                self.stack.push(U256::from(self.reader.len()));
-            },
-           Instruction::SUB => {
-               let a = self.stack.pop();
-               let b = self.stack.pop();
-               log::debug!("{:?}, a: {:?}, b: {:?}, output: {:?}", instruction, a, b, a.overflowing_sub(b));
-               self.stack.push(a.overflowing_sub(b).0);
-           },
-           Instruction::CODECOPY => {
-               let dest_offset = self.stack.pop();
-               let offset = self.stack.pop().as_usize();
-               let end = offset + self.stack.pop().as_usize();
-               log::debug!(
-                   "{:?}, dest_offset: {:?}, offset: {:?}, end: {:?}",
-                   instruction, dest_offset, offset, end
-               );
-               println!("{:x?}", &self.reader.code[offset..end]);
-               self.memory.write_slice(dest_offset, &self.reader.code[offset..end])
-           },
-           Instruction::SWAP1
-           | Instruction::SWAP2
-           | Instruction::SWAP3
-           | Instruction::SWAP4
-           | Instruction::SWAP5
-           | Instruction::SWAP6
-           | Instruction::SWAP7
-           | Instruction::SWAP8
-           | Instruction::SWAP9
-           | Instruction::SWAP10
-           | Instruction::SWAP11
-           | Instruction::SWAP12
-           | Instruction::SWAP13
-           | Instruction::SWAP14
-           | Instruction::SWAP15
-           | Instruction::SWAP16 => {
-               let position = instruction
-                   .swap_position()
-                   .expect("swap_position always return some for SWAP* instructions");
-               log::debug!("{:?}, position: {:?}", instruction, position);
-               self.stack.swap_with_top(position);
-           },
-           Instruction::ADD => {
-               let a = self.stack.pop();
-               let b = self.stack.pop();
-               let v = a.overflowing_add(b).0;
-               log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
-               self.stack.push(v);
-           },
-           Instruction::SSTORE => {
-               let key = H256::from_uint(&self.stack.pop());
-               let val = self.stack.pop();
-
-               let current_val = ext.storage_at(&key)?.into_uint();
-               // Increase refund for clear
-               if ext.schedule().eip1283 {
-                   todo!("impl this");
-               } else {
-                   if !current_val.is_zero() && val.is_zero() {
-                       let sstore_clears_schedule = ext.schedule().sstore_refund_gas;
-                       // TODO: find out what this does
-                       ext.add_sstore_refund(sstore_clears_schedule);
-                   }
-               }
-               ext.set_storage(key, BigEndianHash::from_uint(&val))?;
-               ext.al_insert_storage_key(self.params.address, key);
-               log::debug!("{:?}", instruction);
-           },
-           Instruction::CALLER => {
-               let a = Self::address_to_u256(&self.params.sender);
-               log::debug!("{:?}, address as u256: {:?}", instruction, a);
-               self.stack.push(a);
-           },
-           Instruction::SHA3 => {
-               let offset = self.stack.pop();
-               let size = self.stack.pop();
-               let k = keccak(self.memory.read_slice(offset, size));
-               log::debug!("{:?}, offset: {:?}, size: {:?}, hash: {:?}", instruction, offset, size, k);
-               self.stack.push(k.into_uint());
-           },
-           Instruction::RETURN => {
-               let offset = self.stack.pop();
-               let length = self.stack.pop();
-               log::debug!("{:?}, offset: {:?}, length: {:?}", instruction, offset, length);
-               let mut mem = core::mem::replace(&mut self.memory, Memory::empty());
-               mem.write_slice(U256::zero(), self.memory.read_slice(offset, length));
-               return Ok(StepResult::Returned {memory: mem, offset: offset.as_usize(), length: length.as_usize() })
-           },
-            _ => {
-                log::debug!("{:?}", instruction);
-                return Ok(StepResult::Error(Error::InvalidCommand));
-            }
-        };
+        let handler = Self::JUMP_TABLE[*instruction as usize];
+        match handler(self, instruction, ext)? {
+            StepResult::Continue if self.reader.done() => Ok(StepResult::Success),
+            r => Ok(r),
+        }
+    }
+
+    /// The handlers of each opcode, indexed by the opcode byte. Unsupported
+    /// opcodes are mapped to `Self::unsupported`.
+    const JUMP_TABLE: [InstructionFn<M, G>; 256] = Self::jump_table();
 
-        if self.reader.done() { return Ok(StepResult::Success); }
+    const fn jump_table() -> [InstructionFn<M, G>; 256] {
+        let mut table: [InstructionFn<M, G>; 256] = [Self::unsupported; 256];
 
+        table[Instruction::STOP as usize] = Self::stop;
+        table[Instruction::INVALID as usize] = Self::invalid;
+        table[Instruction::PUSH1 as usize] = Self::push;
+        table[Instruction::PUSH2 as usize] = Self::push;
+        table[Instruction::MSTORE as usize] = Self::mstore;
+        table[Instruction::CALLVALUE as usize] = Self::callvalue;
+        let mut op = Instruction::DUP1 as usize;
+        while op <= Instruction::DUP16 as usize {
+            table[op] = Self::dup;
+            op += 1;
+        }
+        table[Instruction::ISZERO as usize] = Self::iszero;
+        table[Instruction::JUMP as usize] = Self::jump;
+        table[Instruction::JUMPI as usize] = Self::jumpi;
+        table[Instruction::JUMPDEST as usize] = Self::jumpdest;
+        table[Instruction::POP as usize] = Self::pop;
+        table[Instruction::MLOAD as usize] = Self::mload;
+        table[Instruction::CODESIZE as usize] = Self::codesize;
+        table[Instruction::GAS as usize] = Self::gas;
+        table[Instruction::PC as usize] = Self::pc;
+        table[Instruction::MSIZE as usize] = Self::msize;
+        table[Instruction::SUB as usize] = Self::sub;
+        table[Instruction::CODECOPY as usize] = Self::codecopy;
+        table[Instruction::CALLDATALOAD as usize] = Self::calldataload;
+        table[Instruction::CALLDATASIZE as usize] = Self::calldatasize;
+        table[Instruction::CALLDATACOPY as usize] = Self::calldatacopy;
+        table[Instruction::RETURNDATASIZE as usize] = Self::returndatasize;
+        table[Instruction::RETURNDATACOPY as usize] = Self::returndatacopy;
+        table[Instruction::MCOPY as usize] = Self::mcopy;
+        let mut op = Instruction::SWAP1 as usize;
+        while op <= Instruction::SWAP16 as usize {
+            table[op] = Self::swap;
+            op += 1;
+        }
+        table[Instruction::ADD as usize] = Self::add;
+        table[Instruction::MUL as usize] = Self::mul;
+        table[Instruction::DIV as usize] = Self::div;
+        table[Instruction::MOD as usize] = Self::modulo;
+        table[Instruction::SDIV as usize] = Self::sdiv;
+        table[Instruction::SMOD as usize] = Self::smod;
+        table[Instruction::ADDMOD as usize] = Self::addmod;
+        table[Instruction::MULMOD as usize] = Self::mulmod;
+        table[Instruction::EXP as usize] = Self::exp;
+        table[Instruction::SIGNEXTEND as usize] = Self::signextend;
+        table[Instruction::LT as usize] = Self::lt;
+        table[Instruction::GT as usize] = Self::gt;
+        table[Instruction::SLT as usize] = Self::slt;
+        table[Instruction::SGT as usize] = Self::sgt;
+        table[Instruction::EQ as usize] = Self::eq;
+        table[Instruction::AND as usize] = Self::and;
+        table[Instruction::OR as usize] = Self::or;
+        table[Instruction::XOR as usize] = Self::xor;
+        table[Instruction::NOT as usize] = Self::not;
+        table[Instruction::BYTE as usize] = Self::byte;
+        table[Instruction::SLOAD as usize] = Self::sload;
+        table[Instruction::SSTORE as usize] = Self::sstore;
+        table[Instruction::CALLER as usize] = Self::caller;
+        table[Instruction::BLOCKHASH as usize] = Self::blockhash;
+        table[Instruction::COINBASE as usize] = Self::coinbase;
+        table[Instruction::TIMESTAMP as usize] = Self::timestamp;
+        table[Instruction::NUMBER as usize] = Self::number;
+        table[Instruction::DIFFICULTY as usize] = Self::difficulty;
+        table[Instruction::GASLIMIT as usize] = Self::gaslimit;
+        table[Instruction::CHAINID as usize] = Self::chainid;
+        table[Instruction::BALANCE as usize] = Self::balance;
+        table[Instruction::SELFBALANCE as usize] = Self::selfbalance;
+        table[Instruction::EXTCODEHASH as usize] = Self::extcodehash;
+        table[Instruction::BASEFEE as usize] = Self::basefee;
+        table[Instruction::SHA3 as usize] = Self::sha3;
+        let mut op = Instruction::LOG0 as usize;
+        while op <= Instruction::LOG4 as usize {
+            table[op] = Self::log;
+            op += 1;
+        }
+        table[Instruction::RETURN as usize] = Self::ret;
+        table[Instruction::REVERT as usize] = Self::revert;
+        table[Instruction::CALL as usize] = Self::call;
+        table[Instruction::CREATE as usize] = Self::create;
+
+        table
+    }
+
+    fn unsupported(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}", instruction);
+        Ok(StepResult::Error(Error::InvalidCommand))
+    }
+
+    fn stop(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}", instruction);
+        Ok(StepResult::Success)
+    }
+
+    fn invalid(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}", instruction);
+        self.gas_meter.consume_all();
+        Ok(StepResult::Error(Error::InvalidInstruction))
+    }
+
+    fn push(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let bytes = instruction
+            .data_bytes()
+            .expect("invalid push read bytes. qed");
+        let word = self.reader.read_word(bytes);
+        log::debug!("{:?}: {:?}", instruction, word);
+
+        self.stack.push(word);
         Ok(StepResult::Continue)
     }
 
-    fn process_jump(&mut self, cond: bool, dest: ProgramCounter) -> Result<(), Error> {
-        if self.jump_cache.is_none() {
-            self.jump_cache = Some(JumpCache::new(&self.reader.code));
+    fn mstore(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let value = self.stack.pop();
+        log::debug!("{:?}: offset {:?}, value: {:?}", instruction, offset, value);
+        self.memory.write(offset, value);
+        Ok(StepResult::Continue)
+    }
+
+    fn callvalue(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        self.stack.push(self.params.value.value());
+        log::debug!("{:?}: value: {:?}", instruction, self.params.value.value());
+        Ok(StepResult::Continue)
+    }
+
+    fn dup(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let index_from_top = instruction.dup_position().expect("invalid operation.qed");
+        let v = self.stack.peek(index_from_top).clone();
+        log::debug!("{:?}: value: {:?}", instruction, v);
+
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn iszero(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let v = Self::bool_to_u256(self.stack.pop().is_zero());
+        log::debug!("{:?}: is_zero: {:?}", instruction, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn jump(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let dest = self.stack.pop();
+        log::debug!("{:?}: dest: {:?}", instruction, dest);
+        // the pc is set to the destination, the next step reads the JUMPDEST there
+        self.process_jump(true, dest)?;
+        Ok(StepResult::Continue)
+    }
+
+    fn jumpi(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let dest = self.stack.pop();
+        let cond = Self::u256_to_bool(self.stack.pop());
+        log::debug!("{:?}: cond: {:?}, dest: {:?}", instruction, cond, dest);
+        self.process_jump(cond, dest)?;
+        Ok(StepResult::Continue)
+    }
+
+    fn jumpdest(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}", instruction);
+        Ok(StepResult::Continue)
+    }
+
+    fn pop(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        self.stack.pop();
+        log::debug!("{:?}", instruction);
+        Ok(StepResult::Continue)
+    }
+
+    fn mload(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let v = self.memory.read(offset);
+        log::debug!("{:?}, offset: {:?}, v: {:?}", instruction, offset, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn codesize(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}, codelen: {:?}", instruction, self.reader.len());
+        self.stack.push(U256::from(self.reader.len()));
+        Ok(StepResult::Continue)
+    }
+
+    fn gas(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let gas_left = self.gas_meter.gas_left().as_u256();
+        log::debug!("{:?}, gas left: {:?}", instruction, gas_left);
+        self.stack.push(gas_left);
+        Ok(StepResult::Continue)
+    }
+
+    fn pc(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}, pc: {:?}", instruction, self.pc);
+        self.stack.push(U256::from(self.pc));
+        Ok(StepResult::Continue)
+    }
+
+    fn msize(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        log::debug!("{:?}, size: {:?}", instruction, self.memory.size());
+        self.stack.push(U256::from(self.memory.size()));
+        Ok(StepResult::Continue)
+    }
+
+    fn sub(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}, output: {:?}", instruction, a, b, a.overflowing_sub(b));
+        self.stack.push(a.overflowing_sub(b).0);
+        Ok(StepResult::Continue)
+    }
+
+    fn codecopy(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let dest_offset = self.stack.pop();
+        let offset = self.stack.pop();
+        let size = self.stack.pop();
+        log::debug!(
+            "{:?}, dest_offset: {:?}, offset: {:?}, size: {:?}",
+            instruction, dest_offset, offset, size
+        );
+        Self::copy_padded(&mut self.memory, dest_offset, &self.reader.code, offset, size);
+        Ok(StepResult::Continue)
+    }
+
+    fn calldataload(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let data = self.params.data.as_deref().unwrap_or(&[]);
+        // bytes past the end of the call data read as zero
+        let mut word = [0u8; 32];
+        if offset < U256::from(data.len()) {
+            let start = offset.as_usize();
+            let end = cmp::min(start + 32, data.len());
+            word[..end - start].copy_from_slice(&data[start..end]);
         }
+        log::debug!("{:?}, offset: {:?}, word: {:?}", instruction, offset, word);
+        self.stack.push(U256::from(&word[..]));
+        Ok(StepResult::Continue)
+    }
 
-        if !cond {
-            self.reader.set_pc(ProgramCounter::from(self.reader.position + 1));
-            return Ok(());
+    fn calldatasize(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let len = self.params.data.as_ref().map_or(0, |d| d.len());
+        log::debug!("{:?}, len: {:?}", instruction, len);
+        self.stack.push(U256::from(len));
+        Ok(StepResult::Continue)
+    }
+
+    fn calldatacopy(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let dest_offset = self.stack.pop();
+        let offset = self.stack.pop();
+        let size = self.stack.pop();
+        log::debug!(
+            "{:?}, dest_offset: {:?}, offset: {:?}, size: {:?}",
+            instruction, dest_offset, offset, size
+        );
+        let data = self.params.data.as_deref().unwrap_or(&[]);
+        Self::copy_padded(&mut self.memory, dest_offset, data, offset, size);
+        Ok(StepResult::Continue)
+    }
+
+    fn returndatasize(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().have_return_data {
+            return Ok(StepResult::Error(Error::InvalidCommand));
         }
+        log::debug!("{:?}, size: {:?}", instruction, self.return_data.len());
+        self.stack.push(U256::from(self.return_data.len()));
+        Ok(StepResult::Continue)
+    }
 
-        match &self.jump_cache {
-            Some(cache) => {
-                cache.valid_jump_dest(dest.as_usize())?;
-                self.reader.set_pc(dest);
-            },
-            None => panic!("should not happen"),
+    fn returndatacopy(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().have_return_data {
+            return Ok(StepResult::Error(Error::InvalidCommand));
         }
-        Ok(())
+        let dest_offset = self.stack.pop();
+        let offset = self.stack.pop();
+        let size = self.stack.pop();
+        log::debug!(
+            "{:?}, dest_offset: {:?}, offset: {:?}, size: {:?}",
+            instruction, dest_offset, offset, size
+        );
+
+        // unlike call data, reading past the end is an error rather than zero padded
+        let end = offset.overflowing_add(size);
+        if end.1 || end.0 > U256::from(self.return_data.len()) {
+            return Err(Error::ReturnDataOutOfBounds);
+        }
+        if !size.is_zero() {
+            let (start, end) = (offset.as_usize(), end.0.as_usize());
+            self.memory.write_slice(dest_offset, &self.return_data[start..end]);
+        }
+        Ok(StepResult::Continue)
     }
 
-    fn bool_to_u256(val: bool) -> U256 {
-        if val {
-            U256::one()
+    fn mcopy(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().eip5656 {
+            return Ok(StepResult::Error(Error::InvalidCommand));
+        }
+        let dst = self.stack.pop();
+        let src = self.stack.pop();
+        let len = self.stack.pop();
+        log::debug!("{:?}, dst: {:?}, src: {:?}, len: {:?}", instruction, dst, src, len);
+        self.memory.copy_within(dst, src, len);
+        Ok(StepResult::Continue)
+    }
+
+    fn swap(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let position = instruction
+            .swap_position()
+            .expect("swap_position always return some for SWAP* instructions");
+        log::debug!("{:?}, position: {:?}", instruction, position);
+        self.stack.swap_with_top(position);
+        Ok(StepResult::Continue)
+    }
+
+    fn add(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let v = a.overflowing_add(b).0;
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn mul(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let v = a.overflowing_mul(b).0;
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn div(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let v = if b.is_zero() { U256::zero() } else { a / b };
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn modulo(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let v = if b.is_zero() { U256::zero() } else { a % b };
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn sdiv(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let (a, sign_a) = Self::get_and_reset_sign(self.stack.pop());
+        let (b, sign_b) = Self::get_and_reset_sign(self.stack.pop());
+        let min = U256::one() << 255;
+        let v = if b.is_zero() {
+            U256::zero()
+        } else if a == min && b == U256::one() && sign_b {
+            // MIN / -1 overflows back to MIN
+            min
+        } else {
+            Self::set_sign(a / b, sign_a ^ sign_b)
+        };
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn smod(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let (a, sign_a) = Self::get_and_reset_sign(self.stack.pop());
+        let (b, _) = Self::get_and_reset_sign(self.stack.pop());
+        // the result takes the sign of the dividend
+        let v = if b.is_zero() { U256::zero() } else { Self::set_sign(a % b, sign_a) };
+        log::debug!("{:?}, a: {:?}, b: {:?}, v: {:?}", instruction, a, b, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn addmod(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let n = self.stack.pop();
+        // the sum can take 257 bits, so it is computed in 512 bits
+        let v = if n.is_zero() {
+            U256::zero()
+        } else {
+            ((U512::from(a) + U512::from(b)) % U512::from(n)).low_u256()
+        };
+        log::debug!("{:?}, a: {:?}, b: {:?}, n: {:?}, v: {:?}", instruction, a, b, n, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn mulmod(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        let n = self.stack.pop();
+        let v = if n.is_zero() {
+            U256::zero()
+        } else {
+            ((U512::from(a) * U512::from(b)) % U512::from(n)).low_u256()
+        };
+        log::debug!("{:?}, a: {:?}, b: {:?}, n: {:?}, v: {:?}", instruction, a, b, n, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn exp(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let base = self.stack.pop();
+        let exponent = self.stack.pop();
+        let v = base.overflowing_pow(exponent).0;
+        log::debug!("{:?}, base: {:?}, exponent: {:?}, v: {:?}", instruction, base, exponent, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn signextend(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let index = self.stack.pop();
+        let word = self.stack.pop();
+        // extend the sign bit of byte `index`, counted from the least significant byte
+        let v = if index < U256::from(31) {
+            let sign_bit = index.as_usize() * 8 + 7;
+            let mask = (U256::one() << sign_bit) - 1;
+            if word.bit(sign_bit) { word | !mask } else { word & mask }
+        } else {
+            word
+        };
+        log::debug!("{:?}, index: {:?}, word: {:?}, v: {:?}", instruction, index, word, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn lt(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(Self::bool_to_u256(a < b));
+        Ok(StepResult::Continue)
+    }
+
+    fn gt(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(Self::bool_to_u256(a > b));
+        Ok(StepResult::Continue)
+    }
+
+    fn slt(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(Self::bool_to_u256(Self::signed_cmp(a, b) == cmp::Ordering::Less));
+        Ok(StepResult::Continue)
+    }
+
+    fn sgt(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(Self::bool_to_u256(Self::signed_cmp(a, b) == cmp::Ordering::Greater));
+        Ok(StepResult::Continue)
+    }
+
+    fn eq(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(Self::bool_to_u256(a == b));
+        Ok(StepResult::Continue)
+    }
+
+    fn and(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(a & b);
+        Ok(StepResult::Continue)
+    }
+
+    fn or(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(a | b);
+        Ok(StepResult::Continue)
+    }
+
+    fn xor(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        let b = self.stack.pop();
+        log::debug!("{:?}, a: {:?}, b: {:?}", instruction, a, b);
+        self.stack.push(a ^ b);
+        Ok(StepResult::Continue)
+    }
+
+    fn not(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = self.stack.pop();
+        log::debug!("{:?}, a: {:?}", instruction, a);
+        self.stack.push(!a);
+        Ok(StepResult::Continue)
+    }
+
+    fn byte(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let index = self.stack.pop();
+        let word = self.stack.pop();
+        // `U256::byte` counts from the least significant byte
+        let v = if index < U256::from(32) {
+            U256::from(word.byte(31 - index.as_usize()))
         } else {
             U256::zero()
+        };
+        log::debug!("{:?}, index: {:?}, word: {:?}, v: {:?}", instruction, index, word, v);
+        self.stack.push(v);
+        Ok(StepResult::Continue)
+    }
+
+    fn sload(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let key = H256::from_uint(&self.stack.pop());
+        let val = ext.storage_at(&key)?.into_uint();
+        log::debug!("{:?}, key: {:?}, value: {:?}", instruction, key, val);
+        ext.al_insert_storage_key(self.params.address, key);
+        self.stack.push(val);
+        Ok(StepResult::Continue)
+    }
+
+    fn sstore(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let key = H256::from_uint(&self.stack.pop());
+        let val = self.stack.pop();
+
+        let current_val = ext.storage_at(&key)?.into_uint();
+        // Increase refund for clear
+        if ext.schedule().eip1283 {
+            let original_val = ext.initial_storage_at(&key)?.into_uint();
+            Self::eip1283_sstore_refund(ext, &original_val, &current_val, &val);
+        } else if !current_val.is_zero() && val.is_zero() {
+            let sstore_clears_schedule = ext.schedule().sstore_refund_gas;
+            ext.add_sstore_refund(sstore_clears_schedule);
         }
+        ext.set_storage(key, BigEndianHash::from_uint(&val))?;
+        ext.al_insert_storage_key(self.params.address, key);
+        log::debug!("{:?}", instruction);
+        Ok(StepResult::Continue)
     }
 
-    fn u256_to_bool(val: U256) -> bool { !val.is_zero() }
+    /// Net gas metering refunds (EIP-1283, EIP-2200), `original` is the value before the transaction
+    fn eip1283_sstore_refund(ext: &mut dyn Ext, original: &U256, current: &U256, new: &U256) {
+        let schedule = ext.schedule();
+        let clears_refund = schedule.sstore_refund_gas;
+        let set_refund = schedule.sstore_set_gas - schedule.sload_gas;
+        let reset_refund = schedule.sstore_reset_gas - schedule.sload_gas;
 
-    fn address_to_u256(address: &Address) -> U256 {
-        U256::from(address.as_bytes())
+        if current == new {
+            // no-op, nothing to refund
+        } else if original == current {
+            // first write to a clean slot
+            if !original.is_zero() && new.is_zero() {
+                ext.add_sstore_refund(clears_refund);
+            }
+        } else {
+            // the slot is dirty, undo the clear refund given by an earlier write
+            if !original.is_zero() {
+                if current.is_zero() {
+                    ext.sub_sstore_refund(clears_refund);
+                } else if new.is_zero() {
+                    ext.add_sstore_refund(clears_refund);
+                }
+            }
+            // reset to the original value, refund what the first write paid over an SLOAD
+            if original == new {
+                if original.is_zero() {
+                    ext.add_sstore_refund(set_refund);
+                } else {
+                    ext.add_sstore_refund(reset_refund);
+                }
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::interpreter::Interpreter;
-    use crate::types::{ActionParams, Exec, FakeExt};
-    use rustc_hex::FromHex;
-    use env_logger;
-    use common::{Address, U256};
-    use crate::stack::Stack;
+    fn caller(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let a = Self::address_to_u256(&self.params.sender);
+        log::debug!("{:?}, address as u256: {:?}", instruction, a);
+        self.stack.push(a);
+        Ok(StepResult::Continue)
+    }
 
-    #[test]
-    fn run_code_works() {
-        env_logger::init();
+    fn blockhash(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let number = self.stack.pop();
+        let hash = ext.blockhash(&number);
+        log::debug!("{:?}, number: {:?}, hash: {:?}", instruction, number, hash);
+        self.stack.push(U256::from(hash.as_bytes()));
+        Ok(StepResult::Continue)
+    }
 
-        let mut ext = FakeExt::new();
-        let code = "608060405234801561001057600080fd5b5060405161027238038061027283398181016040528101906100329190610082565b816000819055508060018190555050506100c2565b600080fd5b6000819050919050565b61005f8161004c565b811461006a57600080fd5b50565b60008151905061007c81610056565b92915050565b6000806040838503121561009957610098610047565b5b60006100a78582860161006d565b92505060206100b88582860161006d565b9150509250929050565b6101a1806100d16000396000f3fe608060405234801561001057600080fd5b50600436106100415760003560e01c80630dbe671f146100465780634df7e3d01461006457806357fc036314610082575b600080fd5b61004e61009e565b60405161005b91906100df565b60405180910390f35b61006c6100a4565b60405161007991906100df565b60405180910390f35b61009c6004803603810190610097919061012b565b6100aa565b005b60005481565b60015481565b8060026000848152602001908152602001600020819055505050565b6000819050919050565b6100d9816100c6565b82525050565b60006020820190506100f460008301846100d0565b92915050565b600080fd5b610108816100c6565b811461011357600080fd5b50565b600081359050610125816100ff565b92915050565b60008060408385031215610142576101416100fa565b5b600061015085828601610116565b925050602061016185828601610116565b915050925092905056fea26469706673582212203cf30509388126a38220d62e1dc55643f8b148cea6ac8c7b98ccdd8f0ce02cc364736f6c634300080c003300000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001".from_hex().unwrap();
-        let mut action_param = ActionParams::default();
-        action_param.gas = U256::from(100);
-        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
-        interpreter.exec(&mut ext);
+    fn coinbase(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let author = ext.env_info().author;
+        log::debug!("{:?}, author: {:?}", instruction, author);
+        self.stack.push(Self::address_to_u256(&author));
+        Ok(StepResult::Continue)
+    }
 
-        // while interpreter.stack.size() > 0 {
-        //     println!("{:?}", interpreter.stack.pop());
-        // }
+    fn timestamp(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let timestamp = ext.env_info().timestamp;
+        log::debug!("{:?}, timestamp: {:?}", instruction, timestamp);
+        self.stack.push(U256::from(timestamp));
+        Ok(StepResult::Continue)
     }
 
-    #[test]
-    fn run_code_work() {
-        env_logger::init();
+    fn number(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let number = ext.env_info().number;
+        log::debug!("{:?}, number: {:?}", instruction, number);
+        self.stack.push(U256::from(number));
+        Ok(StepResult::Continue)
+    }
 
-        let mut ext = FakeExt::new();
-        let code = "608060405234801561001057600080fd5b5060405160208061021783398101604090815290516000818155338152600160205291909120556101d1806100466000396000f3006080604052600436106100565763ffffffff7c010000000000000000000000000000000000000000000000000000000060003504166318160ddd811461005b57806370a0823114610082578063a9059cbb146100b0575b600080fd5b34801561006757600080fd5b506100706100f5565b60408051918252519081900360200190f35b34801561008e57600080fd5b5061007073ffffffffffffffffffffffffffffffffffffffff600435166100fb565b3480156100bc57600080fd5b506100e173ffffffffffffffffffffffffffffffffffffffff60043516602435610123565b604080519115158252519081900360200190f35b60005490565b73ffffffffffffffffffffffffffffffffffffffff1660009081526001602052604090205490565b600073ffffffffffffffffffffffffffffffffffffffff8316151561014757600080fd5b3360009081526001602052604090205482111561016357600080fd5b503360009081526001602081905260408083208054859003905573ffffffffffffffffffffffffffffffffffffffff85168352909120805483019055929150505600a165627a7a723058209a94330e3566febab4e903a73cf5b2a7674eca91ee95a8fcba4744635ead6c1500290000000000000000000000000000000000000000000000000000000000002710".from_hex().unwrap();
-        let mut action_param = ActionParams::default();
-        action_param.gas = U256::from(100);
-        action_param.sender = Address::random();
-        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
-        interpreter.exec(&mut ext).unwrap();
+    fn difficulty(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let difficulty = ext.env_info().difficulty;
+        log::debug!("{:?}, difficulty: {:?}", instruction, difficulty);
+        self.stack.push(difficulty);
+        Ok(StepResult::Continue)
+    }
 
-        // while interpreter.stack.size() > 0 {
-        //     println!("{:?}", interpreter.stack.pop());
-        // }
+    fn gaslimit(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let gas_limit = ext.env_info().gas_limit;
+        log::debug!("{:?}, gas limit: {:?}", instruction, gas_limit);
+        self.stack.push(gas_limit);
+        Ok(StepResult::Continue)
+    }
+
+    fn chainid(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().have_chain_id {
+            return Ok(StepResult::Error(Error::InvalidCommand));
+        }
+        let chain_id = ext.chain_id();
+        log::debug!("{:?}, chain id: {:?}", instruction, chain_id);
+        self.stack.push(U256::from(chain_id));
+        Ok(StepResult::Continue)
+    }
+
+    fn balance(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let address = Self::u256_to_address(&self.stack.pop());
+        let balance = ext.balance(&address)?;
+        log::debug!("{:?}, address: {:?}, balance: {:?}", instruction, address, balance);
+        ext.al_insert_address(address);
+        self.stack.push(balance);
+        Ok(StepResult::Continue)
+    }
+
+    fn selfbalance(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().have_selfbalance {
+            return Ok(StepResult::Error(Error::InvalidCommand));
+        }
+        let balance = ext.balance(&self.params.address)?;
+        log::debug!("{:?}, balance: {:?}", instruction, balance);
+        self.stack.push(balance);
+        Ok(StepResult::Continue)
+    }
+
+    fn extcodehash(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().have_extcodehash {
+            return Ok(StepResult::Error(Error::InvalidCommand));
+        }
+        let address = Self::u256_to_address(&self.stack.pop());
+        // empty accounts hash to zero, not to the hash of empty code (EIP-161)
+        let hash = if ext.exists_and_not_null(&address)? {
+            ext.extcodehash(&address)?.unwrap_or_default()
+        } else {
+            H256::zero()
+        };
+        log::debug!("{:?}, address: {:?}, hash: {:?}", instruction, address, hash);
+        ext.al_insert_address(address);
+        self.stack.push(hash.into_uint());
+        Ok(StepResult::Continue)
+    }
+
+    fn basefee(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        if !ext.schedule().eip3198 {
+            return Ok(StepResult::Error(Error::InvalidCommand));
+        }
+        let base_fee = ext.env_info().base_fee.unwrap_or_default();
+        log::debug!("{:?}, base fee: {:?}", instruction, base_fee);
+        self.stack.push(base_fee);
+        Ok(StepResult::Continue)
+    }
+
+    fn sha3(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let size = self.stack.pop();
+        let k = keccak(self.memory.read_slice(offset, size));
+        log::debug!("{:?}, offset: {:?}, size: {:?}, hash: {:?}", instruction, offset, size, k);
+        self.stack.push(k.into_uint());
+        Ok(StepResult::Continue)
+    }
+
+    fn log(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let topics = instruction
+            .log_topics()
+            .expect("log_topics always return some for LOG* instructions");
+        let offset = self.stack.pop();
+        let length = self.stack.pop();
+        let topics = (0..topics)
+            .map(|_| H256::from_uint(&self.stack.pop()))
+            .collect::<Vec<_>>();
+        log::debug!("{:?}, offset: {:?}, length: {:?}, topics: {:?}", instruction, offset, length, topics);
+        ext.log(topics, self.memory.read_slice(offset, length))?;
+        Ok(StepResult::Continue)
+    }
+
+    fn ret(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let length = self.stack.pop();
+        log::debug!("{:?}, offset: {:?}, length: {:?}", instruction, offset, length);
+        let memory = core::mem::replace(&mut self.memory, Memory::empty());
+        Ok(StepResult::Returned { memory, offset, length })
+    }
+
+    fn revert(&mut self, instruction: &Instruction, _ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let offset = self.stack.pop();
+        let length = self.stack.pop();
+        log::debug!("{:?}, offset: {:?}, length: {:?}", instruction, offset, length);
+        let memory = core::mem::replace(&mut self.memory, Memory::empty());
+        Ok(StepResult::Reverted { memory, offset, length })
+    }
+
+    fn call(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let gas = self.stack.pop();
+        let address = Self::u256_to_address(&self.stack.pop());
+        let value = self.stack.pop();
+        let in_offset = self.stack.pop();
+        let in_size = self.stack.pop();
+        let out_offset = self.stack.pop();
+        let out_size = self.stack.pop();
+        log::debug!("{:?}, address: {:?}, gas: {:?}, value: {:?}", instruction, address, gas, value);
+        self.return_data = ReturnData::empty();
+        ext.al_insert_address(address);
+
+        let sender = self.params.address;
+        // too deep or short of the value, the call fails without touching the state and
+        // the caller keeps the gas it would have forwarded
+        if self.params.depth >= ext.schedule().max_depth || ext.balance(&sender)? < value {
+            self.stack.push(U256::zero());
+            return Ok(StepResult::Continue);
+        }
+
+        let provided = self.gas_meter.gas_call_or_create(ext.schedule(), G::from(0), Some(gas))?;
+        self.gas_meter.charge(provided)?;
+        // the callee of a value transfer gets the stipend on top, free for the caller
+        let provided = match provided.overflow_add(G::from(ext.schedule().call_stipend)) {
+            _ if value.is_zero() => provided,
+            (with_stipend, false) => with_stipend,
+            (_, true) => return Err(Error::OutOfGas),
+        };
+        let data = self.memory.read_slice(in_offset, in_size).to_vec();
+        let call = match precompiles(&address, ext.schedule(), ext.env_info().number) {
+            // a precompile runs in place, the value moves through the externalities and
+            // back again if the precompile fails
+            Some((precompile, price)) => {
+                if !value.is_zero() {
+                    ext.transfer(&sender, &address, &value)?;
+                }
+                let call = Self::call_precompile(precompile, &price, &data, provided);
+                if matches!(call, MessageCallResult::Failed) && !value.is_zero() {
+                    ext.transfer(&address, &sender, &value)?;
+                }
+                call
+            }
+            None => ext.call(&provided.as_u256(), &sender, &address, Some(value), &data, &address, false)?,
+        };
+        let (success, output) = match call {
+            MessageCallResult::Success(gas_left, output) => {
+                self.gas_meter.refund(&gas_left);
+                (true, Some(output))
+            }
+            MessageCallResult::Reverted(gas_left, output) => {
+                self.gas_meter.refund(&gas_left);
+                (false, Some(output))
+            }
+            MessageCallResult::Failed => (false, None),
+        };
+        if let Some(output) = output {
+            let len = cmp::min(out_size, U256::from(output.len())).as_usize();
+            self.memory.write_slice(out_offset, &output[..len]);
+            self.return_data = output;
+        }
+        self.stack.push(Self::bool_to_u256(success));
+        Ok(StepResult::Continue)
+    }
+
+    /// Run a precompile in place of a call, running out of gas fails the call like an
+    /// exceptional halt of the callee would
+    fn call_precompile(precompile: PrecompileFn, price: &Linear, data: &[u8], gas: G) -> MessageCallResult {
+        let gas = cmp::min(gas.as_u256(), U256::from(u64::MAX)).as_u64();
+        match precompile(data, gas, price) {
+            Ok((output, gas_left)) => {
+                let len = output.len();
+                MessageCallResult::Success(U256::from(gas_left), ReturnData::new(output, 0, len))
+            }
+            Err(_) => MessageCallResult::Failed,
+        }
+    }
+
+    fn create(&mut self, instruction: &Instruction, ext: &mut dyn Ext) -> Result<StepResult<M>, Error> {
+        let value = self.stack.pop();
+        let offset = self.stack.pop();
+        let size = self.stack.pop();
+        log::debug!("{:?}, value: {:?}, offset: {:?}, size: {:?}", instruction, value, offset, size);
+        self.return_data = ReturnData::empty();
+
+        // too deep, the creation fails without touching the state
+        if self.params.depth >= ext.schedule().max_depth {
+            self.stack.push(U256::zero());
+            return Ok(StepResult::Continue);
+        }
+
+        let provided = self.gas_meter.gas_call_or_create(ext.schedule(), G::from(0), None)?;
+        self.gas_meter.charge(provided)?;
+        let code = self.memory.read_slice(offset, size).to_vec();
+        let scheme = CreateContractAddress::FromSenderAndNonce;
+        // the code of a created contract is not return data, only a revert reason is
+        let address = match ext.create(&provided.as_u256(), &value, &code, scheme, false)? {
+            ContractCreateResult::Created(address, gas_left) => {
+                self.gas_meter.refund(&gas_left);
+                Self::address_to_u256(&address)
+            }
+            ContractCreateResult::Reverted(gas_left, data) => {
+                self.gas_meter.refund(&gas_left);
+                self.return_data = data;
+                U256::zero()
+            }
+            ContractCreateResult::Failed => U256::zero(),
+        };
+        self.stack.push(address);
+        Ok(StepResult::Continue)
+    }
+
+    fn process_jump(&mut self, cond: bool, dest: U256) -> Result<(), Error> {
+        if self.jump_cache.is_none() {
+            self.jump_cache = Some(JumpCache::new(&self.reader.code));
+        }
+
+        // the reader is already past the jump, so not jumping just carries on
+        if !cond {
+            return Ok(());
+        }
+
+        // a destination past `usize` is past the end of the code as well
+        if dest > U256::from(usize::MAX) {
+            return Err(Error::InvalidJump);
+        }
+        let dest = dest.as_usize();
+        match &self.jump_cache {
+            Some(cache) => {
+                cache.valid_jump_dest(dest)?;
+                self.reader.set_pc(dest);
+            },
+            None => panic!("should not happen"),
+        }
+        Ok(())
+    }
+
+    /// Copy `size` bytes of `data` from `offset` to memory at `dest_offset`, zero padding
+    /// whatever lies past the end of `data`. The memory was expanded when charging the gas.
+    fn copy_padded(memory: &mut M, dest_offset: U256, data: &[u8], offset: U256, size: U256) {
+        if size.is_zero() {
+            return;
+        }
+        let dest = memory.writeable_slice(dest_offset, size);
+        let start = cmp::min(offset, U256::from(data.len())).as_usize();
+        let end = cmp::min(start.saturating_add(dest.len()), data.len());
+        dest[..end - start].copy_from_slice(&data[start..end]);
+        dest[end - start..].fill(0);
+    }
+
+    fn bool_to_u256(val: bool) -> U256 {
+        if val {
+            U256::one()
+        } else {
+            U256::zero()
+        }
+    }
+
+    fn u256_to_bool(val: U256) -> bool { !val.is_zero() }
+
+    /// The absolute value of a two's complement word and whether it is negative
+    fn get_and_reset_sign(value: U256) -> (U256, bool) {
+        let sign = value.bit(255);
+        (Self::set_sign(value, sign), sign)
+    }
+
+    /// Compare two words as two's complement signed values
+    fn signed_cmp(a: U256, b: U256) -> cmp::Ordering {
+        match (a.bit(255), b.bit(255)) {
+            (true, false) => cmp::Ordering::Less,
+            (false, true) => cmp::Ordering::Greater,
+            // same sign, two's complement keeps the unsigned order
+            _ => a.cmp(&b),
+        }
+    }
+
+    /// Negate `value` in two's complement if `sign` is set
+    fn set_sign(value: U256, sign: bool) -> U256 {
+        if sign {
+            (!value).overflowing_add(U256::one()).0
+        } else {
+            value
+        }
+    }
+
+    fn address_to_u256(address: &Address) -> U256 {
+        U256::from(address.as_bytes())
+    }
+
+    fn u256_to_address(value: &U256) -> Address {
+        Address::from_slice(&H256::from_uint(value)[12..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::instructions::Instruction;
+    use crate::interpreter::Interpreter;
+    use crate::precompile::precompile_addresses;
+    use crate::types::{builtins, ActionParams, ActionParamsBuilder, CallType, Exec, Ext, FakeExt, GasLeft, Schedule};
+    use rustc_hex::FromHex;
+    use env_logger;
+    use common::{keccak, Address, BigEndianHash, H256, U256};
+    use std::sync::Arc;
+    use crate::stack::Stack;
+
+    #[test]
+    fn run_code_works() {
+        let _ = env_logger::try_init();
+
+        let mut ext = FakeExt::new();
+        let code = "608060405234801561001057600080fd5b5060405161027238038061027283398181016040528101906100329190610082565b816000819055508060018190555050506100c2565b600080fd5b6000819050919050565b61005f8161004c565b811461006a57600080fd5b50565b60008151905061007c81610056565b92915050565b6000806040838503121561009957610098610047565b5b60006100a78582860161006d565b92505060206100b88582860161006d565b9150509250929050565b6101a1806100d16000396000f3fe608060405234801561001057600080fd5b50600436106100415760003560e01c80630dbe671f146100465780634df7e3d01461006457806357fc036314610082575b600080fd5b61004e61009e565b60405161005b91906100df565b60405180910390f35b61006c6100a4565b60405161007991906100df565b60405180910390f35b61009c6004803603810190610097919061012b565b6100aa565b005b60005481565b60015481565b8060026000848152602001908152602001600020819055505050565b6000819050919050565b6100d9816100c6565b82525050565b60006020820190506100f460008301846100d0565b92915050565b600080fd5b610108816100c6565b811461011357600080fd5b50565b600081359050610125816100ff565b92915050565b60008060408385031215610142576101416100fa565b5b600061015085828601610116565b925050602061016185828601610116565b915050925092905056fea26469706673582212203cf30509388126a38220d62e1dc55643f8b148cea6ac8c7b98ccdd8f0ce02cc364736f6c634300080c003300000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001".from_hex().unwrap();
+        let mut action_param = ActionParams::default();
+        action_param.gas = U256::from(100);
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
+        interpreter.exec(&mut ext);
+
+        // while interpreter.stack.size() > 0 {
+        //     println!("{:?}", interpreter.stack.pop());
+        // }
+    }
+
+    /// Deployment of a token contract, the constructor stores the initial supply
+    const TOKEN_CODE: &str = "608060405234801561001057600080fd5b5060405160208061021783398101604090815290516000818155338152600160205291909120556101d1806100466000396000f3006080604052600436106100565763ffffffff7c010000000000000000000000000000000000000000000000000000000060003504166318160ddd811461005b57806370a0823114610082578063a9059cbb146100b0575b600080fd5b34801561006757600080fd5b506100706100f5565b60408051918252519081900360200190f35b34801561008e57600080fd5b5061007073ffffffffffffffffffffffffffffffffffffffff600435166100fb565b3480156100bc57600080fd5b506100e173ffffffffffffffffffffffffffffffffffffffff60043516602435610123565b604080519115158252519081900360200190f35b60005490565b73ffffffffffffffffffffffffffffffffffffffff1660009081526001602052604090205490565b600073ffffffffffffffffffffffffffffffffffffffff8316151561014757600080fd5b3360009081526001602052604090205482111561016357600080fd5b503360009081526001602081905260408083208054859003905573ffffffffffffffffffffffffffffffffffffffff85168352909120805483019055929150505600a165627a7a723058209a94330e3566febab4e903a73cf5b2a7674eca91ee95a8fcba4744635ead6c1500290000000000000000000000000000000000000000000000000000000000002710";
+
+    #[test]
+    fn run_code_work() {
+        let _ = env_logger::try_init();
+
+        let mut ext = FakeExt::new();
+        let code = TOKEN_CODE.from_hex().unwrap();
+        let mut action_param = ActionParams::default();
+        action_param.gas = U256::from(1_000_000);
+        action_param.sender = Address::random();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
+        interpreter.exec(&mut ext).unwrap();
+
+        // while interpreter.stack.size() > 0 {
+        //     println!("{:?}", interpreter.stack.pop());
+        // }
+    }
+
+    #[test]
+    fn opcode_counts_works() {
+        // PUSH1 0x01 PUSH1 0x02 ADD PUSH1 0x03 ADD
+        let params = ActionParamsBuilder::new().gas(100).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new_with_profiling("6001600201600301".from_hex().unwrap(), params);
+        interpreter.exec(&mut FakeExt::new()).unwrap();
+        let counts = interpreter.opcode_counts().unwrap();
+        assert_eq!(counts[Instruction::PUSH1 as usize], 3);
+        assert_eq!(counts[Instruction::ADD as usize], 2);
+        assert_eq!(counts.iter().sum::<u64>(), 5);
+
+        let params = ActionParamsBuilder::new().gas(1_000_000).sender(Address::random()).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new_with_profiling(TOKEN_CODE.from_hex().unwrap(), params);
+        interpreter.exec(&mut FakeExt::new()).unwrap();
+        assert!(interpreter.opcode_counts().unwrap()[Instruction::PUSH1 as usize] > 0);
+
+        let interpreter = Interpreter::<Vec<u8>, usize>::new(vec![], ActionParams::default());
+        assert!(interpreter.opcode_counts().is_none());
+    }
+
+    #[test]
+    fn apply_state_works() {
+        // PUSH1 0x01 PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code = "600160005260206000f3".from_hex().unwrap();
+        let mut action_param = ActionParams::default();
+        action_param.gas = U256::from(100);
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
+        match interpreter.exec(&mut FakeExt::new()).unwrap() {
+            GasLeft::NeedsReturn { data, apply_state, .. } => {
+                assert!(apply_state);
+                assert_eq!(U256::from(&data[..]), U256::one());
+            },
+            _ => panic!("expected NeedsReturn"),
+        }
+
+        // PUSH1 0x01 PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 REVERT
+        let code = "600160005260206000fd".from_hex().unwrap();
+        let mut action_param = ActionParams::default();
+        action_param.gas = U256::from(100);
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, action_param);
+        match interpreter.exec(&mut FakeExt::new()).unwrap() {
+            GasLeft::NeedsReturn { data, apply_state, .. } => {
+                assert!(!apply_state);
+                assert_eq!(U256::from(&data[..]), U256::one());
+            },
+            _ => panic!("expected NeedsReturn"),
+        }
+    }
+
+    #[test]
+    fn stop_gas_left_works() {
+        // STOP costs nothing, so all of the gas is handed back
+        let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(vec![0x00], params);
+        let gas_left = interpreter.exec(&mut FakeExt::new()).unwrap();
+        assert!(matches!(gas_left, GasLeft::Known(gas) if gas == U256::from(1000)));
+    }
+
+    #[test]
+    fn invalid_schedule_works() {
+        // a schedule missing tier prices fails before running the code, rather than
+        // panicking on the first instruction of a tier it does not price
+        let mut ext = FakeExt::new();
+        ext.schedule.tier_step_gas = vec![0, 2, 3];
+        let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(vec![0x00], params);
+        assert!(matches!(interpreter.exec(&mut ext), Err(Error::InvalidSchedule)));
+    }
+
+    #[test]
+    fn out_of_gas_works() {
+        let run = |code: &str, gas: usize| {
+            let params = ActionParamsBuilder::new().gas(gas).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut FakeExt::new())
+        };
+
+        // PUSH1 0x01 PUSH1 0x02 ADD costs 3 + 3 + 3
+        assert!(run("6001600201", 9).is_ok());
+        assert!(matches!(run("6001600201", 8), Err(Error::OutOfGas)));
+        // JUMPDEST PUSH1 0x00 JUMP loops until the gas runs out
+        assert!(matches!(run("5b600056", 1000), Err(Error::OutOfGas)));
+    }
+
+    #[test]
+    fn revert_gas_left_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut FakeExt::new_istanbul())
+        };
+
+        // PUSH1 0x01 PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 REVERT
+        match run("600160005260206000fd").unwrap() {
+            GasLeft::NeedsReturn { gas_left, data, apply_state } => {
+                assert!(!apply_state);
+                assert_eq!(U256::from(&data[..]), U256::one());
+                assert_eq!(gas_left, U256::from(1000 - (3 + 3 + 3 + 3) - (3 + 3)));
+            },
+            _ => panic!("expected NeedsReturn"),
+        }
+
+        // PUSH1 0x20 PUSH1 0x40 REVERT, returning unwritten memory still pays for it
+        match run("60206040fd").unwrap() {
+            GasLeft::NeedsReturn { gas_left, data, .. } => {
+                assert_eq!(&data[..], &[0u8; 32][..]);
+                assert_eq!(gas_left, U256::from(1000 - (3 + 3) - 3 * 3));
+            },
+            _ => panic!("expected NeedsReturn"),
+        }
+
+        // PUSH1 0x01 STOP
+        assert!(matches!(run("600100").unwrap(), GasLeft::Known(gas) if gas == U256::from(997)));
+    }
+
+    #[test]
+    fn blockhash_works() {
+        let mut ext = FakeExt::new();
+        let hash = H256::random();
+        ext.blockhashes.insert(U256::from(5), hash);
+
+        // PUSH1 n BLOCKHASH PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        for (code, expected) in [
+            ("60054060005260206000f3", U256::from(hash.as_bytes())),
+            ("60064060005260206000f3", U256::zero()),
+        ] {
+            let mut action_param = ActionParams::default();
+            action_param.gas = U256::from(100);
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), action_param);
+            match interpreter.exec(&mut ext).unwrap() {
+                GasLeft::NeedsReturn { data, .. } => assert_eq!(U256::from(&data[..]), expected),
+                _ => panic!("expected NeedsReturn"),
+            }
+        }
+    }
+
+    /// Runs `code` and decodes the returned word
+    fn run_returning_word(code: &str, ext: &mut FakeExt) -> Result<U256, Error> {
+        let params = ActionParamsBuilder::new().gas(10_000).build::<usize>()?;
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+        match interpreter.exec(ext)? {
+            GasLeft::NeedsReturn { data, .. } => Ok(U256::from(&data[..])),
+            _ => panic!("expected NeedsReturn"),
+        }
+    }
+
+    /// Runs `code` to completion and pops the top of the stack
+    fn run_top_of_stack(code: &str) -> U256 {
+        let params = ActionParamsBuilder::new().gas(100).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+        interpreter.exec(&mut FakeExt::new()).unwrap();
+        interpreter.stack.pop()
+    }
+
+    #[test]
+    fn arithmetic_works() {
+        // the second operand is pushed first, `PUSH1 x PUSH1 0x00 SUB` pushes -x
+        let neg = |x: u64| !U256::from(x) + 1;
+        // PUSH1 0x02 PUSH1 0x01 PUSH1 0x00 SUB DIV PUSH1 0x01 ADD, (MAX / 2) + 1
+        let min = "6002600160000304600101";
+
+        let cases: Vec<(String, U256)> = vec![
+            // MUL
+            ("6007600602".into(), U256::from(42)),
+            ("6002600160000302".into(), neg(2)),
+            // DIV
+            ("6003600704".into(), U256::from(2)),
+            ("6000600704".into(), U256::zero()),
+            // MOD
+            ("6003600706".into(), U256::one()),
+            ("6000600706".into(), U256::zero()),
+            // SDIV: -7 / 2, 7 / -2, -7 / -2, division by zero, MIN / -1
+            ("6002600760000305".into(), neg(3)),
+            ("6002600003600705".into(), neg(3)),
+            ("6002600003600760000305".into(), U256::from(3)),
+            ("6000600760000305".into(), U256::zero()),
+            (format!("6001600003{}05", min), U256::one() << 255),
+            // SMOD: -7 % 2, 7 % -2, modulo by zero
+            ("6002600760000307".into(), neg(1)),
+            ("6002600003600707".into(), U256::one()),
+            ("6000600760000307".into(), U256::zero()),
+            // ADDMOD: 3 + 4 mod 5, MAX + MAX mod 7 overflows 256 bits, modulo by zero
+            ("60056004600308".into(), U256::from(2)),
+            ("60076001600003600160000308".into(), U256::from(2)),
+            ("60006004600308".into(), U256::zero()),
+            // MULMOD: 3 * 4 mod 5, modulo by zero
+            ("60056004600309".into(), U256::from(2)),
+            ("60006004600309".into(), U256::zero()),
+            // EXP: 2 ** 3, 2 ** 256 wraps to 0
+            ("600360020a".into(), U256::from(8)),
+            ("61010060020a".into(), U256::zero()),
+            // SIGNEXTEND: byte 0 of 0xff, 0x7f and 0xff00, byte 31 leaves the word as is
+            ("60ff60000b".into(), neg(1)),
+            ("607f60000b".into(), U256::from(0x7f)),
+            ("61ff0060000b".into(), U256::zero()),
+            ("60ff601f0b".into(), U256::from(0xff)),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(run_top_of_stack(&code), expected, "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn stack_underflow_works() {
+        let params = ActionParamsBuilder::new().gas(100).build::<usize>().unwrap();
+
+        // POP on an empty stack
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("50".from_hex().unwrap(), params.clone());
+        let result = interpreter.exec(&mut FakeExt::new());
+        assert!(matches!(result, Err(Error::StackUnderflow { instruction: "POP", wanted: 1, on_stack: 0 })));
+
+        // PUSH1 0x01 ADD
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("600101".from_hex().unwrap(), params);
+        let result = interpreter.exec(&mut FakeExt::new());
+        assert!(matches!(result, Err(Error::StackUnderflow { instruction: "ADD", wanted: 2, on_stack: 1 })));
+    }
+
+    #[test]
+    fn stack_limit_works() {
+        let run = |pushes: usize| {
+            let params = ActionParamsBuilder::new().gas(10_000).build::<usize>().unwrap();
+            let code = "6001".repeat(pushes).from_hex().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, params);
+            let result = interpreter.exec(&mut FakeExt::new());
+            (result, interpreter.stack.size())
+        };
+
+        let (result, size) = run(1024);
+        assert!(result.is_ok());
+        assert_eq!(size, 1024);
+
+        let (result, size) = run(1025);
+        assert!(matches!(result, Err(Error::OutOfStack { instruction: "PUSH1", wanted: 1, limit: 1024 })));
+        assert_eq!(size, 1024);
+    }
+
+    #[test]
+    fn log_works() {
+        // PUSH2 0xbeef PUSH1 0x00 MSTORE, then PUSH1 0x22 PUSH1 0x11 (topics)
+        // PUSH1 0x02 (length) PUSH1 0x1e (offset) LOG2
+        let code = "61beef600052602260116002601ea2";
+        let params = ActionParamsBuilder::new().gas(10000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+        let mut ext = FakeExt::new_istanbul();
+        interpreter.exec(&mut ext).unwrap();
+
+        assert_eq!(ext.logs.len(), 1);
+        assert_eq!(ext.logs[0].topics, vec![H256::from_low_u64_be(0x11), H256::from_low_u64_be(0x22)]);
+        assert_eq!(ext.logs[0].data, vec![0xbe, 0xef]);
+    }
+
+    #[test]
+    fn log_gas_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(10000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            let mut ext = FakeExt::new_istanbul();
+            ext.schedule.memory_gas = 0;
+            interpreter.exec(&mut ext).unwrap();
+            interpreter.gas_used()
+        };
+
+        // PUSH1 0x00 PUSH1 0x00 LOG0
+        assert_eq!(run("60006000a0"), 3 + 3 + 375);
+        // two topics and no data
+        assert_eq!(run("6022601160006000a2"), 4 * 3 + 375 + 2 * 375);
+        // PUSH1 0x02 PUSH1 0x00 LOG0, two bytes of data
+        assert_eq!(run("60026000a0"), 3 + 3 + 375 + 2 * 8);
+        // PUSH1 0x00 PUSH1 0x00 NOT LOG0, no data wherever it starts
+        assert_eq!(run("6000600019a0"), 3 + 3 + 3 + 375);
+
+        // PUSH1 0x00 NOT PUSH1 0x00 LOG0, more data than could ever be paid for
+        let params = ActionParamsBuilder::new().gas(10000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("6000196000a0".from_hex().unwrap(), params);
+        assert!(matches!(interpreter.exec(&mut FakeExt::new_istanbul()), Err(Error::OutOfGas)));
+    }
+
+    #[test]
+    fn extcodehash_works() {
+        // PUSH1 address EXTCODEHASH PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code = |address: u8| format!("60{:02x}3f60005260206000f3", address);
+        let touched = Address::from_low_u64_be(1);
+        let contract = Address::from_low_u64_be(2);
+        let contract_code = vec![0x60, 0x00];
+
+        let mut ext = FakeExt::new_istanbul();
+        ext.balances.insert(touched, U256::zero());
+        ext.codes.insert(contract, Arc::new(contract_code.clone()));
+
+        assert_eq!(run_returning_word(&code(1), &mut ext).unwrap(), U256::zero());
+        assert_eq!(
+            run_returning_word(&code(2), &mut ext).unwrap(),
+            keccak(&contract_code).into_uint()
+        );
+        // an account that was never touched
+        assert_eq!(run_returning_word(&code(3), &mut ext).unwrap(), U256::zero());
+
+        assert!(matches!(run_returning_word(&code(2), &mut FakeExt::new()), Err(Error::InvalidCommand)));
+    }
+
+    #[test]
+    fn return_data_works() {
+        // CALL address 0xaa RETURNDATASIZE PUSH1 0x00 MSTORE
+        // PUSH1 0x01 PUSH1 0x01 PUSH1 0x1d RETURNDATACOPY (second output byte to 0x1d)
+        // CREATE RETURNDATASIZE PUSH1 0x20 MSTORE PUSH1 0x40 PUSH1 0x00 RETURN
+        let code = "6000600060006000600060aa6010f1503d60005260016001601d3e600060006000f0503d60205260406000f3";
+        let run = |ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(ext)
+        };
+
+        let mut ext = FakeExt::new_istanbul();
+        ext.call_output = vec![0xaa, 0xbb, 0xcc];
+        ext.created_address = Some(Address::from_low_u64_be(2));
+        match run(&mut ext).unwrap() {
+            GasLeft::NeedsReturn { data, .. } => {
+                assert_eq!(U256::from(&data[..32]), U256::from(3) + (U256::from(0xbb) << 16));
+                assert_eq!(U256::from(&data[32..]), U256::zero());
+            }
+            _ => panic!("expected NeedsReturn"),
+        }
+
+        // copying past the end of the output
+        ext.call_output = vec![0xaa];
+        assert!(matches!(run(&mut ext), Err(Error::ReturnDataOutOfBounds)));
+
+        assert!(matches!(run(&mut FakeExt::new()), Err(Error::InvalidCommand)));
+
+        // CALL address 0xaa, then RETURNDATACOPY with an offset or a size past `usize`
+        let copy_after_call = |copy: &str| {
+            let code = format!("6000600060006000600060aa6010f150{}00", copy);
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            let mut ext = FakeExt::new_istanbul();
+            ext.call_output = vec![0xaa, 0xbb, 0xcc];
+            interpreter.exec(&mut ext)
+        };
+        // PUSH1 0x01 PUSH1 0x00 NOT PUSH1 0x00 RETURNDATACOPY
+        assert!(matches!(copy_after_call("600160001960003e"), Err(Error::ReturnDataOutOfBounds)));
+        // PUSH1 0x00 PUSH1 0x00 NOT PUSH1 0x00 RETURNDATACOPY, empty but still out of bounds
+        assert!(matches!(copy_after_call("600060001960003e"), Err(Error::ReturnDataOutOfBounds)));
+        // PUSH1 0x00 NOT PUSH1 0x00 PUSH1 0x00 RETURNDATACOPY
+        assert!(matches!(copy_after_call("600019600060003e"), Err(Error::OutOfGas)));
+        // PUSH1 0x02 PUSH1 0x01 PUSH1 0x00 RETURNDATACOPY, up to the end exactly
+        assert!(copy_after_call("6002600160003e").is_ok());
+    }
+
+    #[test]
+    fn new_with_code_works() {
+        // CODESIZE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let borrowed: Vec<u8> = "3860005260206000f3".from_hex().unwrap();
+        let own = Address::from_low_u64_be(1);
+        let library = Address::from_low_u64_be(2);
+        let mut ext = FakeExt::new();
+        ext.codes.insert(library, Arc::new(borrowed.clone()));
+
+        let mut params = ActionParamsBuilder::new().gas(100).build::<usize>().unwrap();
+        params.address = own;
+        params.code_address = own;
+        params.call_type = CallType::DelegateCall;
+        let code = ext.extcode(&library).unwrap().unwrap().to_vec();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new_with_code(code, params, library);
+        assert_eq!(interpreter.params.address, own);
+        assert_eq!(interpreter.params.code_address, library);
+        assert_eq!(interpreter.params.code_hash, Some(keccak(&borrowed)));
+
+        match interpreter.exec(&mut ext).unwrap() {
+            GasLeft::NeedsReturn { data, .. } => assert_eq!(U256::from(&data[..]), U256::from(borrowed.len())),
+            _ => panic!("expected NeedsReturn"),
+        }
+    }
+
+    #[test]
+    fn call_new_account_gas_works() {
+        // PUSH1 0x00 (x4) PUSH1 0x01 (value) PUSH1 0xaa (address) PUSH1 0x10 (gas) CALL
+        let code = "6000600060006000600160aa6010f1";
+        let run = |ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(ext).unwrap();
+            interpreter.gas_used()
+        };
+
+        let mut ext = FakeExt::new_istanbul();
+        ext.balances.insert(Address::zero(), U256::one());
+        ext.balances.insert(Address::from_low_u64_be(0xaa), U256::one());
+        let existing = run(&mut ext);
+        // touched but empty
+        ext.balances.insert(Address::from_low_u64_be(0xaa), U256::zero());
+        let empty = run(&mut ext);
+        // the callee hands back all of its gas, the stipend included
+        assert_eq!(existing, 7 * 3 + 700 + 9000 - 2300);
+        assert_eq!(empty, existing + 25000);
+    }
+
+    #[test]
+    fn call_value_works() {
+        // PUSH1 0x00 (x4) PUSH1 0x01 (value) PUSH1 0xaa (address) PUSH1 0x10 (gas) CALL
+        let code = "6000600060006000600160aa6010f1";
+        let run = |ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(ext).unwrap();
+            (interpreter.stack.pop(), interpreter.gas_used())
+        };
+
+        // the callee gets the stipend on top of the gas requested
+        let mut ext = FakeExt::new_istanbul();
+        ext.balances.insert(Address::zero(), U256::one());
+        ext.balances.insert(Address::from_low_u64_be(0xaa), U256::one());
+        assert_eq!(run(&mut ext), (U256::one(), 7 * 3 + 700 + 9000 - 2300));
+        assert_eq!(ext.calls.iter().next().unwrap().gas, U256::from(0x10 + 2300));
+
+        // short of the value, the call fails and the gas requested is kept
+        let mut ext = FakeExt::new_istanbul();
+        ext.balances.insert(Address::from_low_u64_be(0xaa), U256::one());
+        assert_eq!(run(&mut ext), (U256::zero(), 7 * 3 + 700 + 9000));
+        assert!(ext.calls.is_empty());
+    }
+
+    #[test]
+    fn call_gas_works() {
+        let run = |code: &str, ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            let result = interpreter.exec(ext).map(|_| interpreter.gas_used());
+            let forwarded = ext.calls.iter().next().map(|c| c.gas);
+            (result, forwarded)
+        };
+        // PUSH1 0x00 (x4) PUSH1 0x00 (value) PUSH1 0xaa (address), then the requested gas and CALL
+        let call = |gas: &str| format!("6000600060006000600060aa{}f100", gas);
+
+        // PUSH2 0xffff, less than the gas left is forwarded as requested and the
+        // unused part handed back
+        let (result, forwarded) = run(&call("61ffff"), &mut FakeExt::new_istanbul());
+        assert_eq!(result.unwrap(), 7 * 3 + 700);
+        assert_eq!(forwarded, Some(U256::from(0xffff)));
+        // PUSH1 0x00 NOT, all but one 64th of the gas left
+        let (_, forwarded) = run(&call("600019"), &mut FakeExt::new_istanbul());
+        let gas_left = 100_000 - (8 * 3 + 700);
+        assert_eq!(forwarded, Some(U256::from(gas_left - gas_left / 64)));
+        // without EIP-150 requesting more than is left runs out of gas
+        let mut ext = FakeExt::new_istanbul();
+        ext.schedule.sub_gas_cap_divisor = None;
+        let (result, _) = run(&call("600019"), &mut ext);
+        assert!(matches!(result, Err(Error::OutOfGas)));
+
+        // PUSH1 0x00 PUSH1 0x00 PUSH1 0x00 CREATE forwards all but one 64th
+        let mut ext = FakeExt::new_istanbul();
+        ext.created_address = Some(Address::from_low_u64_be(2));
+        let (result, forwarded) = run("600060006000f000", &mut ext);
+        assert_eq!(result.unwrap(), 3 * 3 + 32000);
+        let gas_left = 100_000 - (3 * 3 + 32000);
+        assert_eq!(forwarded, Some(U256::from(gas_left - gas_left / 64)));
+    }
+
+    #[test]
+    fn precompile_call_works() {
+        // PUSH1 0x2a PUSH1 0x00 MSTORE, then CALL the identity precompile at 0x04 with the word
+        // as input and 0x20 as output, PUSH1 gas, POP PUSH1 0x20 PUSH1 0x20 RETURN
+        let code = |gas: u8| format!("602a60005260206020602060006000600460{:02x}f15060206020f3", gas);
+        let run = |gas: u8| {
+            let params = ActionParamsBuilder::new().gas(10_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code(gas).from_hex().unwrap(), params);
+            let mut ext = FakeExt::new_istanbul();
+            ext.schedule.set_builtins(&builtins());
+            let data = match interpreter.exec(&mut ext).unwrap() {
+                GasLeft::NeedsReturn { data, .. } => U256::from(&data[..]),
+                _ => panic!("expected NeedsReturn"),
+            };
+            assert!(ext.calls.is_empty());
+            (data, interpreter.gas_used())
+        };
+
+        // 15 + 3 for one word of input
+        assert_eq!(run(0xff), (U256::from(0x2a), 11 * 3 + 3 + 3 + 700 + 3 + 18 + 2));
+        // not enough for the precompile, the call fails and the gas forwarded is gone
+        assert_eq!(run(0x10), (U256::zero(), 11 * 3 + 3 + 3 + 700 + 3 + 0x10 + 2));
+    }
+
+    #[test]
+    fn precompile_call_value_works() {
+        // CALL the precompile at `address` with 1 wei, a word of zero input and no gas
+        // but the stipend
+        let code = |address: u8| format!("6020602060206000600160{:02x}6000f1", address);
+        let run = |address: u8| {
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code(address).from_hex().unwrap(), params);
+            let mut ext = FakeExt::new_istanbul();
+            ext.schedule.set_builtins(&builtins());
+            ext.balances.insert(Address::zero(), U256::from(5));
+            interpreter.exec(&mut ext).unwrap();
+            let precompile = Address::from_low_u64_be(address as u64);
+            (
+                interpreter.stack.pop(),
+                ext.balance(&Address::zero()).unwrap(),
+                ext.balance(&precompile).unwrap(),
+            )
+        };
+
+        // the identity runs on the stipend and keeps the value
+        assert_eq!(run(4), (U256::one(), U256::from(4), U256::one()));
+        // ecrecover costs more than the stipend, the failed call hands the value back
+        assert_eq!(run(1), (U256::zero(), U256::from(5), U256::zero()));
+    }
+
+    #[test]
+    fn mulmod_wide_intermediate_works() {
+        // PUSH1 0x07, 2^255 via (MAX / 2) + 1, DUP1 MULMOD: 2^510 mod 7 is 1, truncating
+        // the product to 256 bits would give 0
+        let code = "600760026001600003046001018009";
+        assert_eq!(run_top_of_stack(code), U256::one());
+    }
+
+    #[test]
+    fn exp_gas_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut FakeExt::new_istanbul()).unwrap();
+            interpreter.gas_used()
+        };
+
+        // PUSH1 + PUSH1 + EXP with a zero exponent
+        assert_eq!(run("600060020a"), 3 + 3 + 10);
+        // a one byte exponent
+        assert_eq!(run("600360020a"), 3 + 3 + 10 + 50);
+        // PUSH2 0x0100, a two byte exponent
+        assert_eq!(run("61010060020a"), 3 + 3 + 10 + 2 * 50);
+    }
+
+    #[test]
+    fn mstore_gas_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut FakeExt::new_istanbul()).unwrap();
+            (interpreter.gas_used(), interpreter.memory.len())
+        };
+
+        // PUSH1 + PUSH1 + MSTORE, expanding memory by one word
+        assert_eq!(run("6001600052"), (3 + 3 + 3 + 3, 32));
+        // the same word again, nothing more to expand
+        assert_eq!(run("60016000526002600052"), (2 * (3 + 3 + 3) + 3, 32));
+        // PUSH1 0x21, an unaligned offset touching two more words, three in total are charged
+        assert_eq!(run("60016000526001602152"), (2 * (3 + 3 + 3) + 3 * 3, 96));
+    }
+
+    #[test]
+    fn huge_memory_offset_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(10_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut FakeExt::new_cancun())
+        };
+
+        // PUSH1 0x00 NOT MLOAD, memory that can never be paid for
+        assert!(matches!(run("6000195100"), Err(Error::OutOfGas)));
+        // PUSH1 0x00 NOT PUSH1 0x00 PUSH1 0x00 MCOPY
+        assert!(matches!(run("600019600060005e"), Err(Error::OutOfGas)));
+        // PUSH1 0x00 PUSH1 0x00 NOT RETURN, an empty region needs no memory wherever it is
+        assert!(matches!(
+            run("6000600019f3"),
+            Ok(GasLeft::NeedsReturn { data, .. }) if data.is_empty()
+        ));
+
+        // PUSH1 0x20 PUSH1 0x00 NOT PUSH1 0x00 CODECOPY PUSH1 0x20 PUSH1 0x00 RETURN,
+        // code past its end reads as zero
+        let code = "602060001960003960206000f3";
+        assert_eq!(run_returning_word(code, &mut FakeExt::new()).unwrap(), U256::zero());
+        // PUSH1 0x20 PUSH1 0x0a PUSH1 0x00 CODECOPY PUSH1 0x20 PUSH1 0x00 RETURN,
+        // the last two bytes of the code followed by zeros
+        let code = "6020600a60003960206000f3";
+        assert_eq!(run_returning_word(code, &mut FakeExt::new()).unwrap(), U256::from(0xf3) << 240);
+    }
+
+    #[test]
+    fn quadratic_mem_gas_works() {
+        // PUSH1 0x01 PUSH1 0x20 PUSH2 0x1000 PUSH2 0x0100 MUL SUB MSTORE,
+        // storing at 0x100000 - 0x20 so the word ends at 1 MiB
+        let code = "60016020611000610100020352";
+        let params = ActionParamsBuilder::new().gas(3_000_000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+        interpreter.exec(&mut FakeExt::new_istanbul()).unwrap();
+        assert_eq!(interpreter.memory.len(), 1 << 20);
+
+        let words = (1 << 20) / 32;
+        let linear = 3 * words;
+        let quadratic = words * words / 512;
+        assert_eq!(quadratic, 2_097_152);
+        assert_eq!(interpreter.gas_used(), 4 * 3 + 5 + 3 + 3 + linear + quadratic);
+    }
+
+    #[test]
+    fn sload_works() {
+        let mut ext = FakeExt::new_istanbul();
+        ext.store.insert(H256::from_low_u64_be(1), H256::from_low_u64_be(0x2a));
+
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SLOAD ADD, an unset slot reads as zero
+        let params = ActionParamsBuilder::new().gas(10_000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("60015460025401".from_hex().unwrap(), params);
+        interpreter.exec(&mut ext).unwrap();
+        assert_eq!(interpreter.stack.pop(), U256::from(0x2a));
+        assert_eq!(interpreter.gas_used(), 3 + 800 + 3 + 800 + 3);
+    }
+
+    #[test]
+    fn sstore_refund_works() {
+        let refund_with = |mut ext: FakeExt, original: u64, code: &str| {
+            let key = H256::zero();
+            ext.initial_store.insert(key, H256::from_low_u64_be(original));
+            ext.store.insert(key, H256::from_low_u64_be(original));
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(&mut ext).unwrap();
+            ext.sstore_clears
+        };
+        let refund = |original: u64, code: &str| refund_with(FakeExt::new_istanbul(), original, code);
+
+        // PUSH1 0x01 PUSH1 0x00 SSTORE PUSH1 0x00 PUSH1 0x00 SSTORE, set then reset to the original zero
+        assert_eq!(refund(0, "60016000556000600055"), 20000 - 800);
+        // PUSH1 0x02 PUSH1 0x00 SSTORE PUSH1 0x01 PUSH1 0x00 SSTORE, change then reset to the original one
+        assert_eq!(refund(1, "60026000556001600055"), 5000 - 800);
+        // PUSH1 0x00 PUSH1 0x00 SSTORE PUSH1 0x01 PUSH1 0x00 SSTORE, the clear refund is taken back
+        assert_eq!(refund(1, "60006000556001600055"), 5000 - 800);
+        // PUSH1 0x02 PUSH1 0x00 SSTORE PUSH1 0x00 PUSH1 0x00 SSTORE, clearing a dirty slot
+        assert_eq!(refund(1, "60026000556000600055"), 15000);
+        // PUSH1 0x01 PUSH1 0x00 SSTORE, a no-op write
+        assert_eq!(refund(1, "6001600055"), 0);
+        // PUSH1 0x00 PUSH1 0x00 SSTORE, clearing under London refunds less (EIP-3529)
+        assert_eq!(refund_with(FakeExt::new_london(), 1, "6000600055"), 4800);
+    }
+
+    #[test]
+    fn sstore_gas_works() {
+        let run = |original: u64, code: &str, gas: usize, ext: &mut FakeExt| {
+            let key = H256::zero();
+            ext.initial_store.insert(key, H256::from_low_u64_be(original));
+            ext.store.insert(key, H256::from_low_u64_be(original));
+            let params = ActionParamsBuilder::new().gas(gas).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            interpreter.exec(ext).map(|_| interpreter.gas_used() - 4 * 3)
+        };
+        let istanbul = |original: u64, code: &str| run(original, code, 100_000, &mut FakeExt::new_istanbul()).unwrap();
+
+        // PUSH1 0x01 PUSH1 0x00 SSTORE PUSH1 0x02 PUSH1 0x00 SSTORE, only the first write to
+        // the clean slot pays for it
+        assert_eq!(istanbul(0, "60016000556002600055"), 20000 + 800);
+        assert_eq!(istanbul(1, "60026000556003600055"), 5000 + 800);
+        // PUSH1 0x01 PUSH1 0x00 SSTORE (x2), writing the current value
+        assert_eq!(istanbul(1, "60016000556001600055"), 800 + 800);
+
+        // without net gas metering every write pays, a no-op one as a reset
+        let mut ext = FakeExt::new_istanbul();
+        ext.schedule.eip1283 = false;
+        assert_eq!(run(0, "60016000556002600055", 100_000, &mut ext).unwrap(), 20000 + 5000);
+
+        // PUSH1 0x01 PUSH1 0x00 SSTORE with just the stipend left fails (EIP-2200)
+        let mut ext = FakeExt::new_istanbul();
+        assert!(matches!(run(0, "6001600055", 2 * 3 + 2300, &mut ext), Err(Error::OutOfGas)));
+        assert!(run(0, "6001600055", 2 * 3 + 20000, &mut ext).is_ok());
+    }
+
+    #[test]
+    fn comparison_works() {
+        // `PUSH1 b PUSH1 a OP` compares a with b, `PUSH1 x PUSH1 0x00 SUB` pushes -x
+        for (code, expected) in [
+            // LT, GT, EQ
+            ("6002600110", 1),
+            ("6001600210", 0),
+            ("6002600111", 0),
+            ("6001600211", 1),
+            ("6002600214", 1),
+            ("6002600314", 0),
+            // equal operands are neither less nor greater
+            ("6002600210", 0),
+            ("6002600211", 0),
+            ("6002600212", 0),
+            ("6002600213", 0),
+            // -1 is the largest unsigned word but below 1 when signed
+            ("6001600160000310", 0),
+            ("6001600160000312", 1),
+            ("6001600160000313", 0),
+            ("6001600003600113", 1),
+            ("6001600003600112", 0),
+            // -2 < -1
+            ("6001600003600260000312", 1),
+            ("6001600003600260000313", 0),
+        ] {
+            assert_eq!(run_top_of_stack(code), U256::from(expected), "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn stop_and_invalid_works() {
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(1000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            let result = interpreter.exec(&mut FakeExt::new());
+            (result, interpreter.gas_used())
+        };
+
+        // PUSH1 0x01 PUSH1 0x02 ADD STOP INVALID, the INVALID is never reached
+        let (result, gas_used) = run("600160020100fe");
+        assert!(matches!(result, Ok(GasLeft::Known(_))));
+        assert_eq!(gas_used, 9);
+
+        // PUSH1 0x01 INVALID
+        let (result, gas_used) = run("6001fe");
+        assert!(matches!(result, Err(Error::InvalidInstruction)));
+        assert_eq!(gas_used, 1000);
+    }
+
+    #[test]
+    fn bitwise_works() {
+        for (code, expected) in [
+            // PUSH1 0x0c PUSH1 0x0a AND / OR / XOR
+            ("600c600a16", U256::from(0x08)),
+            ("600c600a17", U256::from(0x0e)),
+            ("600c600a18", U256::from(0x06)),
+            // PUSH1 0x00 NOT
+            ("600019", U256::MAX),
+            // PUSH2 0x0102 PUSH1 0x1e BYTE, the second least significant byte
+            ("610102601e1a", U256::from(0x01)),
+        ] {
+            assert_eq!(run_top_of_stack(code), expected, "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn byte_boundary_works() {
+        // PUSH2 0x0102 PUSH1 0x1f BYTE, the least significant byte
+        assert_eq!(run_top_of_stack("610102601f1a"), U256::from(0x02));
+        // PUSH2 0x0102 PUSH1 0x20 BYTE, out of the word
+        assert_eq!(run_top_of_stack("61010260201a"), U256::zero());
+    }
+
+    #[test]
+    fn mcopy_works() {
+        // PUSH2 0x0102 PUSH1 0x00 MSTORE
+        // PUSH1 0x02 PUSH1 0x1e PUSH1 0x1f MCOPY (copies bytes 30..32 to 31..33)
+        // PUSH1 0x20 PUSH1 0x01 RETURN
+        let code = "6101026000526002601e601f5e60206001f3";
+
+        let mut ext = FakeExt::new_cancun();
+        assert_eq!(run_returning_word(code, &mut ext).unwrap(), U256::from(0x010102));
+
+        let mut ext = FakeExt::new_london();
+        assert!(matches!(run_returning_word(code, &mut ext), Err(Error::InvalidCommand)));
+    }
+
+    #[test]
+    fn calldata_works() {
+        let data: Vec<u8> = (1..=36).collect();
+        let run = |code: &str| {
+            let params = ActionParamsBuilder::new().gas(1000).data(data.clone()).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            match interpreter.exec(&mut FakeExt::new_istanbul()).unwrap() {
+                GasLeft::NeedsReturn { data, .. } => data.to_vec(),
+                _ => panic!("expected NeedsReturn"),
+            }
+        };
+
+        // PUSH1 0x10 CALLDATALOAD PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        // the word straddles the end of the data, the trailing 12 bytes are zero
+        let mut expected = data[16..].to_vec();
+        expected.resize(32, 0);
+        assert_eq!(run("60103560005260206000f3"), expected);
+
+        // CALLDATASIZE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        assert_eq!(U256::from(&run("3660005260206000f3")[..]), U256::from(36));
+
+        // PUSH1 0x00 NOT PUSH1 0x00 MSTORE, then
+        // PUSH1 0x20 PUSH1 0x1e PUSH1 0x00 CALLDATACOPY overwrites the word with zero padding
+        let mut expected = data[30..].to_vec();
+        expected.resize(32, 0);
+        assert_eq!(run("6000196000526020601e60003760206000f3"), expected);
+
+        // PUSH1 0x20 PUSH1 0x00 NOT PUSH1 0x00 CALLDATACOPY, a data offset past `usize`
+        assert_eq!(run("602060001960003760206000f3"), vec![0; 32]);
+
+        // PUSH1 0x00 NOT PUSH1 0x00 PUSH1 0x00 CALLDATACOPY, more than could ever be paid for
+        let params = ActionParamsBuilder::new().gas(1000).data(data.clone()).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("6000196000600037".from_hex().unwrap(), params);
+        assert!(matches!(interpreter.exec(&mut FakeExt::new_istanbul()), Err(Error::OutOfGas)));
+    }
+
+    #[test]
+    fn selfbalance_works() {
+        // SELFBALANCE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code = "4760005260206000f3";
+
+        let mut ext = FakeExt::new_istanbul();
+        ext.balances.insert(Address::default(), U256::from(1234));
+        assert_eq!(run_returning_word(code, &mut ext).unwrap(), U256::from(1234));
+
+        let mut ext = FakeExt::new();
+        assert!(matches!(run_returning_word(code, &mut ext), Err(Error::InvalidCommand)));
+    }
+
+    #[test]
+    fn access_list_warm_works() {
+        let sender = Address::from_low_u64_be(0xaa);
+        let to = Address::from_low_u64_be(0xbb);
+        let gas_used = |code: String, ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100_000).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            match interpreter.exec(ext).unwrap() {
+                GasLeft::Known(gas_left) => 100_000 - gas_left.as_u64(),
+                _ => panic!("expected Known"),
+            }
+        };
+        // PUSH1 address BALANCE POP
+        let balance = |address: Address| format!("60{:02x}3150", address.to_low_u64_be());
+
+        let mut schedule = Schedule::new_berlin();
+        schedule.set_builtins(&builtins());
+        let precompiles = precompile_addresses(&schedule, 0);
+        let mut ext = FakeExt::new_berlin(sender, to, &precompiles);
+        assert_eq!(gas_used(balance(sender), &mut ext), 3 + 100 + 2);
+        assert_eq!(gas_used(balance(to), &mut ext), 3 + 100 + 2);
+        assert_eq!(gas_used(balance(precompiles[0]), &mut ext), 3 + 100 + 2);
+
+        // an untouched account is cold once per transaction
+        let other = Address::from_low_u64_be(0xcc);
+        let twice = balance(other).repeat(2);
+        assert_eq!(gas_used(twice.clone(), &mut ext), 3 + 2600 + 2 + 3 + 100 + 2);
+        ext.reset_access_list_for_tx(sender, to, &precompiles);
+        assert_eq!(gas_used(balance(other), &mut ext), 3 + 2600 + 2);
+
+        // PUSH1 0x01 SLOAD POP, twice
+        assert_eq!(gas_used("60015450".repeat(2), &mut ext), 3 + 2100 + 2 + 3 + 100 + 2);
+        // PUSH1 0x01 PUSH1 0x02 SSTORE, twice, the first write pays the cold surcharge
+        assert_eq!(gas_used("6001600255".repeat(2), &mut ext), 2 * 3 + 20000 + 2100 + 2 * 3 + 100);
+        // PUSH1 0x00 (x5) PUSH1 0xdd (address) PUSH1 0x00 (gas) CALL POP, twice
+        let call = "6000600060006000600060dd6000f150".repeat(2);
+        assert_eq!(gas_used(call, &mut ext), 7 * 3 + 2600 + 2 + 7 * 3 + 100 + 2);
+
+        // before Berlin the price does not depend on the access list
+        let mut ext = FakeExt::new_istanbul();
+        ext.reset_access_list_for_tx(sender, to, &precompiles);
+        assert_eq!(gas_used(twice, &mut ext), 2 * (3 + 700 + 2));
+    }
+
+    #[test]
+    fn basefee_works() {
+        // BASEFEE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code = "4860005260206000f3";
+
+        let mut ext = FakeExt::new_london();
+        ext.info.base_fee = Some(U256::from(7));
+        assert_eq!(run_returning_word(code, &mut ext).unwrap(), U256::from(7));
+
+        let mut ext = FakeExt::new_istanbul();
+        ext.info.base_fee = Some(U256::from(7));
+        assert!(matches!(run_returning_word(code, &mut ext), Err(Error::InvalidCommand)));
+    }
+
+    #[test]
+    fn gas_works() {
+        // GAS, charged 2 before pushing what is left
+        let params = ActionParamsBuilder::new().gas(100).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new("5a".from_hex().unwrap(), params);
+        interpreter.exec(&mut FakeExt::new_istanbul()).unwrap();
+        assert_eq!(interpreter.stack.pop(), U256::from(98));
+    }
+
+    #[test]
+    fn pc_works() {
+        // PUSH1 0x01 PUSH1 0x01 PC
+        assert_eq!(run_top_of_stack("6001600158"), U256::from(4));
+        // PUSH1 0x04 JUMP INVALID JUMPDEST PC
+        assert_eq!(run_top_of_stack("600456fe5b58"), U256::from(5));
+    }
+
+    #[test]
+    fn msize_works() {
+        // MSIZE
+        assert_eq!(run_top_of_stack("59"), U256::zero());
+        // PUSH1 0x01 PUSH1 0x00 MSTORE MSIZE
+        assert_eq!(run_top_of_stack("600160005259"), U256::from(32));
+    }
+
+    #[test]
+    fn env_info_works() {
+        let author = Address::from_low_u64_be(0xc0ffee);
+        let mut ext = FakeExt::new_london().with_chain_id(5);
+        ext.info.author = author;
+        ext.info.timestamp = 1_700_000_000;
+        ext.info.number = 42;
+        ext.info.difficulty = U256::from(131072);
+        ext.info.gas_limit = U256::from(30_000_000);
+        ext.info.base_fee = Some(U256::from(7));
+
+        // OPCODE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        for (opcode, expected) in [
+            ("41", U256::from(author.as_bytes())),
+            ("42", U256::from(1_700_000_000u64)),
+            ("43", U256::from(42)),
+            ("44", U256::from(131072)),
+            ("45", U256::from(30_000_000)),
+            ("46", U256::from(5)),
+            ("48", U256::from(7)),
+        ] {
+            let code = format!("{}60005260206000f3", opcode);
+            assert_eq!(run_returning_word(&code, &mut ext).unwrap(), expected, "opcode: {}", opcode);
+        }
+
+        let mut ext = FakeExt::new().with_chain_id(5);
+        assert!(matches!(run_returning_word("4660005260206000f3", &mut ext), Err(Error::InvalidCommand)));
+    }
+
+    #[test]
+    fn jump_works() {
+        // PUSH1 0x06 JUMP PUSH1 0x01 STOP JUMPDEST
+        // PUSH1 0x2a PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code = "6006566001005b602a60005260206000f3";
+        assert_eq!(run_returning_word(code, &mut FakeExt::new()).unwrap(), U256::from(0x2a));
+
+        // jumping onto the PUSH1 after the JUMP, which is not a JUMPDEST
+        let code = "6003566001005b602a60005260206000f3";
+        assert!(matches!(run_returning_word(code, &mut FakeExt::new()), Err(Error::InvalidJump)));
+    }
+
+    #[test]
+    fn jump_into_push_data_works() {
+        // PUSH1 0x04 JUMP PUSH1 0x5b STOP, the 0x5b at 4 is push data and not a JUMPDEST
+        let code = "600456605b00";
+        assert!(matches!(run_returning_word(code, &mut FakeExt::new()), Err(Error::InvalidJump)));
+
+        // jumping past the end of the code
+        let code = "60ff56";
+        assert!(matches!(run_returning_word(code, &mut FakeExt::new()), Err(Error::InvalidJump)));
+
+        // PUSH1 0x00 NOT JUMP, a destination too large for `usize`
+        let code = "60001956";
+        assert!(matches!(run_returning_word(code, &mut FakeExt::new()), Err(Error::InvalidJump)));
+        // PUSH1 0x01 PUSH1 0x00 NOT JUMPI
+        let code = "600160001957";
+        assert!(matches!(run_returning_word(code, &mut FakeExt::new()), Err(Error::InvalidJump)));
+    }
+
+    #[test]
+    fn jumpi_not_taken_works() {
+        // PUSH1 0x00 PUSH1 0x0f JUMPI PUSH1 0x42 PUSH1 0x00 MSTORE
+        // PUSH1 0x20 PUSH1 0x00 RETURN JUMPDEST
+        let code = "6000600f57604260005260206000f35b";
+        assert_eq!(run_returning_word(code, &mut FakeExt::new()).unwrap(), U256::from(0x42));
+    }
+
+    #[test]
+    fn call_depth_works() {
+        // PUSH1 0x00 (x4) PUSH1 0x00 (value) PUSH1 0xaa (address) PUSH1 0x10 (gas) CALL
+        // PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let call = "6000600060006000600060aa6010f160005260206000f3";
+        // PUSH1 0x00 PUSH1 0x00 PUSH1 0x00 CREATE PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let create = "600060006000f060005260206000f3";
+
+        let run = |code: &str, depth: usize, ext: &mut FakeExt| {
+            let params = ActionParamsBuilder::new().gas(100_000).depth(depth).build::<usize>().unwrap();
+            let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code.from_hex().unwrap(), params);
+            match interpreter.exec(ext).unwrap() {
+                GasLeft::NeedsReturn { data, .. } => U256::from(&data[..]),
+                _ => panic!("expected NeedsReturn"),
+            }
+        };
+
+        let mut ext = FakeExt::new_istanbul();
+        assert_eq!(run(call, 1023, &mut ext), U256::one());
+        assert_eq!(ext.calls.len(), 1);
+
+        // at the limit neither reaches the externalities
+        let mut ext = FakeExt::new_istanbul();
+        assert_eq!(run(call, 1024, &mut ext), U256::zero());
+        assert_eq!(run(create, 1024, &mut ext), U256::zero());
+        assert!(ext.calls.is_empty());
+    }
+
+    #[test]
+    fn jump_table_dispatch_works() {
+        // sums 1..=255 in a JUMPI loop and returns the sum
+        let code = "600060ff5b8091019060019003806004575b5060005260206000f3".from_hex::<Vec<u8>>().unwrap();
+        let params = ActionParamsBuilder::new().gas(1_000_000).build::<usize>().unwrap();
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, params);
+        match interpreter.exec(&mut FakeExt::new()).unwrap() {
+            GasLeft::NeedsReturn { data, gas_left, .. } => {
+                assert_eq!(U256::from(&data[..]), U256::from(32640));
+                // two pushes, 255 rounds of JUMPDEST and the loop body, then the last JUMPDEST,
+                // storing and returning the sum
+                assert_eq!(gas_left, U256::from(1_000_000 - (3 + 3 + 255 * (1 + 9 * 3 + 10) + 1 + 2 + 3 + 6 + 3 + 3)));
+            }
+            _ => panic!("expected NeedsReturn"),
+        }
     }
 }
@@ -4,10 +4,14 @@ mod gas;
 mod instructions;
 mod interpreter;
 mod memory;
+mod precompile;
 mod stack;
 mod types;
 mod cache;
 
+#[cfg(test)]
+mod gas_regression;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -25,6 +25,8 @@ pub trait Memory {
     fn read_slice(&self, offset: U256, size: U256) -> &[u8];
     /// Retrieve writeable part of memory
     fn writeable_slice(&mut self, offset: U256, size: U256) -> &mut [u8];
+    /// Copy `len` bytes from `src` to `dst`, the regions may overlap. Does not resize memory!
+    fn copy_within(&mut self, dst: U256, src: U256, len: U256);
     /// Convert memory into return data.
     fn into_return_data(self, offset: U256, size: U256) -> ReturnData;
 }
@@ -97,6 +99,14 @@ impl Memory for Vec<u8> {
         }
     }
 
+    fn copy_within(&mut self, dst: U256, src: U256, len: U256) {
+        let len = len.low_u64() as usize;
+        if len > 0 {
+            let src = src.low_u64() as usize;
+            <[u8]>::copy_within(self, src..src + len, dst.low_u64() as usize);
+        }
+    }
+
     fn into_return_data(mut self, offset: U256, size: U256) -> ReturnData {
         let mut off = offset.low_u64() as usize;
         let len = size.low_u64() as usize;
@@ -186,4 +196,23 @@ mod tests {
             assert_eq!(mem.size(), 32);
         }
     }
+
+    #[test]
+    fn copy_within_works() {
+        let mem: &mut dyn Memory = &mut vec![];
+        mem.resize(8);
+        mem.write_slice(U256::zero(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // forward overlap
+        mem.copy_within(U256::from(2), U256::zero(), U256::from(4));
+        assert_eq!(mem.read_slice(U256::zero(), U256::from(8)), &[1, 2, 1, 2, 3, 4, 7, 8]);
+
+        // backward overlap
+        mem.copy_within(U256::zero(), U256::from(3), U256::from(5));
+        assert_eq!(mem.read_slice(U256::zero(), U256::from(8)), &[2, 3, 4, 7, 8, 4, 7, 8]);
+
+        // zero length is a no-op even out of bounds
+        mem.copy_within(U256::from(0x1000), U256::from(0x2000), U256::zero());
+        assert_eq!(mem.size(), 8);
+    }
 }
@@ -0,0 +1,253 @@
+use crate::error::Error;
+use crate::types::Schedule;
+use common::{public_to_address, recover, ripemd160, sha256, Address, H256, H520};
+use ethjson::spec::Pricing;
+use std::collections::BTreeMap;
+
+const WORD_BYTES_SIZE: u64 = 32;
+const ECRECOVER_INPUT_SIZE: usize = 128;
+
+/// Linear builtin pricing, `base + word * ceil(len / 32)`, as in the builtin spec
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Linear {
+    pub base: u64,
+    pub word: u64,
+}
+
+impl Linear {
+    pub const fn new(base: u64, word: u64) -> Self {
+        Self { base, word }
+    }
+
+    /// The gas charged for an input of `len` bytes
+    pub fn cost(&self, len: usize) -> u64 {
+        let words = (len as u64).div_ceil(WORD_BYTES_SIZE);
+        self.base.saturating_add(self.word.saturating_mul(words))
+    }
+
+    /// Charge the cost of the input against `gas`, returning the gas left
+    pub fn charge(&self, len: usize, gas: u64) -> Result<u64, Error> {
+        gas.checked_sub(self.cost(len)).ok_or(Error::OutOfGas)
+    }
+}
+
+/// A precompile taking the input, the gas provided and its price, returning the output
+/// and the gas left
+pub type PrecompileFn = fn(&[u8], u64, &Linear) -> Result<(Vec<u8>, u64), Error>;
+
+/// A precompile with its prices by the block they activate at, as in the builtin spec
+#[derive(Debug, Clone)]
+pub struct Builtin {
+    precompile: PrecompileFn,
+    pricing: BTreeMap<u64, Linear>,
+}
+
+impl Builtin {
+    /// The builtin named in the spec, if it is implemented. Only linear pricing is read,
+    /// the precompiles priced otherwise are not implemented.
+    pub fn from_spec(builtin: &ethjson::spec::Builtin) -> Option<Self> {
+        let precompile: PrecompileFn = match builtin.name.as_str() {
+            "ecrecover" => ecrecover,
+            "sha256" => sha256_precompile,
+            "ripemd160" => ripemd160_precompile,
+            "identity" => identity,
+            _ => return None,
+        };
+        let pricing = builtin
+            .pricing
+            .iter()
+            .filter_map(|(block, pricing)| match &pricing.price {
+                Pricing::Linear(linear) => Some((*block, Linear::new(linear.base, linear.word))),
+                _ => None,
+            })
+            .collect();
+        Some(Self { precompile, pricing })
+    }
+
+    /// The price at `block`, none before the builtin activates
+    pub fn price_at(&self, block: u64) -> Option<Linear> {
+        self.pricing.range(..=block).next_back().map(|(_, price)| *price)
+    }
+}
+
+/// The precompile at `address` and its price, if it is active at `block` under the schedule
+pub fn precompiles(address: &Address, schedule: &Schedule, block: u64) -> Option<(PrecompileFn, Linear)> {
+    let builtin = schedule.builtins.get(address)?;
+    Some((builtin.precompile, builtin.price_at(block)?))
+}
+
+/// Addresses of the precompiles active at `block` under the schedule
+pub fn precompile_addresses(schedule: &Schedule, block: u64) -> Vec<Address> {
+    schedule
+        .builtins
+        .keys()
+        .filter(|address| precompiles(address, schedule, block).is_some())
+        .cloned()
+        .collect()
+}
+
+/// The ecrecover precompile at address 0x01.
+/// Returns the output and the gas left; malformed input gives an empty output.
+pub fn ecrecover(input: &[u8], gas: u64, price: &Linear) -> Result<(Vec<u8>, u64), Error> {
+    let gas_left = price.charge(input.len(), gas)?;
+
+    // input is [hash, v, r, s], zero padded to 128 bytes
+    let mut data = [0u8; ECRECOVER_INPUT_SIZE];
+    let len = input.len().min(ECRECOVER_INPUT_SIZE);
+    data[..len].copy_from_slice(&input[..len]);
+
+    let hash = H256::from_slice(&data[0..32]);
+    let v = &data[32..64];
+    if v[..31].iter().any(|b| *b != 0) || !(v[31] == 27 || v[31] == 28) {
+        return Ok((Vec::new(), gas_left));
+    }
+
+    let mut signature = [0u8; 65];
+    signature[..64].copy_from_slice(&data[64..128]);
+    signature[64] = v[31] - 27;
+
+    match recover(&H520::from(signature), &hash) {
+        Ok(public) => {
+            let mut output = vec![0u8; 32];
+            output[12..].copy_from_slice(public_to_address(&public).as_bytes());
+            Ok((output, gas_left))
+        }
+        Err(_) => Ok((Vec::new(), gas_left)),
+    }
+}
+
+/// The sha256 precompile at address 0x02
+pub fn sha256_precompile(input: &[u8], gas: u64, price: &Linear) -> Result<(Vec<u8>, u64), Error> {
+    let gas_left = price.charge(input.len(), gas)?;
+    Ok((sha256(input).as_bytes().to_vec(), gas_left))
+}
+
+/// The ripemd160 precompile at address 0x03, the hash is left padded to 32 bytes
+pub fn ripemd160_precompile(input: &[u8], gas: u64, price: &Linear) -> Result<(Vec<u8>, u64), Error> {
+    let gas_left = price.charge(input.len(), gas)?;
+    let mut output = vec![0u8; 32];
+    output[12..].copy_from_slice(ripemd160(input).as_bytes());
+    Ok((output, gas_left))
+}
+
+/// The identity precompile at address 0x04, returns the input as is
+pub fn identity(input: &[u8], gas: u64, price: &Linear) -> Result<(Vec<u8>, u64), Error> {
+    let gas_left = price.charge(input.len(), gas)?;
+    Ok((input.to_vec(), gas_left))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::precompile::{ecrecover, precompile_addresses, precompiles, Builtin, Linear};
+    use crate::types::{builtins, Schedule};
+    use common::Address;
+    use rustc_hex::FromHex;
+
+    const INPUT: &str = "38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e000000000000000000000000000000000000000000000000000000000000001b38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02";
+
+    fn schedule() -> Schedule {
+        let mut schedule = Schedule::new_london();
+        schedule.set_builtins(&builtins());
+        schedule
+    }
+
+    fn run(address: u64, input: &[u8], gas: u64) -> (Vec<u8>, u64) {
+        let (f, price) = precompiles(&Address::from_low_u64_be(address), &schedule(), 10).unwrap();
+        f(input, gas, &price).unwrap()
+    }
+
+    #[test]
+    fn linear_cost_works() {
+        let price = Linear::new(15, 3);
+        assert_eq!(price.cost(0), 15);
+        assert_eq!(price.cost(1), 18);
+        assert_eq!(price.cost(32), 18);
+        assert_eq!(price.cost(33), 21);
+    }
+
+    #[test]
+    fn ecrecover_works() {
+        let input: Vec<u8> = "47173285a8d7341e5e972fc677286384f802f8ef42a5ec5f03bbfa254cb01fad000000000000000000000000000000000000000000000000000000000000001b650acf9d3f5f0a2c799776a1254355d5f4061762a237396a99a0e0e3fc2bcd6729514a0dacb2e623ac4abd157cb18163ff942280db4d5caad66ddf941ba12e03".from_hex().unwrap();
+        let expected: Vec<u8> = "000000000000000000000000c08b5542d177ac6686946920409741463a15dddb"
+            .from_hex()
+            .unwrap();
+        let (output, gas_left) = ecrecover(&input, 5000, &Linear::new(3000, 0)).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(gas_left, 2000);
+
+        // v out of range gives empty output
+        let mut bad_v = input.clone();
+        bad_v[63] = 29;
+        assert_eq!(ecrecover(&bad_v, 5000, &Linear::new(3000, 0)).unwrap(), (vec![], 2000));
+
+        // short input is zero padded and fails to recover
+        assert_eq!(ecrecover(&input[..64], 3000, &Linear::new(3000, 0)).unwrap(), (vec![], 0));
+
+        assert!(matches!(ecrecover(&input, 2999, &Linear::new(3000, 0)), Err(Error::OutOfGas)));
+    }
+
+    #[test]
+    fn sha256_works() {
+        let input: Vec<u8> = INPUT.from_hex().unwrap();
+        let expected: Vec<u8> = "811c7003375852fabd0d362e40e68607a12bdabae61a7d068fe5fdd1dbbf2a5d"
+            .from_hex()
+            .unwrap();
+        let (output, gas_left) = run(2, &input, 1000);
+        assert_eq!(output, expected);
+        assert_eq!(1000 - gas_left, Linear::new(60, 12).cost(input.len()));
+        assert_eq!(1000 - gas_left, 60 + 12 * 4);
+    }
+
+    #[test]
+    fn ripemd160_works() {
+        let input: Vec<u8> = INPUT.from_hex().unwrap();
+        let expected: Vec<u8> = "0000000000000000000000009215b8d9882ff46f0dfde6684d78e831467f65e6"
+            .from_hex()
+            .unwrap();
+        let (output, gas_left) = run(3, &input, 2000);
+        assert_eq!(output, expected);
+        assert_eq!(2000 - gas_left, Linear::new(600, 120).cost(input.len()));
+        assert_eq!(2000 - gas_left, 600 + 120 * 4);
+    }
+
+    #[test]
+    fn identity_works() {
+        let input = vec![7u8; 33];
+        let (output, gas_left) = run(4, &input, 100);
+        assert_eq!(output, input);
+        assert_eq!(100 - gas_left, Linear::new(15, 3).cost(input.len()));
+        assert_eq!(100 - gas_left, 15 + 3 * 2);
+    }
+
+    #[test]
+    fn precompiles_works() {
+        let schedule = schedule();
+        assert!(precompiles(&Address::from_low_u64_be(0), &schedule, 10).is_none());
+        // modexp is in the spec but not implemented
+        assert!(precompiles(&Address::from_low_u64_be(5), &schedule, 10).is_none());
+        assert!(precompiles(&Address::from_low_u64_be(0x0101), &schedule, 10).is_none());
+        assert_eq!(run(1, &[], 3000), (vec![], 0));
+
+        // ripemd160 activates at block 10
+        assert!(precompiles(&Address::from_low_u64_be(3), &schedule, 9).is_none());
+        assert_eq!(precompile_addresses(&schedule, 9).len(), 3);
+        assert_eq!(precompile_addresses(&schedule, 10).len(), 4);
+
+        // without builtins in the spec no precompile is active
+        assert!(precompile_addresses(&Schedule::new_london(), 10).is_empty());
+    }
+
+    #[test]
+    fn builtin_price_at_works() {
+        let spec: ethjson::spec::Builtin = serde_json::from_str::<ethjson::spec::builtin::BuiltinCompat>(
+            r#"{ "name": "identity", "pricing": { "0": { "price": { "linear": { "base": 15, "word": 3 } } }, "100": { "price": { "linear": { "base": 10, "word": 1 } } } } }"#,
+        )
+        .unwrap()
+        .into();
+        let builtin = Builtin::from_spec(&spec).unwrap();
+        assert_eq!(builtin.price_at(0), Some(Linear::new(15, 3)));
+        assert_eq!(builtin.price_at(99), Some(Linear::new(15, 3)));
+        assert_eq!(builtin.price_at(100), Some(Linear::new(10, 1)));
+    }
+}
@@ -1,7 +1,9 @@
 //! Evm input params.
-use common::{Address, H256, KECCAK_EMPTY, U256};
+use common::{keccak, Address, H256, KECCAK_EMPTY, U256};
 
 use std::sync::Arc;
+use crate::cost::CostType;
+use crate::error::Error;
 use crate::types::access_list::AccessList;
 use crate::types::Bytes;
 use crate::types::call_type::CallType;
@@ -74,6 +76,8 @@ pub struct ActionParams {
     pub params_type: ParamsType,
     /// Current access list
     pub access_list: AccessList,
+    /// Number of frames above this one, 0 for the transaction itself
+    pub depth: usize,
 }
 
 impl Default for ActionParams {
@@ -93,10 +97,110 @@ impl Default for ActionParams {
             call_type: CallType::None,
             params_type: ParamsType::Separate,
             access_list: AccessList::default(),
+            depth: 0,
         }
     }
 }
 
+/// Chainable construction of `ActionParams`, validated on `build`
+#[derive(Default)]
+pub struct ActionParamsBuilder {
+    params: ActionParams,
+}
+
+impl ActionParamsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn code_address(mut self, code_address: Address) -> Self {
+        self.params.code_address = code_address;
+        self
+    }
+
+    /// Sets the code and its hash
+    pub fn code(mut self, code: Bytes) -> Self {
+        self.params.code_hash = Some(keccak(&code));
+        self.params.code = Some(Arc::new(code));
+        self
+    }
+
+    pub fn address(mut self, address: Address) -> Self {
+        self.params.address = address;
+        self
+    }
+
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.params.sender = sender;
+        self
+    }
+
+    pub fn origin(mut self, origin: Address) -> Self {
+        self.params.origin = origin;
+        self
+    }
+
+    pub fn gas<T: Into<U256>>(mut self, gas: T) -> Self {
+        self.params.gas = gas.into();
+        self
+    }
+
+    pub fn gas_price<T: Into<U256>>(mut self, gas_price: T) -> Self {
+        self.params.gas_price = gas_price.into();
+        self
+    }
+
+    pub fn value(mut self, value: ActionValue) -> Self {
+        self.params.value = value;
+        self
+    }
+
+    pub fn data(mut self, data: Bytes) -> Self {
+        self.params.data = Some(data);
+        self
+    }
+
+    pub fn call_type(mut self, call_type: CallType) -> Self {
+        self.params.call_type = call_type;
+        self
+    }
+
+    pub fn params_type(mut self, params_type: ParamsType) -> Self {
+        self.params.params_type = params_type;
+        self
+    }
+
+    pub fn access_list(mut self, access_list: AccessList) -> Self {
+        self.params.access_list = access_list;
+        self
+    }
+
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.params.depth = depth;
+        self
+    }
+
+    /// Validates the params against the gas type `G` the interpreter will run with.
+    /// `DELEGATECALL` only carries an apparent value, every other call type transfers,
+    /// and `STATICCALL` cannot transfer a non-zero value.
+    pub fn build<G: CostType>(self) -> Result<ActionParams, Error> {
+        G::from_u256(self.params.gas)?;
+
+        let value_consistent = match (&self.params.call_type, &self.params.value) {
+            (CallType::DelegateCall, ActionValue::Apparent(_)) => true,
+            (CallType::DelegateCall, ActionValue::Transfer(_)) => false,
+            (_, ActionValue::Apparent(_)) => false,
+            (CallType::StaticCall, ActionValue::Transfer(v)) => v.is_zero(),
+            (_, ActionValue::Transfer(_)) => true,
+        };
+        if !value_consistent {
+            return Err(Error::InvalidActionParams);
+        }
+
+        Ok(self.params)
+    }
+}
+
 // impl From<ethjson::vm::Transaction> for ActionParams {
 //     fn from(t: ethjson::vm::Transaction) -> Self {
 //         let address: Address = t.address.into();
@@ -120,3 +224,57 @@ impl Default for ActionParams {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::interpreter::Interpreter;
+    use crate::types::{ActionParamsBuilder, ActionValue, CallType, Exec, FakeExt, GasLeft};
+    use common::{Address, U256};
+    use rustc_hex::FromHex;
+
+    #[test]
+    fn builder_works() {
+        // PUSH1 0x01 PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let code: Vec<u8> = "600160005260206000f3".from_hex().unwrap();
+        let params = ActionParamsBuilder::new()
+            .code(code.clone())
+            .sender(Address::random())
+            .gas(100)
+            .value(ActionValue::transfer(5))
+            .call_type(CallType::Call)
+            .build::<usize>()
+            .unwrap();
+        assert_eq!(params.gas, U256::from(100));
+
+        let mut interpreter = Interpreter::<Vec<u8>, usize>::new(code, params);
+        match interpreter.exec(&mut FakeExt::new()).unwrap() {
+            GasLeft::NeedsReturn { data, .. } => assert_eq!(U256::from(&data[..]), U256::one()),
+            _ => panic!("expected NeedsReturn"),
+        }
+    }
+
+    #[test]
+    fn builder_validation_works() {
+        let r = ActionParamsBuilder::new().gas(U256::MAX).build::<usize>();
+        assert!(matches!(r, Err(Error::OutOfGas)));
+
+        let r = ActionParamsBuilder::new()
+            .call_type(CallType::DelegateCall)
+            .value(ActionValue::transfer(1))
+            .build::<usize>();
+        assert!(matches!(r, Err(Error::InvalidActionParams)));
+
+        let r = ActionParamsBuilder::new()
+            .call_type(CallType::StaticCall)
+            .value(ActionValue::transfer(1))
+            .build::<usize>();
+        assert!(matches!(r, Err(Error::InvalidActionParams)));
+
+        let r = ActionParamsBuilder::new()
+            .call_type(CallType::DelegateCall)
+            .value(ActionValue::apparent(1))
+            .build::<usize>();
+        assert!(r.is_ok());
+    }
+}
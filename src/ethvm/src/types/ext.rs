@@ -103,6 +103,9 @@ pub trait Ext {
         trap: bool,
     ) -> Result<MessageCallResult, Error>;
 
+    /// Moves `value` between the accounts, for a call run in place rather than through `call`
+    fn transfer(&mut self, from: &Address, to: &Address, value: &U256) -> Result<(), Error>;
+
     /// Returns code at given address
     fn extcode(&self, address: &Address) -> Result<Option<Arc<Bytes>>, Error>;
 
@@ -184,4 +187,8 @@ pub trait Ext {
 
     /// Inserts an address into the list
     fn al_insert_address(&mut self, address: Address);
+
+    /// Starts the access list of a new transaction. Under EIP-2929 the sender, the
+    /// recipient and the precompiles are warm from their first access.
+    fn reset_access_list_for_tx(&mut self, sender: Address, to: Address, precompiles: &[Address]);
 }
@@ -1,3 +1,9 @@
+use crate::error::Error;
+use crate::instructions::Instruction;
+use crate::precompile::Builtin;
+use common::Address;
+use std::collections::BTreeMap;
+
 /// Definition of the cost schedule and other parameterizations for the EVM.
 #[derive(Debug, Default)]
 pub struct Schedule {
@@ -8,23 +14,217 @@ pub struct Schedule {
     pub memory_gas: usize,
     /// Coefficient used to convert memory size to gas price for memory
     pub quad_coeff_div: usize,
-    /// Gas prices for instructions in all tiers
-    pub tier_step_gas: [usize; 8],
+    /// Gas prices for instructions in all tiers, indexed by `GasPriceTier::idx`
+    pub tier_step_gas: Vec<usize>,
     /// TODO: read up on https://github.com/ethereum/EIPs/blob/master/EIPS/eip-1283.md
     pub eip1283: bool,
     /// Gas refund for `SSTORE` clearing (when `storage!=0`, `new==0`)
     pub sstore_refund_gas: usize,
+    /// The refund of a transaction is capped at the gas used divided by this
+    pub max_refund_quotient: usize,
+    /// Gas of an `SSTORE` setting a zero slot to non-zero
+    pub sstore_set_gas: usize,
+    /// Gas of an `SSTORE` changing a non-zero slot
+    pub sstore_reset_gas: usize,
+    /// Gas of an `SLOAD`, also what a no-op `SSTORE` costs under EIP-1283
+    pub sload_gas: usize,
+    /// Under EIP-2200 an `SSTORE` fails unless more gas than this is left
+    pub sstore_sentry_gas: usize,
+    /// Enables the `SELFBALANCE` opcode (EIP-1884, Istanbul)
+    pub have_selfbalance: bool,
+    /// Enables the `BASEFEE` opcode (EIP-3198, London)
+    pub eip3198: bool,
+    /// Gas per word copied, for copy operations
+    pub copy_gas: usize,
+    /// Enables the `MCOPY` opcode (EIP-5656, Cancun)
+    pub eip5656: bool,
+    /// Max depth of nested `CALL`/`CREATE` frames
+    pub max_depth: usize,
+    /// Gas of a `JUMPDEST`
+    pub jumpdest_gas: usize,
+    /// Base gas of a `CALL`
+    pub call_gas: usize,
+    /// Additional gas of a `CALL` transferring value to an empty account (EIP-161)
+    pub call_new_account_gas: usize,
+    /// Additional gas of a `CALL` transferring value
+    pub call_value_transfer_gas: usize,
+    /// Free gas given to the callee of a `CALL` transferring value
+    pub call_stipend: usize,
+    /// Base gas of a `CREATE`
+    pub create_gas: usize,
+    /// Base gas of an `EXP`
+    pub exp_gas: usize,
+    /// Additional gas of an `EXP` per byte of the exponent
+    pub exp_byte_gas: usize,
+    /// Base gas of a `LOG*`
+    pub log_gas: usize,
+    /// Additional gas of a `LOG*` per topic
+    pub log_topic_gas: usize,
+    /// Additional gas of a `LOG*` per byte of data
+    pub log_data_gas: usize,
+    /// Enables the `EXTCODEHASH` opcode (EIP-1052, Constantinople)
+    pub have_extcodehash: bool,
+    /// Gas of an `EXTCODEHASH`
+    pub extcodehash_gas: usize,
+    /// Enables the `CHAINID` opcode (EIP-1344, Istanbul)
+    pub have_chain_id: bool,
+    /// Enables the `RETURNDATASIZE` and `RETURNDATACOPY` opcodes (EIP-211, Byzantium)
+    pub have_return_data: bool,
+    /// Gas of a `BALANCE` before EIP-2929
+    pub balance_gas: usize,
+    /// Prices account and storage accesses by whether they are in the access list (EIP-2929, Berlin).
+    /// Applies to `BALANCE`, `EXTCODEHASH`, `SLOAD`, `SSTORE` and `CALL`, the other opcodes
+    /// accessing accounts are not implemented yet.
+    pub eip2929: bool,
+    /// Gas of the first access to an account in a transaction
+    pub cold_account_access_cost: usize,
+    /// Gas of the first `SLOAD` of a slot in a transaction
+    pub cold_sload_cost: usize,
+    /// Gas of any later access to an account or a slot
+    pub warm_storage_read_cost: usize,
+    /// Precompiles by their address, read from the builtins of the chain spec
+    pub builtins: BTreeMap<Address, Builtin>,
 }
 
 impl Schedule {
     fn new() -> Schedule {
         Schedule {
-            tier_step_gas: [0, 2, 3, 5, 8, 10, 20, 0],
+            tier_step_gas: vec![0, 2, 3, 5, 8, 10, 20, 0],
             memory_gas: 3,
             quad_coeff_div: 512,
             sub_gas_cap_divisor: None,
             eip1283: false,
-            sstore_refund_gas: 15000
+            sstore_refund_gas: 15000,
+            max_refund_quotient: 2,
+            sstore_set_gas: 20000,
+            sstore_reset_gas: 5000,
+            sload_gas: 200,
+            sstore_sentry_gas: 2300,
+            have_selfbalance: false,
+            eip3198: false,
+            copy_gas: 3,
+            eip5656: false,
+            max_depth: 1024,
+            jumpdest_gas: 1,
+            call_gas: 700,
+            call_new_account_gas: 25000,
+            call_value_transfer_gas: 9000,
+            call_stipend: 2300,
+            create_gas: 32000,
+            exp_gas: 10,
+            exp_byte_gas: 50,
+            log_gas: 375,
+            log_topic_gas: 375,
+            log_data_gas: 8,
+            have_extcodehash: false,
+            extcodehash_gas: 700,
+            have_chain_id: false,
+            have_return_data: false,
+            balance_gas: 400,
+            eip2929: false,
+            cold_account_access_cost: 2600,
+            cold_sload_cost: 2100,
+            warm_storage_read_cost: 100,
+            builtins: BTreeMap::new(),
+        }
+    }
+
+    /// Schedule for the Istanbul fork
+    pub fn new_istanbul() -> Schedule {
+        let mut schedule = Self::new();
+        schedule.have_selfbalance = true;
+        schedule.have_extcodehash = true;
+        schedule.have_chain_id = true;
+        schedule.have_return_data = true;
+        // all but one 64th of the gas left is forwarded (EIP-150)
+        schedule.sub_gas_cap_divisor = Some(64);
+        // net gas metering for SSTORE (EIP-2200)
+        schedule.eip1283 = true;
+        schedule.sload_gas = 800;
+        schedule.balance_gas = 700;
+        schedule
+    }
+
+    /// Schedule for the Berlin fork
+    pub fn new_berlin() -> Schedule {
+        let mut schedule = Self::new_istanbul();
+        schedule.eip2929 = true;
+        // the cold surcharge is paid on top, a warm read prices the writes not paying for one
+        schedule.sload_gas = schedule.warm_storage_read_cost;
+        schedule.sstore_reset_gas -= schedule.cold_sload_cost;
+        schedule
+    }
+
+    /// Schedule for the London fork
+    pub fn new_london() -> Schedule {
+        let mut schedule = Self::new_berlin();
+        schedule.eip3198 = true;
+        // smaller refunds (EIP-3529), clearing refunds the reset gas and a storage key
+        // access list entry, 2900 + 1900
+        schedule.sstore_refund_gas = 4800;
+        schedule.max_refund_quotient = 5;
+        schedule
+    }
+
+    /// Schedule for the Cancun fork
+    pub fn new_cancun() -> Schedule {
+        let mut schedule = Self::new_london();
+        schedule.eip5656 = true;
+        schedule
+    }
+}
+
+impl Schedule {
+    /// Sets the precompiles from the builtins of a chain spec, skipping the ones not implemented
+    pub fn set_builtins(&mut self, builtins: &BTreeMap<ethjson::hash::Address, ethjson::spec::Builtin>) {
+        self.builtins = builtins
+            .iter()
+            .filter_map(|(address, builtin)| Some((address.0, Builtin::from_spec(builtin)?)))
+            .collect();
+    }
+
+    /// The most a transaction using `gas_used` gets refunded
+    pub fn max_refund(&self, gas_used: usize) -> usize {
+        gas_used / self.max_refund_quotient
+    }
+
+    /// Checks `tier_step_gas` has a price for every tier used by the instruction table.
+    /// Should be called once before the schedule is used for execution.
+    pub fn validate(&self) -> Result<(), Error> {
+        let all_priced = (0..=u8::MAX)
+            .filter_map(Instruction::from_u8)
+            .all(|i| i.info().tier.idx() < self.tier_step_gas.len());
+        if all_priced {
+            Ok(())
+        } else {
+            Err(Error::InvalidSchedule)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::types::Schedule;
+
+    #[test]
+    fn validate_works() {
+        assert!(Schedule::new().validate().is_ok());
+
+        let mut schedule = Schedule::new();
+        schedule.tier_step_gas = vec![0, 2, 3];
+        assert!(matches!(schedule.validate(), Err(Error::InvalidSchedule)));
+    }
+
+    #[test]
+    fn london_refund_works() {
+        let berlin = Schedule::new_berlin();
+        assert_eq!(berlin.sstore_refund_gas, 15000);
+        assert_eq!(berlin.max_refund(100_000), 50_000);
+
+        let london = Schedule::new_london();
+        assert_eq!(london.sstore_refund_gas, london.sstore_reset_gas + 1900);
+        assert_eq!(london.max_refund(100_000), 20_000);
+        assert_eq!(Schedule::new_cancun().max_refund(100_000), 20_000);
+    }
+}
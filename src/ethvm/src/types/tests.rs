@@ -6,7 +6,7 @@ use crate::types::{
     ReturnData, Schedule,
 };
 use common::{keccak, Address, H256, U256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 pub struct FakeLogEntry {
@@ -52,10 +52,28 @@ pub struct FakeExt {
     pub tracing: bool,
     pub is_static: bool,
     pub access_list: AccessList,
+    /// Output of every successful call
+    pub call_output: Bytes,
+    /// Address of every successful create, creation fails if not set
+    pub created_address: Option<Address>,
 
     chain_id: u64,
 }
 
+/// Builtins of the frontier chain spec, ripemd160 only activates at block 10
+const BUILTINS: &str = r#"{
+    "0x0000000000000000000000000000000000000001": { "builtin": { "name": "ecrecover", "pricing": { "linear": { "base": 3000, "word": 0 } } } },
+    "0x0000000000000000000000000000000000000002": { "builtin": { "name": "sha256", "pricing": { "linear": { "base": 60, "word": 12 } } } },
+    "0x0000000000000000000000000000000000000003": { "builtin": { "name": "ripemd160", "activate_at": 10, "pricing": { "linear": { "base": 600, "word": 120 } } } },
+    "0x0000000000000000000000000000000000000004": { "builtin": { "name": "identity", "pricing": { "linear": { "base": 15, "word": 3 } } } },
+    "0x0000000000000000000000000000000000000005": { "builtin": { "name": "modexp", "pricing": { "modexp": { "divisor": 20 } } } }
+}"#;
+
+/// The builtins of a test chain spec
+pub fn builtins() -> BTreeMap<ethjson::hash::Address, ethjson::spec::Builtin> {
+    serde_json::from_str::<ethjson::spec::State>(BUILTINS).unwrap().builtins()
+}
+
 // similar to the normal `finalize` function, but ignoring NeedsReturn.
 pub fn test_finalize(res: Result<GasLeft, Error>) -> Result<U256, Error> {
     match res {
@@ -69,8 +87,10 @@ impl FakeExt {
     /// New fake externalities
     pub fn new() -> Self {
         let mut e = FakeExt::default();
-        e.schedule.tier_step_gas = [0, 2, 3, 5, 8, 10, 20, 0];
+        e.schedule.tier_step_gas = vec![0, 2, 3, 5, 8, 10, 20, 0];
         e.schedule.memory_gas = 3;
+        e.schedule.quad_coeff_div = 512;
+        e.schedule.jumpdest_gas = 1;
         e
     }
 
@@ -88,32 +108,35 @@ impl FakeExt {
     //     ext
     // }
     //
-    // /// New fake externalities with Istanbul schedule rules
-    // pub fn new_istanbul() -> Self {
-    //     let mut ext = FakeExt::default();
-    //     ext.schedule = Schedule::new_istanbul();
-    //     ext
-    // }
-    //
-    // /// New fake externalities with Berlin schedule rules
-    // pub fn new_berlin(from: Address, to: Address, builtins: &[Address]) -> Self {
-    //     let mut ext = FakeExt::default();
-    //     ext.schedule = Schedule::new_berlin();
-    //     ext.access_list.enable();
-    //     ext.access_list.insert_address(from);
-    //     ext.access_list.insert_address(to);
-    //     for builtin in builtins {
-    //         ext.access_list.insert_address(*builtin);
-    //     }
-    //     ext
-    // }
-    //
-    // /// New fake externalities with London schedule rules
-    // pub fn new_london(from: Address, to: Address, builtins: &[Address]) -> Self {
-    //     let mut ext = FakeExt::new_berlin(from, to, builtins);
-    //     ext.schedule = Schedule::new_london();
-    //     ext
-    // }
+    /// New fake externalities with Istanbul schedule rules
+    pub fn new_istanbul() -> Self {
+        let mut ext = FakeExt::default();
+        ext.schedule = Schedule::new_istanbul();
+        ext
+    }
+
+    /// New fake externalities with London schedule rules
+    pub fn new_london() -> Self {
+        let mut ext = FakeExt::default();
+        ext.schedule = Schedule::new_london();
+        ext
+    }
+
+    /// New fake externalities with Cancun schedule rules
+    pub fn new_cancun() -> Self {
+        let mut ext = FakeExt::default();
+        ext.schedule = Schedule::new_cancun();
+        ext
+    }
+
+    /// New fake externalities with Berlin schedule rules, seeded for a transaction
+    /// from `from` to `to`
+    pub fn new_berlin(from: Address, to: Address, builtins: &[Address]) -> Self {
+        let mut ext = FakeExt::default();
+        ext.schedule = Schedule::new_berlin();
+        ext.reset_access_list_for_tx(from, to, builtins);
+        ext
+    }
     //
     // /// Alter fake externalities to allow wasm
     // pub fn with_wasm(mut self) -> Self {
@@ -166,7 +189,9 @@ impl Ext for FakeExt {
     }
 
     fn exists_and_not_null(&self, address: &Address) -> Result<bool, Error> {
-        Ok(self.balances.get(address).map_or(false, |b| !b.is_zero()))
+        let has_balance = self.balances.get(address).is_some_and(|b| !b.is_zero());
+        let has_code = self.codes.get(address).is_some_and(|c| !c.is_empty());
+        Ok(has_balance || has_code)
     }
 
     fn origin_balance(&self) -> Result<U256, Error> {
@@ -177,6 +202,14 @@ impl Ext for FakeExt {
         Ok(self.balances.get(address).cloned().unwrap_or(U256::zero()))
     }
 
+    fn transfer(&mut self, from: &Address, to: &Address, value: &U256) -> Result<(), Error> {
+        let from_balance = self.balances.entry(*from).or_default();
+        *from_balance = from_balance.saturating_sub(*value);
+        let to_balance = self.balances.entry(*to).or_default();
+        *to_balance = to_balance.saturating_add(*value);
+        Ok(())
+    }
+
     fn blockhash(&mut self, number: &U256) -> H256 {
         self.blockhashes
             .get(number)
@@ -203,7 +236,10 @@ impl Ext for FakeExt {
             code_address: None,
         });
         // TODO: support traps in testing.
-        Ok(ContractCreateResult::Failed)
+        match self.created_address {
+            Some(address) => Ok(ContractCreateResult::Created(address, *gas)),
+            None => Ok(ContractCreateResult::Failed),
+        }
     }
 
     fn calc_address(&self, _code: &[u8], _address: CreateContractAddress) -> Option<Address> {
@@ -232,7 +268,9 @@ impl Ext for FakeExt {
             code_address: Some(code_address.clone()),
         });
         // TODO: support traps in testing.
-        Ok(MessageCallResult::Success(*gas, ReturnData::empty()))
+        let output = self.call_output.clone();
+        let len = output.len();
+        Ok(MessageCallResult::Success(*gas, ReturnData::new(output, 0, len)))
     }
 
     fn extcode(&self, address: &Address) -> Result<Option<Arc<Bytes>>, Error> {
@@ -315,4 +353,11 @@ impl Ext for FakeExt {
     fn al_insert_address(&mut self, address: Address) {
         self.access_list.insert_address(address)
     }
+
+    fn reset_access_list_for_tx(&mut self, sender: Address, to: Address, precompiles: &[Address]) {
+        self.access_list = AccessList::new(self.schedule.eip2929);
+        for address in [sender, to].iter().chain(precompiles) {
+            self.access_list.insert_address(*address);
+        }
+    }
 }
@@ -1,5 +1,11 @@
 mod memory;
+mod namespaced;
+#[cfg(feature = "rocksdb")]
+mod rocks;
 mod traits;
 
 pub use crate::traits::{DBStorage};
 pub use crate::memory::{ MemoryDB };
+pub use crate::namespaced::{ NamespacedDB };
+#[cfg(feature = "rocksdb")]
+pub use crate::rocks::RocksDB;
@@ -33,4 +33,67 @@ impl DBStorage for MemoryDB {
     fn remove(&mut self, key: &[u8]) {
         self.data.remove(key);
     }
+
+    fn write_batch(&mut self, inserts: Vec<(Vec<u8>, Vec<u8>)>, removals: Vec<Vec<u8>>) {
+        let data = &mut self.data;
+        for key in removals {
+            data.remove(&key);
+        }
+        data.extend(inserts);
+    }
+
+    fn keys(&self) -> Option<Vec<Vec<u8>>> {
+        Some(self.data.keys().cloned().collect())
+    }
+
+    fn byte_size(&self) -> Option<usize> {
+        Some(self.data.iter().map(|(k, v)| k.len() + v.len()).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DBStorage, MemoryDB};
+
+    #[test]
+    fn byte_size_works() {
+        let mut db = MemoryDB::new();
+        assert_eq!(db.byte_size(), Some(0));
+
+        db.insert(vec![1; 4], vec![2; 10]);
+        db.insert(vec![3; 2], vec![4; 6]);
+        assert_eq!(db.byte_size(), Some(22));
+
+        // overwriting a key replaces the value size
+        db.insert(vec![3; 2], vec![4; 1]);
+        assert_eq!(db.byte_size(), Some(17));
+
+        db.remove(&[1; 4]);
+        assert_eq!(db.byte_size(), Some(3));
+    }
+
+    #[test]
+    fn write_batch_works() {
+        let mut db = MemoryDB::new();
+        db.insert(b"a".to_vec(), b"1".to_vec());
+        db.insert(b"b".to_vec(), b"2".to_vec());
+        db.insert(b"c".to_vec(), b"3".to_vec());
+
+        db.write_batch(
+            vec![
+                (b"c".to_vec(), b"4".to_vec()),
+                (b"d".to_vec(), b"5".to_vec()),
+                (b"b".to_vec(), b"6".to_vec()),
+            ],
+            vec![b"a".to_vec(), b"b".to_vec(), b"e".to_vec()],
+        );
+
+        let mut keys = db.keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        // removed then inserted again in the same batch
+        assert_eq!(db.get(b"b"), Some(b"6".to_vec()));
+        assert_eq!(db.get(b"c"), Some(b"4".to_vec()));
+        assert_eq!(db.get(b"d"), Some(b"5".to_vec()));
+    }
 }
@@ -0,0 +1,123 @@
+use crate::DBStorage;
+
+/// A view into a shared `DBStorage` where every key is prefixed with a namespace byte.
+/// Allows different subsystems, i.e. trie nodes and block bodies, to share one backend
+/// without key collisions.
+pub struct NamespacedDB<'a, D: DBStorage> {
+    db: &'a mut D,
+    namespace: u8,
+}
+
+impl<'a, D: DBStorage> NamespacedDB<'a, D> {
+    pub fn new(db: &'a mut D, namespace: u8) -> Self {
+        NamespacedDB { db, namespace }
+    }
+
+    /// The namespace byte prepended to each key
+    pub fn namespace(&self) -> u8 {
+        self.namespace
+    }
+
+    fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+        let mut k = Vec::with_capacity(key.len() + 1);
+        k.push(self.namespace);
+        k.extend_from_slice(key);
+        k
+    }
+}
+
+impl<'a, D: DBStorage> DBStorage for NamespacedDB<'a, D> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db.get(&self.prefixed(key))
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.db.contains(&self.prefixed(key))
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        let key = self.prefixed(&key);
+        self.db.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let key = self.prefixed(key);
+        self.db.remove(&key);
+    }
+
+    fn write_batch(&mut self, inserts: Vec<(Vec<u8>, Vec<u8>)>, removals: Vec<Vec<u8>>) {
+        let inserts = inserts.into_iter().map(|(k, v)| (self.prefixed(&k), v)).collect();
+        let removals = removals.iter().map(|k| self.prefixed(k)).collect();
+        self.db.write_batch(inserts, removals);
+    }
+
+    fn keys(&self) -> Option<Vec<Vec<u8>>> {
+        let keys = self
+            .db
+            .keys()?
+            .into_iter()
+            .filter(|k| k.first() == Some(&self.namespace))
+            .map(|k| k[1..].to_vec())
+            .collect();
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DBStorage, MemoryDB, NamespacedDB};
+
+    #[test]
+    fn namespaces_do_not_collide() {
+        let mut db = MemoryDB::new();
+        {
+            let mut trie = NamespacedDB::new(&mut db, 0);
+            trie.insert(b"key".to_vec(), b"trie".to_vec());
+        }
+        {
+            let mut blocks = NamespacedDB::new(&mut db, 1);
+            assert!(!blocks.contains(b"key"));
+            blocks.insert(b"key".to_vec(), b"block".to_vec());
+            assert_eq!(blocks.keys(), Some(vec![b"key".to_vec()]));
+        }
+
+        assert_eq!(NamespacedDB::new(&mut db, 0).get(b"key"), Some(b"trie".to_vec()));
+        assert_eq!(NamespacedDB::new(&mut db, 1).get(b"key"), Some(b"block".to_vec()));
+
+        NamespacedDB::new(&mut db, 1).remove(b"key");
+        assert_eq!(NamespacedDB::new(&mut db, 0).get(b"key"), Some(b"trie".to_vec()));
+        assert_eq!(NamespacedDB::new(&mut db, 1).get(b"key"), None);
+        assert_eq!(db.keys().unwrap().len(), 1);
+    }
+
+    /// A backend implementing only the required methods
+    struct Unlisted(MemoryDB);
+
+    impl DBStorage for Unlisted {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.0.get(key)
+        }
+
+        fn contains(&self, key: &[u8]) -> bool {
+            self.0.contains(key)
+        }
+
+        fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+            self.0.insert(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.0.remove(key)
+        }
+    }
+
+    #[test]
+    fn unlisted_keys_works() {
+        let mut db = Unlisted(MemoryDB::new());
+        let mut ns = NamespacedDB::new(&mut db, 0);
+        ns.insert(b"key".to_vec(), b"value".to_vec());
+        assert_eq!(ns.get(b"key"), Some(b"value".to_vec()));
+        assert_eq!(ns.keys(), None);
+        assert_eq!(ns.byte_size(), None);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::DBStorage;
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, WriteBatch, DB};
+use std::path::Path;
+
+/// The column family holding the key-value pairs
+const COLUMN: &str = "kv";
+
+/// Database storage persisted with RocksDB. The `DBStorage` methods cannot report
+/// errors, so a failing read or write of the underlying db panics.
+pub struct RocksDB {
+    db: DB,
+}
+
+impl RocksDB {
+    /// Open the db at `path`, creating it and the column family if missing
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rocksdb::Error> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let column = ColumnFamilyDescriptor::new(COLUMN, Options::default());
+        let db = DB::open_cf_descriptors(&opts, path, vec![column])?;
+        Ok(RocksDB { db })
+    }
+
+    fn column(&self) -> &ColumnFamily {
+        self.db.cf_handle(COLUMN).expect("column family is created on open")
+    }
+}
+
+impl DBStorage for RocksDB {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db.get_cf(self.column(), key).expect("rocksdb read failed")
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.db
+            .get_pinned_cf(self.column(), key)
+            .expect("rocksdb read failed")
+            .is_some()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.db.put_cf(self.column(), key, value).expect("rocksdb write failed");
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.db.delete_cf(self.column(), key).expect("rocksdb write failed");
+    }
+
+    fn write_batch(&mut self, inserts: Vec<(Vec<u8>, Vec<u8>)>, removals: Vec<Vec<u8>>) {
+        let column = self.column();
+        let mut batch = WriteBatch::default();
+        for key in removals {
+            batch.delete_cf(column, key);
+        }
+        for (key, value) in inserts {
+            batch.put_cf(column, key, value);
+        }
+        self.db.write(batch).expect("rocksdb write failed");
+    }
+
+    fn keys(&self) -> Option<Vec<Vec<u8>>> {
+        let keys = self
+            .db
+            .iterator_cf(self.column(), IteratorMode::Start)
+            .map(|item| item.expect("rocksdb read failed").0.to_vec())
+            .collect();
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DBStorage, RocksDB};
+    use std::fs;
+
+    #[test]
+    fn reopen_works() {
+        let path = std::env::temp_dir().join(format!("kv-storage-rocks-{}", std::process::id()));
+        {
+            let mut db = RocksDB::open(&path).unwrap();
+            db.insert(b"a".to_vec(), b"1".to_vec());
+            db.insert(b"b".to_vec(), b"2".to_vec());
+            db.write_batch(vec![(b"c".to_vec(), b"3".to_vec())], vec![b"b".to_vec()]);
+        }
+
+        let db = RocksDB::open(&path).unwrap();
+        assert_eq!(db.get(b"a"), Some(b"1".to_vec()));
+        assert!(!db.contains(b"b"));
+        assert_eq!(db.get(b"c"), Some(b"3".to_vec()));
+        assert_eq!(db.keys(), Some(vec![b"a".to_vec(), b"c".to_vec()]));
+
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -15,4 +15,25 @@ pub trait DBStorage: Send + Sync {
     /// Remove a datum previously inserted. Insertions can be "owed" such that the same number of `insert()`s may
     /// happen without the data being eventually being inserted into the DB. It can be "owed" more than once.
     fn remove(&mut self, key: &[u8]);
+
+    /// Apply the removals then the inserts, so a key both removed and inserted ends up
+    /// stored. Backends that can should override it to write the batch atomically.
+    fn write_batch(&mut self, inserts: Vec<(Vec<u8>, Vec<u8>)>, removals: Vec<Vec<u8>>) {
+        for key in removals {
+            self.remove(&key);
+        }
+        for (key, value) in inserts {
+            self.insert(key, value);
+        }
+    }
+
+    /// List all the keys currently stored in the DB, `None` if the backend can't list them.
+    fn keys(&self) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    /// Total bytes of the keys and values stored, `None` if the backend can't compute it cheaply.
+    fn byte_size(&self) -> Option<usize> {
+        None
+    }
 }
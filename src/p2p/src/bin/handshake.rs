@@ -1,7 +1,8 @@
-use common::{Public, H256};
-use p2p::{Connection, Handshake};
+use common::Public;
+use p2p::{Connection, Handshake, SeenNonces};
 use std::error::Error;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tokio::net::TcpStream;
@@ -20,8 +21,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let connection = Connection::new(remote);
 
     let remote_node_pub = Public::from_str("d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666").unwrap();
-    let nonce = H256::random();
-    let handshake = Handshake::new(remote_node_pub, connection, nonce);
+    let handshake = Handshake::new(remote_node_pub, connection, Arc::new(SeenNonces::new()));
     handshake.start(true).await.unwrap();
 
     thread::sleep(Duration::from_millis(60000));
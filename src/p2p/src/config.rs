@@ -1,5 +1,6 @@
-use crate::node::NodeEndpoint;
-use common::KeyPair;
+use crate::node::{NodeEndpoint, NodeId};
+use common::{KeyPair, Secret};
+use std::fmt;
 use std::net::{SocketAddr, SocketAddrV4};
 
 pub struct HostInfo {
@@ -26,6 +27,17 @@ impl HostInfo {
         }
     }
 
+    /// The node id, the public key of the key pair
+    pub fn id(&self) -> Option<NodeId> {
+        self.key_pair.as_ref().map(|k| *k.public())
+    }
+
+    /// Sensitive: the node secret key. Never log it, and compare it only as a `Secret`,
+    /// whose comparison is constant time.
+    pub fn secret(&self) -> Option<&Secret> {
+        self.key_pair.as_ref().map(|k| k.secret())
+    }
+
     pub fn public_endpoint(&self) -> NodeEndpoint {
         match &self.public_endpoint {
             None => NodeEndpoint {
@@ -37,6 +49,16 @@ impl HostInfo {
     }
 }
 
+impl fmt::Debug for HostInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // only the public parts, the secret stays out of the logs
+        f.debug_struct("HostInfo")
+            .field("id", &self.id())
+            .field("public_endpoint", &self.public_endpoint)
+            .finish()
+    }
+}
+
 impl Default for HostInfo {
     fn default() -> Self {
         Self {
@@ -74,8 +96,32 @@ pub struct NetowkrConfig {
     pub max_peers: u32,
     /// Maximum handshakes
     pub max_handshakes: u32,
+    /// Maximum number of discovery pings sent in one round, pending nodes wait for the next
+    pub max_pings_per_round: usize,
     /// List of reserved node addresses.
     pub reserved_nodes: Vec<String>,
     /// Client identifier
     pub client_version: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::HostInfo;
+
+    #[test]
+    fn host_info_debug_works() {
+        let info = HostInfo::default();
+        let debug = format!("{:?}", info);
+        let secret = info.secret().unwrap();
+        assert!(!debug.contains(&secret.to_hex()));
+        assert!(!debug.contains(&format!("{:?}", secret.as_bytes())));
+        assert!(debug.contains(&format!("{:?}", info.id().unwrap())));
+
+        let info = HostInfo {
+            key_pair: None,
+            public_endpoint: None,
+        };
+        assert!(info.id().is_none());
+        assert!(info.secret().is_none());
+    }
+}
@@ -1,10 +1,24 @@
 use crate::error::Error;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, NewBlockCipher, NewCipher, StreamCipher};
+use aes::{Aes256, Aes256Ctr};
 use bytes::BytesMut;
+use common::H256;
+use tiny_keccak::{Hasher, Keccak};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
 const BUFFER_CAPACITY: usize = 4 * 1024;
 
+/// Size of the encrypted frame header, followed by its MAC
+pub const FRAME_HEADER_SIZE: usize = 32;
+const MAC_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 16;
+/// Max frame size that fits the 3 bytes size field of the header
+const MAX_FRAME_SIZE: usize = (1 << 24) - 1;
+/// rlp([capability-id, context-id]), both always zero
+const HEADER_DATA: [u8; 3] = [0xc2, 0x80, 0x80];
+
 pub type Bytes = Vec<u8>;
 
 /// This represents a connection to a peer
@@ -101,6 +115,141 @@ impl Connection {
     }
 }
 
+/// The secrets derived at the end of the handshake, from which the frames are
+/// encrypted and authenticated
+pub struct Secrets {
+    pub aes: H256,
+    pub mac: H256,
+    /// `(mac ^ remote nonce) || sent handshake packet`, the initial egress MAC input
+    pub egress_seed: Bytes,
+    /// `(mac ^ local nonce) || received handshake packet`, the initial ingress MAC input
+    pub ingress_seed: Bytes,
+}
+
+/// Encrypts and MACs the RLPx frames of a session. A frame is sent as the
+/// encrypted header and its MAC, then the encrypted padded body and its MAC.
+pub struct FrameCodec {
+    encryptor: Aes256Ctr,
+    decryptor: Aes256Ctr,
+    /// Cipher used to update the MAC states with each header and body
+    mac_cipher: Aes256,
+    egress_mac: Keccak,
+    ingress_mac: Keccak,
+}
+
+impl FrameCodec {
+    pub fn new(secrets: &Secrets) -> Self {
+        let iv = [0u8; BLOCK_SIZE];
+        let mut egress_mac = Keccak::v256();
+        egress_mac.update(&secrets.egress_seed);
+        let mut ingress_mac = Keccak::v256();
+        ingress_mac.update(&secrets.ingress_seed);
+
+        Self {
+            encryptor: Aes256Ctr::new(secrets.aes.as_bytes().into(), &iv.into()),
+            decryptor: Aes256Ctr::new(secrets.aes.as_bytes().into(), &iv.into()),
+            mac_cipher: Aes256::new(GenericArray::from_slice(secrets.mac.as_bytes())),
+            egress_mac,
+            ingress_mac,
+        }
+    }
+
+    /// Encode `payload` into a full frame, header first
+    pub fn encode(&mut self, payload: &[u8]) -> Result<Bytes, Error> {
+        if payload.len() > MAX_FRAME_SIZE {
+            return Err(Error::InvalidPacket);
+        }
+
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..3].copy_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        header[3..3 + HEADER_DATA.len()].copy_from_slice(&HEADER_DATA);
+        self.encryptor.apply_keystream(&mut header);
+        let header_mac = update_mac(&mut self.egress_mac, &self.mac_cipher, &header);
+
+        let mut body = vec![0u8; padded(payload.len())];
+        body[..payload.len()].copy_from_slice(payload);
+        self.encryptor.apply_keystream(&mut body);
+        self.egress_mac.update(&body);
+        let seed = digest(&self.egress_mac);
+        let body_mac = update_mac(&mut self.egress_mac, &self.mac_cipher, &seed);
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + body.len() + MAC_SIZE);
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(&header_mac);
+        frame.extend_from_slice(&body);
+        frame.extend_from_slice(&body_mac);
+        Ok(frame)
+    }
+
+    /// Check and decrypt the frame header, returning the payload size of the frame
+    pub fn decode_header(&mut self, header: &[u8]) -> Result<usize, Error> {
+        if header.len() != FRAME_HEADER_SIZE {
+            return Err(Error::InvalidPacket);
+        }
+        let (header, mac) = header.split_at(BLOCK_SIZE);
+        if !mac_eq(&update_mac(&mut self.ingress_mac, &self.mac_cipher, header), mac) {
+            return Err(Error::FrameMacNotMatch);
+        }
+
+        let mut header = header.to_vec();
+        self.decryptor.apply_keystream(&mut header);
+        Ok(u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize)
+    }
+
+    /// Number of bytes following the header for a payload of `size`
+    pub fn body_size(size: usize) -> usize {
+        padded(size) + MAC_SIZE
+    }
+
+    /// Check and decrypt the frame body following a header of payload `size`
+    pub fn decode_body(&mut self, body: &[u8], size: usize) -> Result<Bytes, Error> {
+        if body.len() != Self::body_size(size) {
+            return Err(Error::InvalidPacket);
+        }
+        let (body, mac) = body.split_at(body.len() - MAC_SIZE);
+        self.ingress_mac.update(body);
+        let seed = digest(&self.ingress_mac);
+        if !mac_eq(&update_mac(&mut self.ingress_mac, &self.mac_cipher, &seed), mac) {
+            return Err(Error::FrameMacNotMatch);
+        }
+
+        let mut payload = body.to_vec();
+        self.decryptor.apply_keystream(&mut payload);
+        payload.truncate(size);
+        Ok(payload)
+    }
+}
+
+/// Payload size rounded up to the cipher block size
+fn padded(size: usize) -> usize {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// The first 16 bytes of the current MAC state
+fn digest(mac: &Keccak) -> [u8; MAC_SIZE] {
+    let mut out = [0u8; 32];
+    mac.clone().finalize(&mut out);
+    let mut d = [0u8; MAC_SIZE];
+    d.copy_from_slice(&out[..MAC_SIZE]);
+    d
+}
+
+/// Update the MAC state with `aes(mac secret, digest) ^ seed`, returning the new digest
+fn update_mac(mac: &mut Keccak, mac_cipher: &Aes256, seed: &[u8]) -> [u8; MAC_SIZE] {
+    let mut block = GenericArray::clone_from_slice(&digest(mac));
+    mac_cipher.encrypt_block(&mut block);
+    for (b, s) in block.iter_mut().zip(seed) {
+        *b ^= s;
+    }
+    mac.update(&block);
+    digest(mac)
+}
+
+/// Compare MACs in constant time, so the time taken leaks nothing of where they differ
+fn mac_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Error::StdError(e)
@@ -109,9 +258,44 @@ impl From<std::io::Error> for Error {
 
 #[cfg(test)]
 mod tests {
+    use crate::connection::{FrameCodec, Secrets, FRAME_HEADER_SIZE};
+    use crate::error::Error;
+    use common::H256;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn frame_codec_works() {
+        let (aes, mac) = (H256::random(), H256::random());
+        let (auth, ack) = (b"auth packet".to_vec(), b"ack packet".to_vec());
+        let mut local = FrameCodec::new(&Secrets { aes, mac, egress_seed: auth.clone(), ingress_seed: ack.clone() });
+        let mut remote = FrameCodec::new(&Secrets { aes, mac, egress_seed: ack, ingress_seed: auth });
+
+        // the cipher and MAC states carry over from one frame to the next
+        for payload in [b"hello".to_vec(), vec![7u8; 16], vec![]] {
+            let frame = local.encode(&payload).unwrap();
+            let (header, body) = frame.split_at(FRAME_HEADER_SIZE);
+            let size = remote.decode_header(header).unwrap();
+            assert_eq!(size, payload.len());
+            assert_eq!(body.len(), FrameCodec::body_size(size));
+            assert_eq!(remote.decode_body(body, size).unwrap(), payload);
+        }
+
+        // and the other way round
+        let frame = remote.encode(b"world").unwrap();
+        let (header, body) = frame.split_at(FRAME_HEADER_SIZE);
+        let size = local.decode_header(header).unwrap();
+        assert_eq!(local.decode_body(body, size).unwrap(), b"world");
+
+        // a tampered body fails the MAC check
+        let mut frame = local.encode(b"hello").unwrap();
+        frame[FRAME_HEADER_SIZE] ^= 1;
+        let (header, body) = frame.split_at(FRAME_HEADER_SIZE);
+        let size = remote.decode_header(header).unwrap();
+        assert!(matches!(remote.decode_body(body, size), Err(Error::FrameMacNotMatch)));
+    }
 }
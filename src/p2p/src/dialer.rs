@@ -0,0 +1,276 @@
+use crate::connection::Connection;
+use crate::error::Error;
+use crate::handshake::{Handshake, SeenNonces};
+use crate::node::{NodeEntry, NodeId};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::time::timeout;
+
+const MAX_IN_FLIGHT_DIALS: usize = 16;
+const DIAL_TIMEOUT: Duration = Duration::from_secs(5);
+const DIAL_BACKOFF: [Duration; 4] = [
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+    Duration::from_secs(600),
+];
+
+/// The dial failures recorded for a peer
+struct DialFailure {
+    fail_count: usize,
+    backoff_until: Instant,
+}
+
+/// Marks a dial to the node as in flight until dropped, so a cancelled dial is released too
+struct InFlightDial<'a> {
+    dialing: &'a Mutex<HashSet<NodeId>>,
+    id: NodeId,
+}
+
+impl Drop for InFlightDial<'_> {
+    fn drop(&mut self) {
+        self.dialing
+            .lock()
+            .expect("dialing lock is never poisoned")
+            .remove(&self.id);
+    }
+}
+
+/// Opens outbound connections to peers and runs the originating handshake
+pub struct Dialer {
+    /// Permits for the dials allowed in flight at the same time
+    in_flight: Arc<Semaphore>,
+    /// The peers a dial is in flight to
+    dialing: Mutex<HashSet<NodeId>>,
+    failures: RwLock<HashMap<NodeId, DialFailure>>,
+    /// The peers with an established session
+    sessions: RwLock<HashMap<NodeId, Handshake>>,
+    seen_nonces: Arc<SeenNonces>,
+}
+
+impl Dialer {
+    pub fn new() -> Self {
+        Self::with_max_in_flight(MAX_IN_FLIGHT_DIALS)
+    }
+
+    pub fn with_max_in_flight(max_in_flight: usize) -> Self {
+        Self {
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+            dialing: Mutex::new(HashSet::new()),
+            failures: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            seen_nonces: Arc::new(SeenNonces::new()),
+        }
+    }
+
+    /// Dial the node and register the session once the handshake completes.
+    /// Waits for a free slot if too many dials are in flight.
+    pub async fn dial(&self, entry: &NodeEntry) -> Result<(), Error> {
+        if let Some(f) = self.failures.read().await.get(entry.id()) {
+            if f.backoff_until > Instant::now() {
+                return Err(Error::DialBackoff);
+            }
+        }
+
+        let _dial = self
+            .start_dial(entry.id())
+            .ok_or(Error::AlreadyConnected)?;
+        if self.has_session(entry.id()).await {
+            return Err(Error::AlreadyConnected);
+        }
+
+        let _permit = self
+            .in_flight
+            .acquire()
+            .await
+            .expect("dialer semaphore is never closed");
+
+        match self.connect(entry).await {
+            Ok(handshake) => {
+                self.failures.write().await.remove(entry.id());
+                self.sessions.write().await.insert(*entry.id(), handshake);
+                Ok(())
+            }
+            Err(e) => {
+                log::debug!("dial to {:?} failed: {:?}", entry.endpoint().address, e);
+                self.record_failure(entry.id()).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether a session with the node is established
+    pub async fn has_session(&self, id: &NodeId) -> bool {
+        self.sessions.read().await.contains_key(id)
+    }
+
+    pub async fn session_count(&self) -> usize {
+        self.sessions.read().await.len()
+    }
+
+    /// Mark a dial to the node as in flight, `None` if one already is
+    fn start_dial(&self, id: &NodeId) -> Option<InFlightDial<'_>> {
+        let mut dialing = self.dialing.lock().expect("dialing lock is never poisoned");
+        if !dialing.insert(*id) {
+            return None;
+        }
+        Some(InFlightDial {
+            dialing: &self.dialing,
+            id: *id,
+        })
+    }
+
+    /// Connect and run the handshake, both within `DIAL_TIMEOUT`
+    async fn connect(&self, entry: &NodeEntry) -> Result<Handshake, Error> {
+        timeout(DIAL_TIMEOUT, async {
+            let stream = TcpStream::connect(entry.endpoint().address).await?;
+            let handshake = Handshake::new(
+                *entry.id(),
+                Connection::new(stream),
+                Arc::clone(&self.seen_nonces),
+            );
+            handshake.originate().await?;
+            Ok(handshake)
+        })
+        .await
+        .map_err(|_| Error::DialTimeout)?
+    }
+
+    async fn record_failure(&self, id: &NodeId) {
+        let mut failures = self.failures.write().await;
+        let f = failures.entry(*id).or_insert(DialFailure {
+            fail_count: 0,
+            backoff_until: Instant::now(),
+        });
+        let backoff = DIAL_BACKOFF[f.fail_count.min(DIAL_BACKOFF.len() - 1)];
+        f.fail_count += 1;
+        f.backoff_until = Instant::now() + backoff;
+    }
+}
+
+impl Default for Dialer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::{Dialer, NodeEndpoint, NodeEntry};
+    use common::{decrypt, encrypt, random_nonce, KeyPair, Public, H256};
+    use rlp::{RLPStream, Rlp};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const ECIES_OVERHEAD: usize = 113;
+
+    /// A minimal responder that reads the auth packet and replies with an ack
+    async fn respond(listener: TcpListener, key_pair: KeyPair) {
+        respond_with_nonce(listener, key_pair, random_nonce()).await
+    }
+
+    async fn respond_with_nonce(listener: TcpListener, key_pair: KeyPair, nonce: H256) {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut prefix = [0u8; 2];
+        socket.read_exact(&mut prefix).await.unwrap();
+        let mut encrypted = vec![0u8; u16::from_be_bytes(prefix) as usize];
+        socket.read_exact(&mut encrypted).await.unwrap();
+        let auth = decrypt(key_pair.secret(), &prefix, &encrypted).unwrap();
+        let remote_pub: Public = Rlp::new(&auth).val_at(1).unwrap();
+
+        let mut rlp = RLPStream::new_list(3);
+        rlp.append(KeyPair::random().public());
+        rlp.append(&nonce);
+        rlp.append(&4u64);
+        let ack = rlp.out();
+        let prefix = ((ack.len() + ECIES_OVERHEAD) as u16).to_be_bytes();
+        let message = encrypt(&remote_pub, &prefix, &ack).unwrap();
+        socket.write_all(&prefix).await.unwrap();
+        socket.write_all(&message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dial_works() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let key_pair = KeyPair::random();
+        let entry = NodeEntry::new(
+            *key_pair.public(),
+            NodeEndpoint::from_socket(address, address.port()),
+        );
+        let responder = tokio::spawn(respond(listener, key_pair));
+
+        let dialer = Dialer::new();
+        dialer.dial(&entry).await.unwrap();
+        responder.await.unwrap();
+
+        assert!(dialer.has_session(entry.id()).await);
+        assert_eq!(dialer.session_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn dial_once_per_peer_works() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let key_pair = KeyPair::random();
+        let entry = NodeEntry::new(
+            *key_pair.public(),
+            NodeEndpoint::from_socket(address, address.port()),
+        );
+        let responder = tokio::spawn(respond(listener, key_pair));
+
+        // the second dial finds the first in flight, then the session
+        let dialer = Dialer::with_max_in_flight(2);
+        let (first, second) = tokio::join!(dialer.dial(&entry), dialer.dial(&entry));
+        responder.await.unwrap();
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::AlreadyConnected)));
+        assert!(matches!(dialer.dial(&entry).await, Err(Error::AlreadyConnected)));
+        assert_eq!(dialer.session_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn replayed_nonce_rejected_works() {
+        let dialer = Dialer::new();
+        let nonce = random_nonce();
+
+        let mut results = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let key_pair = KeyPair::random();
+            let entry = NodeEntry::new(
+                *key_pair.public(),
+                NodeEndpoint::from_socket(address, address.port()),
+            );
+            let responder = tokio::spawn(respond_with_nonce(listener, key_pair, nonce));
+            results.push(dialer.dial(&entry).await);
+            responder.await.unwrap();
+        }
+
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::NonceReplayed)));
+        assert_eq!(dialer.session_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn dial_backoff_works() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let entry = NodeEntry::new(
+            *KeyPair::random().public(),
+            NodeEndpoint::from_socket(address, address.port()),
+        );
+
+        let dialer = Dialer::with_max_in_flight(1);
+        assert!(matches!(dialer.dial(&entry).await, Err(Error::StdError(_))));
+        assert!(matches!(dialer.dial(&entry).await, Err(Error::DialBackoff)));
+        assert_eq!(dialer.session_count().await, 0);
+    }
+}
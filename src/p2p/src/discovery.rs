@@ -1,21 +1,23 @@
 use crate::config::HostInfo;
 use crate::connection::Bytes;
+use crate::enr::NodeRecord;
 use crate::error::Error;
 use crate::node::{NodeEndpoint, NodeEntry, NodeId};
 use crate::node_table::NodeTable;
 use crate::PROTOCOL_VERSION;
-use common::{keccak, recover, sign, Secret, H256, H520};
+use common::{keccak, keccak_concat, recover, sign, Secret, H256, H520};
 use lru::LruCache;
 use rlp::{RLPStream, Rlp};
 use std::cmp::Ordering;
 use std::collections::hash_map::Entry;
 use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio::task::JoinHandle;
 
 const ADDRESS_BYTES_SIZE: usize = 32;
@@ -27,15 +29,26 @@ const BUCKET_SIZE: usize = 16; // Denoted by k in [Kademlia]. Number of nodes st
 const DISCOVERY_ROUND_TIMEOUT: u64 = 300; // in millis
 const DISCOVERY_REFRESH_TIMEOUT: u64 = 10; // in second
 const ALPHA: usize = 3; // Kademlia alpha parameter
+const MAX_FIND_NODE_PER_ROUND: usize = 16; // Max FIND_NODE packets sent in one round window
+const MAX_PINGS_PER_ROUND: usize = 8; // Default max PING packets sent in one round window
 const NODE_LAST_SEEN_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+const ENDPOINT_QUORUM: usize = 3; // Nodes that must report the same external endpoint before adopting it
+const MAX_OBSERVED_ENDPOINTS: usize = 32; // Max external endpoint reports kept
+const MAX_OBSERVED_NODES: usize = 1024; // Default max nodes kept that answered a discovery ping
+
+/// Number of hex characters of the node id used to prefix the log lines
+const LOG_PREFIX_LEN: usize = 8;
 
 const PACKET_PING: u8 = 1;
 const PACKET_PONG: u8 = 2;
 const PACKET_FIND_NODE: u8 = 3;
 const PACKET_NEIGHBOURS: u8 = 4;
+const PACKET_ENR_REQUEST: u8 = 5;
+const PACKET_ENR_RESPONSE: u8 = 6;
 
 const PING_TIMEOUT: Duration = Duration::from_millis(500);
 const FIND_NODE_TIMEOUT: Duration = Duration::from_secs(2);
+const ENR_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
 const REQUEST_BACKOFF: [Duration; 4] = [
     Duration::from_secs(1),
     Duration::from_secs(4),
@@ -43,6 +56,14 @@ const REQUEST_BACKOFF: [Duration; 4] = [
     Duration::from_secs(64),
 ];
 
+/// Log a line prefixed with the short id of the discovery node, so that the output
+/// of several nodes running in the same process can be told apart.
+macro_rules! node_log {
+    ($level:ident, $inner:expr, $($arg:tt)+) => {
+        log::$level!("[{}] {}", $inner.log_prefix, format_args!($($arg)+))
+    };
+}
+
 #[derive(Debug)]
 pub struct BucketEntry {
     pub node: NodeEntry,
@@ -166,17 +187,128 @@ enum NodeValidity {
 enum PingReason {
     Default,
     FromDiscoveryRequest(NodeId, NodeValidity),
+    /// Ping a node of a full bucket to check it is still alive
+    Revalidate,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub enum Request {
     AddNode(NodeEntry),
     AddNodes(Vec<NodeEntry>),
     FindNode(NodeId, NodeEntry),
+    /// Evict the node without banning it, it can be added again later
+    RemoveNode(NodeId),
+    /// Report the packet counters
+    Stats(oneshot::Sender<DiscoveryStats>),
     /// Stop the discovery service
     Stop,
 }
 
+/// A snapshot of the discovery packet counters
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiscoveryStats {
+    pub ping_sent: u64,
+    pub pong_sent: u64,
+    pub find_node_sent: u64,
+    pub neighbours_sent: u64,
+    pub enr_request_sent: u64,
+    pub enr_response_sent: u64,
+    pub ping_received: u64,
+    pub pong_received: u64,
+    pub find_node_received: u64,
+    pub neighbours_received: u64,
+    pub enr_request_received: u64,
+    pub enr_response_received: u64,
+    /// Packets that failed size, hash or signature checks
+    pub malformed_packets: u64,
+    /// Packets with an unknown packet type
+    pub unknown_packets: u64,
+    /// Valid packets whose processing returned an error
+    pub handling_errors: u64,
+    /// Packets the udp socket failed to send
+    pub send_errors: u64,
+    /// Nodes that answered a discovery ping but are not in the buckets
+    pub observed_nodes: usize,
+}
+
+/// Tunables of the discovery service
+#[derive(Clone, Debug)]
+pub struct DiscoveryConfig {
+    /// Max PING packets sent in one round window, see `NetowkrConfig::max_pings_per_round`
+    pub max_pings_per_round: usize,
+    /// Max observed but unvalidated nodes kept, the least recently seen are evicted
+    pub max_observed_nodes: usize,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            max_pings_per_round: MAX_PINGS_PER_ROUND,
+            max_observed_nodes: MAX_OBSERVED_NODES,
+        }
+    }
+}
+
+/// The live packet counters, indexed by packet type
+#[derive(Default)]
+struct DiscoveryMetrics {
+    sent: [AtomicU64; 6],
+    received: [AtomicU64; 6],
+    malformed_packets: AtomicU64,
+    unknown_packets: AtomicU64,
+    handling_errors: AtomicU64,
+    send_errors: AtomicU64,
+}
+
+impl DiscoveryMetrics {
+    fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+
+    fn packet_index(packet_type: u8) -> Option<usize> {
+        match packet_type {
+            PACKET_PING..=PACKET_ENR_RESPONSE => Some((packet_type - PACKET_PING) as usize),
+            _ => None,
+        }
+    }
+
+    fn on_sent(&self, packet_type: u8) {
+        if let Some(i) = Self::packet_index(packet_type) {
+            Self::inc(&self.sent[i]);
+        }
+    }
+
+    fn on_received(&self, packet_type: u8) {
+        match Self::packet_index(packet_type) {
+            Some(i) => Self::inc(&self.received[i]),
+            None => Self::inc(&self.unknown_packets),
+        }
+    }
+
+    fn snapshot(&self) -> DiscoveryStats {
+        let load = |c: &AtomicU64| c.load(AtomicOrdering::Relaxed);
+        DiscoveryStats {
+            ping_sent: load(&self.sent[0]),
+            pong_sent: load(&self.sent[1]),
+            find_node_sent: load(&self.sent[2]),
+            neighbours_sent: load(&self.sent[3]),
+            enr_request_sent: load(&self.sent[4]),
+            enr_response_sent: load(&self.sent[5]),
+            ping_received: load(&self.received[0]),
+            pong_received: load(&self.received[1]),
+            find_node_received: load(&self.received[2]),
+            neighbours_received: load(&self.received[3]),
+            enr_request_received: load(&self.received[4]),
+            enr_response_received: load(&self.received[5]),
+            malformed_packets: load(&self.malformed_packets),
+            unknown_packets: load(&self.unknown_packets),
+            handling_errors: load(&self.handling_errors),
+            send_errors: load(&self.send_errors),
+            observed_nodes: 0,
+        }
+    }
+}
+
 pub struct Discovery {
     is_stop: bool,
     handle: Option<JoinHandle<()>>,
@@ -185,16 +317,41 @@ pub struct Discovery {
 
 impl Discovery {
     pub async fn start(info: &HostInfo, node_table: Arc<RwLock<NodeTable>>) -> Result<Self, Error> {
+        Self::start_with_config(info, node_table, DiscoveryConfig::default()).await
+    }
+
+    /// Start discovery sending at most `max_pings_per_round` pings each round,
+    /// see `NetowkrConfig::max_pings_per_round`
+    pub async fn start_with_ping_budget(
+        info: &HostInfo,
+        node_table: Arc<RwLock<NodeTable>>,
+        max_pings_per_round: usize,
+    ) -> Result<Self, Error> {
+        let config = DiscoveryConfig {
+            max_pings_per_round,
+            ..Default::default()
+        };
+        Self::start_with_config(info, node_table, config).await
+    }
+
+    /// Start discovery with the given tunables
+    pub async fn start_with_config(
+        info: &HostInfo,
+        node_table: Arc<RwLock<NodeTable>>,
+        config: DiscoveryConfig,
+    ) -> Result<Self, Error> {
         let (udp_tx, mut udp_rx) = mpsc::channel(1024);
         let (request_tx, mut request_rx) = mpsc::channel(1024);
 
-        log::debug!(
+        let socket = UdpSocket::bind(info.public_endpoint().udp_address()).await?;
+        let mut discovery = DiscoveryInner::new(info, node_table, udp_tx);
+        discovery.apply_config(&config);
+        node_log!(
+            debug,
+            discovery,
             "discovery starting udp at {:}",
             info.public_endpoint().udp_address()
         );
-
-        let socket = UdpSocket::bind(info.public_endpoint().udp_address()).await?;
-        let mut discovery = DiscoveryInner::new(info, node_table, udp_tx);
         let handle = tokio::spawn(async move {
             let mut round_interval =
                 tokio::time::interval(Duration::from_millis(DISCOVERY_ROUND_TIMEOUT));
@@ -208,25 +365,28 @@ impl Discovery {
                     Some((bytes, target)) = udp_rx.recv() => {
                         match socket.send_to(&bytes, target).await {
                             Ok(_) => {},
-                            Err(e) => log::error!("error sending udp {:?}", e),
+                            Err(e) => {
+                                DiscoveryMetrics::inc(&discovery.metrics.send_errors);
+                                node_log!(error, discovery, "error sending udp {:?}", e);
+                            }
                         }
                     }
                     Ok((size, peer)) = socket.recv_from(&mut buf) => {
                         let data = &buf[..size];
                         match discovery.on_packet(data, peer).await {
                             Ok(_) => {},
-                            Err(e) => log::error!("error processing packet {:?}", e),
+                            Err(e) => node_log!(error, discovery, "error processing packet {:?}", e),
                         }
                     }
                     Some(request) = request_rx.recv() => {
-                        log::debug!("received request: {:?}", request);
+                        node_log!(debug, discovery, "received request: {:?}", request);
                         if let Request::Stop = request { break; }
                         discovery.handle(request).await;
                     }
                     _ = round_interval.tick() => {
                         match discovery.round().await {
                             Ok(_) => {},
-                            Err(e) => log::error!("error processing round {:?}", e),
+                            Err(e) => node_log!(error, discovery, "error processing round {:?}", e),
                         }
                     }
                     _ = refresh_interval.tick() => {
@@ -234,7 +394,7 @@ impl Discovery {
                     }
                 }
             }
-            log::debug!("discovery ended");
+            node_log!(debug, discovery, "discovery ended");
         });
 
         Ok(Self {
@@ -265,6 +425,11 @@ impl Discovery {
         self.request_tx.send(Request::AddNodes(nodes)).await
     }
 
+    /// Remove a node from the buckets and the node table, it is not banned
+    pub async fn remove_node(&mut self, id: NodeId) -> Result<(), SendError<Request>> {
+        self.request_tx.send(Request::RemoveNode(id)).await
+    }
+
     /// Find nodes that are closest to the `to_find` from `from`
     pub async fn find_node(
         &mut self,
@@ -273,6 +438,13 @@ impl Discovery {
     ) -> Result<(), SendError<Request>> {
         self.request_tx.send(Request::FindNode(to_find, from)).await
     }
+
+    /// Get a snapshot of the packet counters
+    pub async fn stats(&self) -> Result<DiscoveryStats, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.request_tx.send(Request::Stats(tx)).await?;
+        rx.await.map_err(|_| Error::ServiceStopped)
+    }
 }
 
 impl Drop for Discovery {
@@ -295,6 +467,8 @@ struct DiscoveryInner {
     id: NodeId,
     /// The hash of self node id
     id_hash: H256,
+    /// Short hex of the node id prepended to the log lines
+    log_prefix: String,
     /// The secret of self
     secret: Secret,
     /// The self public endpoint
@@ -308,7 +482,8 @@ struct DiscoveryInner {
     /// The nodes that is currently being `find`
     finding_nodes: HashMap<NodeId, FindNodeRequest>,
     /// The node entries to be added
-    to_add: Vec<NodeEntry>,
+    to_add: Vec<(NodeEntry, PingReason)>,
+    /// Nodes that answered a discovery ping, kept apart from the buckets
     other_observed_nodes: LruCache<NodeId, (NodeEndpoint, Instant)>,
     sender: mpsc::Sender<(Bytes, SocketAddr)>,
 
@@ -317,6 +492,32 @@ struct DiscoveryInner {
     discovery_round: Option<u16>,
     discovery_id: NodeId,
     discovery_nodes: HashSet<NodeId>,
+
+    // outbound rate limiting
+    /// Max number of FIND_NODE packets allowed per round window
+    find_node_budget: usize,
+    /// Number of FIND_NODE packets sent in the current round window
+    find_node_sent: usize,
+    /// FIND_NODE requests exceeding the budget, sent in the next round
+    deferred_find_nodes: VecDeque<(NodeId, NodeEntry)>,
+    /// Max number of PING packets allowed per round window, so pinging new
+    /// nodes is spread over rounds instead of sent in a single burst
+    ping_budget: usize,
+    /// Number of PING packets sent through `try_ping` in the current round window
+    ping_sent: usize,
+
+    /// Packet counters for observability
+    metrics: DiscoveryMetrics,
+
+    // node records (EIP-868)
+    /// Sequence number of the local node record
+    enr_seq: u64,
+    /// Hash and send time of the outstanding ENR requests
+    enr_requests: HashMap<NodeId, (H256, Instant)>,
+    /// Latest verified records received from other nodes
+    node_records: HashMap<NodeId, NodeRecord>,
+    /// External endpoint of self as reported in verified pongs, by reporting node
+    observed_endpoints: LruCache<NodeId, NodeEndpoint>,
 }
 
 impl DiscoveryInner {
@@ -325,11 +526,14 @@ impl DiscoveryInner {
         node_table: Arc<RwLock<NodeTable>>,
         udp_tx: mpsc::Sender<(Bytes, SocketAddr)>,
     ) -> Self {
+        // a host without a key pair gets a random one on each call, so take it once
+        let key_pair = info.key_pair();
         Self {
             node_table,
-            id: info.key_pair().public().clone(),
-            id_hash: keccak(info.key_pair().public().as_bytes()),
-            secret: info.key_pair().secret().clone(),
+            id: *key_pair.public(),
+            id_hash: keccak(key_pair.public().as_bytes()),
+            log_prefix: format!("{:x}", key_pair.public())[..LOG_PREFIX_LEN].to_string(),
+            secret: key_pair.secret().clone(),
             public_endpoint: info.public_endpoint(),
             buckets: (0..ADDRESS_BYTES_SIZE * 8)
                 .map(|_| VecDeque::new())
@@ -338,15 +542,30 @@ impl DiscoveryInner {
             pinging_nodes: HashMap::new(),
             finding_nodes: HashMap::new(),
             to_add: vec![],
-            other_observed_nodes: LruCache::new(1024),
+            other_observed_nodes: LruCache::new(MAX_OBSERVED_NODES),
             sender: udp_tx,
             discovery_initiated: false,
             discovery_round: None,
             discovery_id: Default::default(),
             discovery_nodes: Default::default(),
+            find_node_budget: MAX_FIND_NODE_PER_ROUND,
+            find_node_sent: 0,
+            deferred_find_nodes: VecDeque::new(),
+            ping_budget: MAX_PINGS_PER_ROUND,
+            ping_sent: 0,
+            metrics: DiscoveryMetrics::default(),
+            enr_seq: 1,
+            enr_requests: HashMap::new(),
+            node_records: HashMap::new(),
+            observed_endpoints: LruCache::new(MAX_OBSERVED_ENDPOINTS),
         }
     }
 
+    fn apply_config(&mut self, config: &DiscoveryConfig) {
+        self.ping_budget = config.max_pings_per_round;
+        self.other_observed_nodes.resize(config.max_observed_nodes);
+    }
+
     // ========= Handling Requests =========
 
     /// Handling different requests
@@ -355,27 +574,55 @@ impl DiscoveryInner {
             Request::AddNode(e) => self.add_node(e).await,
             Request::AddNodes(ns) => self.add_node_list(ns).await,
             Request::FindNode(id, node) => self.find_node(id, &node).await,
+            Request::RemoveNode(id) => {
+                self.remove_node(&id).await;
+                Ok(())
+            }
+            Request::Stats(tx) => {
+                let mut stats = self.metrics.snapshot();
+                stats.observed_nodes = self.other_observed_nodes.len();
+                tx.send(stats).unwrap_or_default();
+                Ok(())
+            }
             _ => Ok(()),
         };
         match r {
             Ok(_) => {}
-            Err(e) => log::error!("error handling request: {:?}", e),
+            Err(e) => node_log!(error, self, "error handling request: {:?}", e),
         }
     }
 
     /// Add a new node to discovery table. Pings the node
     async fn add_node(&mut self, e: NodeEntry) -> Result<(), Error> {
-        log::debug!("attempt to add node: {:?}", e);
+        node_log!(debug, self, "attempt to add node: {:?}", e);
         let node_hash = keccak(e.id().as_bytes());
-        match distance(&self.id_hash, &node_hash) {
-            Some(d) => {
-                if self.buckets[d].iter().any(|bn| bn.node.id() == e.id()) {
-                    return Ok(());
-                }
-                self.try_ping(e, PingReason::Default).await
-            }
-            None => Err(Error::InvalidNodeDistance),
+        if distance(&self.id_hash, &node_hash).is_none() {
+            return Err(Error::InvalidNodeDistance);
         }
+        self.try_ping(e, PingReason::Default).await
+    }
+
+    /// Whether the node is in a bucket, being pinged or waiting to be pinged
+    fn is_known(&self, id: &NodeId) -> bool {
+        let in_bucket = distance(&self.id_hash, &keccak(id.as_bytes()))
+            .is_some_and(|d| self.buckets[d].iter().any(|bn| bn.node.id() == id));
+        in_bucket || self.is_ping_pending(id)
+    }
+
+    /// Whether the node is being pinged or waiting to be pinged
+    fn is_ping_pending(&self, id: &NodeId) -> bool {
+        self.pinging_nodes.contains_key(id) || self.to_add.iter().any(|(n, _)| n.id() == id)
+    }
+
+    /// Forget the node, unlike a failed validation it does not go into `not_allowed`
+    async fn remove_node(&mut self, id: &NodeId) {
+        if let Some(d) = distance(&self.id_hash, &keccak(id.as_bytes())) {
+            self.buckets[d].retain(|bn| bn.node.id() != id);
+        }
+        self.to_add.retain(|(n, _)| n.id() != id);
+        self.pinging_nodes.remove(id);
+        self.node_table.write().await.remove(id);
+        node_log!(debug, self, "removed node {:?}", id);
     }
 
     /// Add a list of nodes. Pings a few nodes each round
@@ -387,13 +634,20 @@ impl DiscoveryInner {
     }
 
     async fn find_node(&mut self, target: NodeId, node: &NodeEntry) -> Result<(), Error> {
+        if self.find_node_sent >= self.find_node_budget {
+            node_log!(debug, self, "find node budget exhausted, deferring {:?}", node);
+            self.deferred_find_nodes.push_back((target, node.clone()));
+            return Ok(());
+        }
+
         let mut rlp = RLPStream::new_list(2);
         rlp.append(&target);
         append_expiration(&mut rlp);
 
         self.send_packet(PACKET_FIND_NODE, &rlp.out(), node.endpoint().udp_address())
             .await?;
-        log::debug!("sent FindNode to {:?}", node);
+        self.find_node_sent += 1;
+        node_log!(debug, self, "sent FindNode to {:?}", node);
 
         self.finding_nodes.insert(
             *node.id(),
@@ -412,27 +666,38 @@ impl DiscoveryInner {
     async fn on_packet(&mut self, packet: &[u8], from: SocketAddr) -> Result<(), Error> {
         // validate packet
         if packet.len() < 32 + 65 + 4 + 1 {
+            DiscoveryMetrics::inc(&self.metrics.malformed_packets);
             return Err(Error::BadProtocol);
         }
 
         // check hash of package
         let hash_signed = keccak(&packet[32..]);
         if hash_signed[..] != packet[0..32] {
-            log::error!(
+            node_log!(
+                error,
+                self,
                 "signature of packet does not match, packet size: {:}",
                 packet.len()
             );
+            DiscoveryMetrics::inc(&self.metrics.malformed_packets);
             return Err(Error::PacketHashNotMatch);
         }
 
         // recover message sender node id
         let signed = &packet[(32 + 65)..];
-        let signature = H520::from_slice(&packet[32..(32 + 65)]);
-        let node_id = recover(&signature.into(), &keccak(signed))?;
+        let signature = H520::try_from_slice(&packet[32..(32 + 65)])?;
+        let node_id = match recover(&signature.into(), &keccak(signed)) {
+            Ok(id) => id,
+            Err(e) => {
+                DiscoveryMetrics::inc(&self.metrics.malformed_packets);
+                return Err(e.into());
+            }
+        };
 
         // handle the actual data
         let packet_id = signed[0];
-        match packet_id {
+        self.metrics.on_received(packet_id);
+        let r = match packet_id {
             PACKET_PING => {
                 self.on_ping(&signed[1..], node_id, from, hash_signed.as_bytes())
                     .await
@@ -440,11 +705,20 @@ impl DiscoveryInner {
             PACKET_PONG => self.on_pong(&signed[1..], node_id, from).await,
             PACKET_FIND_NODE => self.on_find_node(&signed[1..], node_id, from).await,
             PACKET_NEIGHBOURS => self.on_neighbours(&signed[1..], node_id, from).await,
+            PACKET_ENR_REQUEST => {
+                self.on_enr_request(&signed[1..], node_id, from, hash_signed)
+                    .await
+            }
+            PACKET_ENR_RESPONSE => self.on_enr_response(&signed[1..], node_id, from),
             _ => {
-                log::debug!("Unknown UDP packet: {}", packet_id);
+                node_log!(debug, self, "Unknown UDP packet: {}", packet_id);
                 Ok(())
             }
+        };
+        if r.is_err() {
+            DiscoveryMetrics::inc(&self.metrics.handling_errors);
         }
+        r
     }
 
     async fn on_find_node(
@@ -453,7 +727,9 @@ impl DiscoveryInner {
         from_node: NodeId,
         from_socket: SocketAddr,
     ) -> Result<(), Error> {
-        log::debug!(
+        node_log!(
+            debug,
+            self,
             "got find node from {:?} ; node_id={:#x}",
             &from_socket,
             from_node
@@ -490,7 +766,7 @@ impl DiscoveryInner {
         node_id: NodeId,
         from: SocketAddr,
     ) -> Result<(), Error> {
-        log::debug!("got neighbours from {:?} ; node_id={:#x}", &from, node_id);
+        node_log!(debug, self, "got neighbours from {:?} ; node_id={:#x}", &from, node_id);
 
         let rlp = Rlp::new(bytes);
 
@@ -503,7 +779,7 @@ impl DiscoveryInner {
                         request.response_count += nodes_count;
                         true
                     } else {
-                        log::debug!("got unexpected Neighbors from {:?} ; oversized packet ({} + {}) node_id={:#x}", &from, request.response_count, nodes_count, node_id);
+                        node_log!(debug, self, "got unexpected Neighbors from {:?} ; oversized packet ({} + {}) node_id={:#x}", &from, request.response_count, nodes_count, node_id);
                         false
                     }
                 };
@@ -537,12 +813,12 @@ impl DiscoveryInner {
 
             let endpoint = NodeEndpoint::from_rlp(&r)?;
             if !endpoint.is_valid_discovery_node() {
-                log::debug!("invalid address: {:?}", endpoint);
+                node_log!(debug, self, "invalid address: {:?}", endpoint);
                 continue;
             }
 
             if !self.is_allowed(&id) {
-                log::debug!("node id not allowed: {:?}", id);
+                node_log!(debug, self, "node id not allowed: {:?}", id);
                 continue;
             }
 
@@ -558,6 +834,84 @@ impl DiscoveryInner {
         Ok(())
     }
 
+    /// Ask the node for its latest node record
+    async fn enr_request(&mut self, node: &NodeEntry) -> Result<(), Error> {
+        let mut rlp = RLPStream::new_list(1);
+        append_expiration(&mut rlp);
+        let hash = self
+            .send_packet(PACKET_ENR_REQUEST, &rlp.out(), node.endpoint().udp_address())
+            .await?;
+        self.enr_requests.insert(*node.id(), (hash, Instant::now()));
+        Ok(())
+    }
+
+    async fn on_enr_request(
+        &mut self,
+        bytes: &[u8],
+        node_id: NodeId,
+        from: SocketAddr,
+        request_hash: H256,
+    ) -> Result<(), Error> {
+        node_log!(debug, self, "got enr request from {:?} ; node_id={:#x}", &from, node_id);
+        let rlp = Rlp::new(bytes);
+        self.check_expired(rlp.val_at(0)?)?;
+
+        // the response is larger than the request, only answer bonded nodes
+        let from_entry = NodeEntry::new(node_id, NodeEndpoint::from_socket(from, from.port()));
+        if !matches!(self.check_validity(&from_entry), NodeValidity::ValidNode(_)) {
+            node_log!(debug, self, "dropped enr request from unbonded {:?}", from);
+            return Ok(());
+        }
+
+        // packet-data = [request-hash, ENR]
+        let record = NodeRecord::new(self.enr_seq, self.id, self.public_endpoint.clone());
+        let mut response = RLPStream::new_list(2);
+        response.append(&request_hash);
+        record.to_rlp(&mut response, &self.secret)?;
+
+        self.send_packet(PACKET_ENR_RESPONSE, &response.out(), from)
+            .await?;
+        Ok(())
+    }
+
+    fn on_enr_response(
+        &mut self,
+        bytes: &[u8],
+        node_id: NodeId,
+        from: SocketAddr,
+    ) -> Result<(), Error> {
+        node_log!(debug, self, "got enr response from {:?} ; node_id={:#x}", &from, node_id);
+        let rlp = Rlp::new(bytes);
+        let request_hash: H256 = rlp.val_at(0)?;
+        match self.enr_requests.get(&node_id) {
+            Some((hash, sent_at))
+                if *hash == request_hash && sent_at.elapsed() < ENR_REQUEST_TIMEOUT => {}
+            _ => {
+                node_log!(debug, self, "unexpected enr response from {:?}", from);
+                return Ok(());
+            }
+        }
+        self.enr_requests.remove(&node_id);
+
+        let record = NodeRecord::from_rlp(&rlp.at(1)?)?;
+        if record.id != node_id {
+            return Err(Error::InvalidNodeRecord);
+        }
+        if let Some(known) = self.node_records.get(&node_id) {
+            if known.seq >= record.seq {
+                return Ok(());
+            }
+        }
+
+        let entry = NodeEntry::new(node_id, record.endpoint.clone());
+        if entry.endpoint().is_valid_discovery_node() {
+            // only refreshes nodes already in the buckets
+            self.update_bucket(entry).unwrap_or_default();
+        }
+        self.node_records.insert(node_id, record);
+        Ok(())
+    }
+
     async fn on_ping(
         &mut self,
         bytes: &[u8],
@@ -565,7 +919,7 @@ impl DiscoveryInner {
         from: SocketAddr,
         echo_hash: &[u8],
     ) -> Result<(), Error> {
-        log::debug!("got ping from {:?} ; node_id={:#x}", &from, node_id);
+        node_log!(debug, self, "got ping from {:?} ; node_id={:#x}", &from, node_id);
         let rlp = Rlp::new(bytes);
         let ping_from = if let Ok(ne) = NodeEndpoint::from_rlp(&rlp.at(1)?) {
             ne
@@ -577,15 +931,22 @@ impl DiscoveryInner {
             address.set_port(0);
             NodeEndpoint::from_socket(address, from.port())
         };
-        let ping_to = NodeEndpoint::from_rlp(&rlp.at(2)?)?;
+        // only validated, the pong echoes the envelope address instead
+        NodeEndpoint::from_rlp(&rlp.at(2)?)?;
         let timestamp: u64 = rlp.val_at(3)?;
         self.check_expired(timestamp)?;
 
         // now form the response packet, https://github.com/ethereum/devp2p/blob/master/discv4.md#pong-packet-0x02
-        let mut response = RLPStream::new_list(3);
-        ping_to.to_rlp_list(&mut response);
+        // `to` mirrors the envelope address of the ping so the sender learns its external endpoint
+        let seen_from = NodeEndpoint::from_socket(
+            SocketAddr::new(from.ip(), ping_from.address.port()),
+            from.port(),
+        );
+        let mut response = RLPStream::new_list(4);
+        seen_from.to_rlp_list(&mut response);
         response.append(&echo_hash);
         append_expiration(&mut response);
+        response.append(&self.enr_seq);
 
         self.send_packet(PACKET_PONG, &response.out(), from.clone())
             .await?;
@@ -593,11 +954,11 @@ impl DiscoveryInner {
         let pong_to = NodeEndpoint::from_socket(from.clone(), ping_from.udp_port);
         let entry = NodeEntry::new(node_id.clone(), pong_to);
         if !entry.endpoint().is_valid_discovery_node() {
-            log::debug!("got bad address: {:?}", entry);
+            node_log!(debug, self, "got bad address: {:?}", entry);
         } else if !self.is_allowed(&node_id) {
-            log::debug!("address not allowed: {:?}", entry);
+            node_log!(debug, self, "address not allowed: {:?}", entry);
         } else {
-            log::debug!("adding node on ping from: {:?}", entry);
+            node_log!(debug, self, "adding node on ping from: {:?}", entry);
             self.add_node(entry).await?;
         }
         Ok(())
@@ -609,24 +970,35 @@ impl DiscoveryInner {
         node_id: NodeId,
         from: SocketAddr,
     ) -> Result<(), Error> {
-        log::debug!("got pong from {:?} ; node_id={:#x}", &from, node_id);
+        node_log!(debug, self, "got pong from {:?} ; node_id={:#x}", &from, node_id);
 
         let rlp = Rlp::new(bytes);
         let echo_hash: H256 = rlp.val_at(1)?;
         let timestamp: u64 = rlp.val_at(2)?;
         self.check_expired(timestamp)?;
+        // the record sequence number, missing from nodes without records (EIP-868)
+        let enr_seq: Option<u64> = rlp.val_at(3).ok();
 
         match self.pinging_nodes.entry(node_id) {
             Entry::Occupied(entry) => {
                 if echo_hash != entry.get().hash {
-                    log::debug!("Hash doesn't match for node {:?} at {:?}", node_id, from);
+                    node_log!(debug, self, "Hash doesn't match for node {:?} at {:?}", node_id, from);
                     return Ok(());
                 }
                 let meta = entry.remove();
-                if let PingReason::FromDiscoveryRequest(node_id, _validity) = meta.reason {
-                    log::debug!("node id: {:?}", node_id);
+                if let Ok(endpoint) = NodeEndpoint::from_rlp(&rlp.at(0)?) {
+                    self.observe_endpoint(node_id, endpoint);
+                }
+                if let PingReason::FromDiscoveryRequest(target, _validity) = meta.reason {
+                    node_log!(debug, self, "node id: {:?}", target);
+                    self.observe_node(&meta.node);
                 } else {
-                    self.update_node(meta.node).await?;
+                    self.update_node(meta.node.clone()).await?;
+                    // the node is verified now, fetch its record if it changed since the last one
+                    let known_seq = self.node_records.get(&node_id).map(|r| r.seq);
+                    if enr_seq.is_some_and(|seq| known_seq.is_none_or(|known| seq > known)) {
+                        self.enr_request(&meta.node).await?;
+                    }
                 }
                 Ok(())
             }
@@ -635,7 +1007,39 @@ impl DiscoveryInner {
     }
 
     // ========= Helper Functions =========
+    /// Remember a node that answered a ping sent while handling a discovery request
+    fn observe_node(&mut self, node: &NodeEntry) {
+        self.other_observed_nodes
+            .put(*node.id(), (node.endpoint().clone(), Instant::now()));
+    }
+
+    /// Records the external endpoint a verified node has seen us at, and adopts it as
+    /// the public endpoint once a quorum of nodes agrees on a new one
+    fn observe_endpoint(&mut self, node_id: NodeId, endpoint: NodeEndpoint) {
+        if !endpoint.is_valid_discovery_node() {
+            return;
+        }
+        self.observed_endpoints.put(node_id, endpoint.clone());
+        if endpoint == self.public_endpoint {
+            return;
+        }
+
+        let reports = self
+            .observed_endpoints
+            .iter()
+            .filter(|(_, e)| **e == endpoint)
+            .count();
+        if reports >= ENDPOINT_QUORUM {
+            node_log!(info, self, "public endpoint updated to {:?}", endpoint);
+            self.public_endpoint = endpoint;
+            // the record carries the endpoint
+            self.enr_seq += 1;
+            self.observed_endpoints.clear();
+        }
+    }
+
     async fn round(&mut self) -> Result<(), Error> {
+        self.reset_round_budget().await?;
         self.clear_expired(Instant::now());
         self.update_new_nodes().await?;
 
@@ -648,6 +1052,20 @@ impl DiscoveryInner {
         Ok(())
     }
 
+    /// Starts a new round window, sending the FIND_NODE requests deferred
+    /// from the previous round first
+    async fn reset_round_budget(&mut self) -> Result<(), Error> {
+        self.ping_sent = 0;
+        self.find_node_sent = 0;
+        while self.find_node_sent < self.find_node_budget {
+            match self.deferred_find_nodes.pop_front() {
+                Some((target, node)) => self.find_node(target, &node).await?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
     fn refresh(&mut self) {
         if self.discovery_round.is_none() {
             self.start_discovery();
@@ -656,7 +1074,7 @@ impl DiscoveryInner {
 
     /// Starts the discovery process at round 0
     fn start_discovery(&mut self) {
-        log::debug!("starting discovery");
+        node_log!(debug, self, "starting discovery");
         self.discovery_round = Some(0);
         self.discovery_id.randomize();
         self.discovery_nodes.clear();
@@ -664,7 +1082,7 @@ impl DiscoveryInner {
 
     /// Complete the discovery process
     fn stop_discovery(&mut self) {
-        log::debug!("completing discovery");
+        node_log!(debug, self, "completing discovery");
         self.discovery_round = None;
         self.discovery_nodes.clear();
     }
@@ -678,7 +1096,7 @@ impl DiscoveryInner {
             self.stop_discovery();
             return;
         }
-        log::debug!("starting round {:?}", self.discovery_round);
+        node_log!(debug, self, "starting round {:?}", self.discovery_round);
         let mut tried_count = 0;
         {
             let nearest = self
@@ -696,7 +1114,9 @@ impl DiscoveryInner {
                         tried_count += 1;
                     }
                     Err(e) => {
-                        log::warn!(
+                        node_log!(
+                            warn,
+                            self,
                             "error sending node discovery packet for {:?}: {:?}",
                             &r.endpoint(),
                             e
@@ -715,9 +1135,9 @@ impl DiscoveryInner {
     }
 
     async fn update_new_nodes(&mut self) -> Result<(), Error> {
-        while self.pinging_nodes.len() < MAX_NODES_PING {
+        while self.pinging_nodes.len() < MAX_NODES_PING && self.ping_sent < self.ping_budget {
             match self.to_add.pop() {
-                Some(next) => self.try_ping(next, PingReason::Default).await?,
+                Some((next, reason)) => self.try_ping(next, reason).await?,
                 None => break,
             }
         }
@@ -729,7 +1149,7 @@ impl DiscoveryInner {
         let mut nodes_to_expire = Vec::new();
         self.pinging_nodes.retain(|node_id, ping_request| {
             if time.duration_since(ping_request.send_at) > PING_TIMEOUT {
-                log::debug!("removing expired PING request for node_id={:?}", node_id);
+                node_log!(debug, self, "removing expired PING request for node_id={:?}", node_id);
                 nodes_to_expire.push(*node_id);
                 false
             } else {
@@ -739,7 +1159,9 @@ impl DiscoveryInner {
         self.finding_nodes.retain(|node_id, find_node_request| {
             if time.duration_since(find_node_request.sent_at) > FIND_NODE_TIMEOUT {
                 if !find_node_request.answered {
-                    log::debug!(
+                    node_log!(
+                        debug,
+                        self,
                         "removing expired FIND NODE request for node_id={:?}",
                         node_id
                     );
@@ -750,6 +1172,8 @@ impl DiscoveryInner {
                 true
             }
         });
+        self.enr_requests
+            .retain(|_, (_, sent_at)| time.duration_since(*sent_at) <= ENR_REQUEST_TIMEOUT);
         for node_id in nodes_to_expire {
             self.expire_node_request(node_id);
         }
@@ -767,7 +1191,9 @@ impl DiscoveryInner {
                 let entry = &mut bucket[index];
                 entry.backoff_until = Instant::now() + REQUEST_BACKOFF[entry.fail_count];
                 entry.fail_count += 1;
-                log::debug!(
+                node_log!(
+                    debug,
+                    self,
                     "requests to node {:?} timed out {} consecutive time(s)",
                     &entry.node.id(),
                     entry.fail_count
@@ -776,7 +1202,7 @@ impl DiscoveryInner {
                 let node = bucket
                     .remove(index)
                     .expect("index was located in if condition");
-                log::debug!("removed expired node {:?}", &node.node.id());
+                node_log!(debug, self, "removed expired node {:?}", &node.node.id());
             }
         }
     }
@@ -796,7 +1222,9 @@ impl DiscoveryInner {
                 .await?;
         }
 
-        log::debug!(
+        node_log!(
+            debug,
+            self,
             "sent {} neighbours to {:?}",
             nearest_nodes.len(),
             &node.endpoint()
@@ -809,14 +1237,16 @@ impl DiscoveryInner {
         let dist = match distance(&self.id_hash, &id_hash) {
             Some(dist) => dist,
             None => {
-                log::debug!("got an incoming discovery request from self: {:?}", node);
+                node_log!(debug, self, "got an incoming discovery request from self: {:?}", node);
                 return NodeValidity::Ourselves;
             }
         };
 
         let bucket = &self.buckets[dist];
         if let Some(entry) = bucket.iter().find(|n| n.node.id() == node.id()) {
-            log::debug!(
+            node_log!(
+                debug,
+                self,
                 "found a known node in a bucket when processing discovery: {:?} / {:?}",
                 entry.node,
                 node
@@ -863,7 +1293,9 @@ impl DiscoveryInner {
         match self.update_bucket(n) {
             Err(Error::NodeIsSelf) => {}
             Err(Error::NodeNotFoundInBucket { entry, distance }) => {
-                log::debug!(
+                node_log!(
+                    debug,
+                    self,
                     "adding node: {:?} with distance {:?} to bucket",
                     entry,
                     distance
@@ -878,7 +1310,7 @@ impl DiscoveryInner {
                     self.try_ping(
                         // unwrap should be safe
                         node_to_ping(&self.buckets[distance]).unwrap(),
-                        PingReason::Default,
+                        PingReason::Revalidate,
                     )
                     .await?;
                 }
@@ -929,26 +1361,34 @@ impl DiscoveryInner {
 
     async fn try_ping(&mut self, e: NodeEntry, reason: PingReason) -> Result<(), Error> {
         if !self.is_allowed(e.id()) {
-            log::debug!("node id {} not allowed", e.id());
+            node_log!(debug, self, "node id {} not allowed", e.id());
             return Err(Error::NodeBlocked);
         }
 
-        // Currently pinging, return directly.
+        // Already known, return directly. Only a node of a bucket is pinged again, to
+        // revalidate it.
         // TODO: maybe perform timeout check?
-        if self.pinging_nodes.contains_key(e.id()) {
-            log::debug!("node id {} is being pinged", e.id());
+        let known = match reason {
+            PingReason::Revalidate => self.is_ping_pending(e.id()),
+            _ => self.is_known(e.id()),
+        };
+        if known {
+            node_log!(debug, self, "node id {} is already known", e.id());
             return Ok(());
         }
 
-        if self.pinging_nodes.len() < MAX_NODES_PING {
-            log::debug!("pinging node id {}", e.id());
+        if self.pinging_nodes.len() < MAX_NODES_PING && self.ping_sent < self.ping_budget {
+            node_log!(debug, self, "pinging node id {}", e.id());
+            self.ping_sent += 1;
             self.ping(e, reason).await
         } else {
-            log::debug!(
-                "pinging nodes full, add node id {} to pending nodes",
+            node_log!(
+                debug,
+                self,
+                "pinging nodes full or ping budget exhausted, add node id {} to pending nodes",
                 e.id()
             );
-            self.to_add.push(e);
+            self.to_add.push((e, reason));
             Ok(())
         }
     }
@@ -986,9 +1426,10 @@ impl DiscoveryInner {
         socket: SocketAddr,
     ) -> Result<H256, Error> {
         let packet = assemble_packet(packet_type, packet_bytes, &self.secret)?;
-        let hash = H256::from_slice(&packet[..32]);
+        let hash = H256::try_from_slice(&packet[..32])?;
         // send to the channel for processing
         self.sender.send((packet, socket)).await?;
+        self.metrics.on_sent(packet_type);
         Ok(hash)
     }
 
@@ -1050,37 +1491,34 @@ fn node_to_ping(nodes: &VecDeque<BucketEntry>) -> Option<NodeEntry> {
 
 /// Prepare the package: [hash_of_signature_and_bytes, signature, bytes]
 fn assemble_packet(packet_id: u8, bytes: &[u8], secret: &Secret) -> Result<Bytes, Error> {
+    let hash = keccak_concat(&[&[packet_id], bytes]);
+    let signature = sign(secret, &hash)?;
+    let signed_hash = keccak_concat(&[&signature[..], &[packet_id], bytes]);
+
     let mut packet = Bytes::with_capacity(bytes.len() + 32 + 65 + 1);
-    packet.resize(32 + 65, 0); // Filled in below
+    packet.extend_from_slice(signed_hash.as_bytes());
+    packet.extend_from_slice(&signature[..]);
     packet.push(packet_id);
     packet.extend_from_slice(bytes);
-
-    let hash = keccak(&packet[(32 + 65)..]);
-    let signature = match sign(secret, &hash) {
-        Ok(s) => s,
-        Err(e) => {
-            log::warn!("Error signing UDP packet");
-            return Err(Error::from(e));
-        }
-    };
-    packet[32..(32 + 65)].copy_from_slice(&signature[..]);
-    let signed_hash = keccak(&packet[32..]);
-    packet[0..32].copy_from_slice(signed_hash.as_bytes());
     Ok(packet)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::discovery::{DiscoveryInner, ADDRESS_BYTES_SIZE, distance};
-    use crate::node::NodeId;
+    use crate::discovery::{
+        distance, DiscoveryConfig, DiscoveryInner, PingReason, Request, ADDRESS_BYTES_SIZE,
+        ENDPOINT_QUORUM, MAX_NODES_PING, MAX_PINGS_PER_ROUND, PACKET_ENR_REQUEST,
+    };
+    use crate::enr::NodeRecord;
+    use crate::node::{NodeEndpoint, NodeEntry, NodeId};
     use crate::{HostInfo, NodeTable};
-    use common::{keccak, H256};
+    use common::{keccak, KeyPair, H256};
     use std::collections::{HashMap, HashSet, VecDeque};
     use std::net::SocketAddr;
     use std::str::FromStr;
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
     use tokio::net::UdpSocket;
-    use tokio::sync::{mpsc, RwLock};
+    use tokio::sync::{mpsc, oneshot, RwLock};
 
     fn mock_discovery_inner() -> DiscoveryInner {
         let info = HostInfo::default();
@@ -1138,6 +1576,361 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn stats_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, mut udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, node_table, udp_tx);
+
+        let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 30304));
+        mock_inner.ping(node, PingReason::Default).await.unwrap();
+        assert!(udp_rx.try_recv().is_ok());
+
+        let (tx, rx) = oneshot::channel();
+        mock_inner.handle(Request::Stats(tx)).await;
+        let stats = rx.await.unwrap();
+        assert_eq!(stats.ping_sent, 1);
+        assert_eq!(stats.find_node_sent, 0);
+
+        assert!(mock_inner.on_packet(&[0u8; 8], info.public_endpoint().udp_address()).await.is_err());
+        assert_eq!(mock_inner.metrics.snapshot().malformed_packets, 1);
+    }
+
+    #[tokio::test]
+    async fn observed_nodes_cap_works() {
+        let mut mock_inner = mock_discovery_inner();
+        mock_inner.apply_config(&DiscoveryConfig {
+            max_observed_nodes: 4,
+            ..Default::default()
+        });
+
+        for port in 30304..30314 {
+            let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", port));
+            mock_inner.observe_node(&node);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        mock_inner.handle(Request::Stats(tx)).await;
+        assert_eq!(rx.await.unwrap().observed_nodes, 4);
+    }
+
+    #[tokio::test]
+    async fn find_node_budget_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, mut udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, node_table, udp_tx);
+        mock_inner.find_node_budget = 2;
+
+        let target = NodeId::random();
+        for port in 30304..30309 {
+            let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", port));
+            mock_inner.find_node(target, &node).await.unwrap();
+        }
+        let mut sent = 0;
+        while udp_rx.try_recv().is_ok() {
+            sent += 1;
+        }
+        assert_eq!(sent, 2);
+        assert_eq!(mock_inner.deferred_find_nodes.len(), 3);
+
+        // next round only flushes up to the budget
+        mock_inner.round().await.unwrap();
+        let mut sent = 0;
+        while udp_rx.try_recv().is_ok() {
+            sent += 1;
+        }
+        assert_eq!(sent, 2);
+        assert_eq!(mock_inner.deferred_find_nodes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn remove_node_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, _udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, Arc::clone(&node_table), udp_tx);
+
+        let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("10.0.0.1", 30304));
+        let id = *node.id();
+        mock_inner.update_node(node.clone()).await.unwrap();
+        assert!(mock_inner.is_known(&id));
+        assert!(node_table.read().await.contains(&id));
+
+        mock_inner.handle(Request::RemoveNode(id)).await;
+        assert!(!mock_inner.is_known(&id));
+        assert!(!node_table.read().await.contains(&id));
+        assert!(mock_inner.is_allowed(&id));
+
+        // not banned, so it can come back
+        mock_inner.add_node(node.clone()).await.unwrap();
+        assert!(mock_inner.pinging_nodes.contains_key(&id));
+        mock_inner.update_node(node).await.unwrap();
+        assert!(node_table.read().await.contains(&id));
+    }
+
+    #[tokio::test]
+    async fn ping_budget_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, mut udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, node_table, udp_tx);
+
+        let nodes = (0..MAX_NODES_PING as u16)
+            .map(|i| NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 31000 + i)))
+            .collect();
+        mock_inner.add_node_list(nodes).await.unwrap();
+
+        // the pings go out a budget at a time, one batch per round
+        let mut batches = vec![];
+        loop {
+            let mut sent = 0;
+            while udp_rx.try_recv().is_ok() {
+                sent += 1;
+            }
+            if sent == 0 {
+                break;
+            }
+            batches.push(sent);
+            mock_inner.round().await.unwrap();
+        }
+        assert_eq!(batches, vec![MAX_PINGS_PER_ROUND; MAX_NODES_PING / MAX_PINGS_PER_ROUND]);
+        assert!(mock_inner.to_add.is_empty());
+        assert_eq!(mock_inner.pinging_nodes.len(), MAX_NODES_PING);
+    }
+
+    /// Collects the discovery log lines of all tests in this module
+    struct CaptureLogger;
+
+    static CAPTURED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target().starts_with("p2p::discovery")
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                CAPTURED.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[tokio::test]
+    async fn log_prefix_works() {
+        let _ = log::set_logger(&CaptureLogger);
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let info_a = HostInfo::default();
+        let info_b = HostInfo {
+            key_pair: Some(KeyPair::random()),
+            public_endpoint: Some(NodeEndpoint::new("127.0.0.1", 30320)),
+        };
+        let (udp_tx, mut rx_a) = mpsc::channel(1024);
+        let mut a = DiscoveryInner::new(&info_a, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+        let (udp_tx, _rx_b) = mpsc::channel(1024);
+        let mut b = DiscoveryInner::new(&info_b, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+
+        let node_b = NodeEntry::new(*info_b.key_pair().public(), info_b.public_endpoint());
+        a.add_node(node_b).await.unwrap();
+        let (ping, _) = rx_a.try_recv().unwrap();
+        b.on_packet(&ping, info_a.public_endpoint().udp_address()).await.unwrap();
+
+        let lines = CAPTURED.lock().unwrap().clone();
+        let prefix_a = format!("[{}] ", a.log_prefix);
+        let prefix_b = format!("[{}] ", b.log_prefix);
+        assert_ne!(prefix_a, prefix_b);
+        assert!(lines.iter().any(|l| l.starts_with(&prefix_a) && l.contains("attempt to add node")));
+        assert!(lines.iter().any(|l| l.starts_with(&prefix_b) && l.contains("got ping")));
+        assert!(lines.iter().all(|l| l.starts_with('[')));
+    }
+
+    #[tokio::test]
+    async fn add_known_node_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, mut udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, node_table, udp_tx);
+        mock_inner.ping_budget = MAX_NODES_PING;
+
+        let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 30304));
+        mock_inner.add_node(node.clone()).await.unwrap();
+        mock_inner.add_node(node).await.unwrap();
+        assert!(udp_rx.try_recv().is_ok());
+        assert!(udp_rx.try_recv().is_err());
+
+        // once the ping slots are full, a pending node is queued only once
+        for port in 1..MAX_NODES_PING as u16 {
+            let n = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 31000 + port));
+            mock_inner.add_node(n).await.unwrap();
+        }
+        let pending = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 30305));
+        mock_inner.add_node(pending.clone()).await.unwrap();
+        mock_inner.add_node(pending).await.unwrap();
+        assert_eq!(mock_inner.to_add.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn try_ping_known_node_works() {
+        let info = HostInfo::default();
+        let node_table = Arc::new(RwLock::new(NodeTable::new_in_memory()));
+        let (udp_tx, mut udp_rx) = mpsc::channel(1024);
+        let mut mock_inner = DiscoveryInner::new(&info, node_table, udp_tx);
+        mock_inner.ping_budget = MAX_NODES_PING;
+
+        // a node of a bucket is not pinged again, unless to revalidate it
+        let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 30304));
+        mock_inner.update_node(node.clone()).await.unwrap();
+        mock_inner.try_ping(node.clone(), PingReason::Default).await.unwrap();
+        assert!(udp_rx.try_recv().is_err());
+        mock_inner.try_ping(node.clone(), PingReason::Revalidate).await.unwrap();
+        mock_inner.try_ping(node, PingReason::Revalidate).await.unwrap();
+        assert!(udp_rx.try_recv().is_ok());
+        assert!(udp_rx.try_recv().is_err());
+
+        // a queued revalidation is still sent once the node leaves the queue
+        let queued = NodeEntry::new(NodeId::random(), NodeEndpoint::new("127.0.0.1", 30305));
+        mock_inner.update_node(queued.clone()).await.unwrap();
+        mock_inner.ping_budget = 0;
+        mock_inner.try_ping(queued, PingReason::Revalidate).await.unwrap();
+        assert_eq!(mock_inner.to_add.len(), 1);
+        mock_inner.ping_budget = MAX_NODES_PING;
+        mock_inner.update_new_nodes().await.unwrap();
+        assert!(mock_inner.to_add.is_empty());
+        assert!(udp_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn enr_request_works() {
+        let requester_info = HostInfo::default();
+        let (udp_tx, mut requester_rx) = mpsc::channel(1024);
+        let mut requester = DiscoveryInner::new(
+            &requester_info,
+            Arc::new(RwLock::new(NodeTable::new_in_memory())),
+            udp_tx,
+        );
+
+        let endpoint = NodeEndpoint::new("10.0.0.1", 30310);
+        let responder_info = HostInfo {
+            key_pair: Some(KeyPair::random()),
+            public_endpoint: Some(endpoint.clone()),
+        };
+        let (udp_tx, mut responder_rx) = mpsc::channel(1024);
+        let mut responder = DiscoveryInner::new(
+            &responder_info,
+            Arc::new(RwLock::new(NodeTable::new_in_memory())),
+            udp_tx,
+        );
+        responder.enr_seq = 7;
+
+        let responder_id = *responder_info.key_pair.as_ref().unwrap().public();
+        let node = NodeEntry::new(responder_id, endpoint.clone());
+        requester.enr_request(&node).await.unwrap();
+
+        // an unbonded requester gets no answer
+        let (request, _) = requester_rx.try_recv().unwrap();
+        let requester_address = requester_info.public_endpoint().udp_address();
+        responder.on_packet(&request, requester_address).await.unwrap();
+        assert!(responder_rx.try_recv().is_err());
+
+        let bonded = NodeEntry::new(
+            requester.id,
+            NodeEndpoint::from_socket(requester_address, requester_address.port()),
+        );
+        responder.update_node(bonded).await.unwrap();
+        responder.on_packet(&request, requester_address).await.unwrap();
+        let (response, to) = responder_rx.try_recv().unwrap();
+        assert_eq!(to, requester_address);
+
+        requester.on_packet(&response, endpoint.udp_address()).await.unwrap();
+        let record = &requester.node_records[&responder_id];
+        assert_eq!(record.seq, 7);
+        assert_eq!(record.endpoint, endpoint);
+        assert!(requester.enr_requests.is_empty());
+        assert_eq!(requester.metrics.snapshot().enr_response_received, 1);
+    }
+
+    #[tokio::test]
+    async fn pong_enr_seq_works() {
+        let info_a = HostInfo::default();
+        let info_b = HostInfo {
+            key_pair: Some(KeyPair::random()),
+            public_endpoint: Some(NodeEndpoint::new("127.0.0.1", 30330)),
+        };
+        let (udp_tx, mut rx_a) = mpsc::channel(1024);
+        let mut a = DiscoveryInner::new(&info_a, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+        let (udp_tx, mut rx_b) = mpsc::channel(1024);
+        let mut b = DiscoveryInner::new(&info_b, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+        let node_b = NodeEntry::new(*info_b.key_pair().public(), info_b.public_endpoint());
+
+        // the first pong asks for b's record, once it is held at the pong's seq it is not asked again
+        for (known_seq, expected) in [(None, true), (Some(0), true), (Some(1), false)] {
+            a.node_records.clear();
+            if let Some(seq) = known_seq {
+                a.node_records
+                    .insert(*node_b.id(), NodeRecord::new(seq, *node_b.id(), info_b.public_endpoint()));
+            }
+            a.ping(node_b.clone(), PingReason::Default).await.unwrap();
+            let (ping, _) = rx_a.try_recv().unwrap();
+            b.on_packet(&ping, info_a.public_endpoint().udp_address()).await.unwrap();
+            let (pong, _) = rx_b.try_recv().unwrap();
+            a.on_packet(&pong, info_b.public_endpoint().udp_address()).await.unwrap();
+
+            let mut enr_requested = false;
+            while let Ok((packet, _)) = rx_a.try_recv() {
+                enr_requested |= packet[32 + 65] == PACKET_ENR_REQUEST;
+            }
+            assert_eq!(enr_requested, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn public_endpoint_update_works() {
+        let info = HostInfo {
+            key_pair: Some(KeyPair::random()),
+            public_endpoint: Some(NodeEndpoint::new("192.168.1.2", 30303)),
+        };
+        let (udp_tx, mut rx) = mpsc::channel(1024);
+        let mut inner = DiscoveryInner::new(&info, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+        // the address the NAT maps our pings to
+        let external = SocketAddr::from_str("203.0.113.7:40000").unwrap();
+
+        for i in 0..ENDPOINT_QUORUM {
+            assert_eq!(inner.public_endpoint, info.public_endpoint());
+
+            let peer_endpoint = NodeEndpoint::new("10.0.0.1", 30400 + i as u16);
+            let peer_info = HostInfo {
+                key_pair: Some(KeyPair::random()),
+                public_endpoint: Some(peer_endpoint.clone()),
+            };
+            let (udp_tx, mut peer_rx) = mpsc::channel(1024);
+            let mut peer = DiscoveryInner::new(&peer_info, Arc::new(RwLock::new(NodeTable::new_in_memory())), udp_tx);
+
+            let node = NodeEntry::new(*peer_info.key_pair().public(), peer_endpoint.clone());
+            inner.ping(node, PingReason::Default).await.unwrap();
+            let (ping, _) = rx.try_recv().unwrap();
+            peer.on_packet(&ping, external).await.unwrap();
+            let (pong, to) = peer_rx.try_recv().unwrap();
+            assert_eq!(to, external);
+            inner.on_packet(&pong, peer_endpoint.udp_address()).await.unwrap();
+            while rx.try_recv().is_ok() {}
+        }
+
+        let expected = NodeEndpoint::from_socket(SocketAddr::from_str("203.0.113.7:30303").unwrap(), 40000);
+        assert_eq!(inner.public_endpoint, expected);
+        assert_eq!(inner.enr_seq, 2);
+
+        // subsequent pings advertise the new endpoint
+        let node = NodeEntry::new(NodeId::random(), NodeEndpoint::new("10.0.0.2", 30500));
+        inner.ping(node, PingReason::Default).await.unwrap();
+        let (ping, _) = rx.try_recv().unwrap();
+        let rlp = rlp::Rlp::new(&ping[32 + 65 + 1..]);
+        assert_eq!(NodeEndpoint::from_rlp(&rlp.at(1).unwrap()).unwrap(), expected);
+    }
+
     // #[test]
     // async fn nearest_nodes_fewer_than_bucket_limit_works() {
     //     let mut mock_inner = mock_discovery_inner();
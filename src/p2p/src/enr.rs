@@ -0,0 +1,226 @@
+use crate::error::Error;
+use crate::node::{NodeEndpoint, NodeId};
+use common::{keccak, recover, sign, Secret, H520};
+use rlp::{RLPStream, Rlp};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Max size of an encoded record, see EIP-778
+const MAX_RECORD_SIZE: usize = 300;
+const COMPRESSED_KEY_SIZE: usize = 33;
+/// Number of items in the record content: seq and 5 key value pairs
+const CONTENT_ITEMS: usize = 11;
+
+/// A node record (EIP-778) using the "v4" identity scheme
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeRecord {
+    /// Sequence number, increased whenever the record changes
+    pub seq: u64,
+    pub id: NodeId,
+    pub endpoint: NodeEndpoint,
+}
+
+impl NodeRecord {
+    pub fn new(seq: u64, id: NodeId, endpoint: NodeEndpoint) -> Self {
+        Self { seq, id, endpoint }
+    }
+
+    /// Sign the record content with `secret` and append `[signature, seq, k, v, ...]`
+    pub fn to_rlp(&self, rlp: &mut RLPStream, secret: &Secret) -> Result<(), Error> {
+        let mut content = RLPStream::new_list(CONTENT_ITEMS);
+        self.append_content(&mut content);
+        let signature = sign(secret, &keccak(content.as_bytes()))?;
+
+        rlp.begin_list(CONTENT_ITEMS + 1);
+        rlp.append(&&signature[..64]);
+        self.append_content(rlp);
+        Ok(())
+    }
+
+    /// Decode the record and verify its signature against the secp256k1 key it carries
+    pub fn from_rlp(rlp: &Rlp) -> Result<Self, Error> {
+        if rlp.as_raw().len() > MAX_RECORD_SIZE {
+            return Err(Error::InvalidNodeRecord);
+        }
+        let count = rlp.item_count()?;
+        if count < 2 || count % 2 != 0 {
+            return Err(Error::InvalidNodeRecord);
+        }
+
+        let signature = rlp.at(0)?.data()?;
+        let seq: u64 = rlp.val_at(1)?;
+        let mut content = RLPStream::new_list(count - 1);
+        content.append_raw(rlp.at(1)?.as_raw());
+
+        let (mut scheme, mut ip4, mut ip6, mut key) = (None, None, None, None);
+        let (mut tcp, mut udp) = (None, None);
+        for i in (2..count).step_by(2) {
+            let k = rlp.at(i)?;
+            let v = rlp.at(i + 1)?;
+            content.append_raw(k.as_raw());
+            content.append_raw(v.as_raw());
+            match k.data()? {
+                b"id" => scheme = Some(v.data()?),
+                b"ip" => ip4 = Some(v.data()?),
+                b"ip6" => ip6 = Some(v.data()?),
+                b"secp256k1" => key = Some(v.data()?),
+                b"tcp" => tcp = Some(v.as_val::<u16>()?),
+                b"udp" => udp = Some(v.as_val::<u16>()?),
+                _ => {}
+            }
+        }
+
+        if scheme != Some(&b"v4"[..]) {
+            return Err(Error::InvalidNodeRecord);
+        }
+        let key = key.ok_or(Error::InvalidNodeRecord)?;
+        let id = recover_signer(signature, &content.out(), key)?;
+
+        // the IPv4 address is preferred when the record has both
+        let ip = match (ip4, ip6) {
+            (Some(b), _) if b.len() == 4 => IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])),
+            (None, Some(b)) if b.len() == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(b);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(Error::InvalidNodeRecord),
+        };
+        let tcp = tcp.unwrap_or_default();
+        let endpoint = NodeEndpoint::from_socket(SocketAddr::new(ip, tcp), udp.unwrap_or(tcp));
+
+        Ok(Self { seq, id, endpoint })
+    }
+
+    /// Append the signed content `seq, k, v, ...` with keys sorted
+    fn append_content(&self, rlp: &mut RLPStream) {
+        rlp.append(&self.seq);
+        rlp.append(&"id").append(&"v4");
+        match self.endpoint.address.ip() {
+            IpAddr::V4(ip) => rlp.append(&"ip").append(&&ip.octets()[..]),
+            IpAddr::V6(ip) => rlp.append(&"ip6").append(&&ip.octets()[..]),
+        };
+        rlp.append(&"secp256k1").append(&&compress(&self.id)[..]);
+        rlp.append(&"tcp").append(&self.endpoint.address.port());
+        rlp.append(&"udp").append(&self.endpoint.udp_port);
+    }
+}
+
+/// Compressed form of an uncompressed (x, y) public key
+fn compress(id: &NodeId) -> [u8; COMPRESSED_KEY_SIZE] {
+    let mut out = [0u8; COMPRESSED_KEY_SIZE];
+    out[0] = 0x02 | (id[63] & 1);
+    out[1..].copy_from_slice(&id[..32]);
+    out
+}
+
+/// The record signature carries no recovery id, so try both and match the record key
+fn recover_signer(signature: &[u8], content: &[u8], key: &[u8]) -> Result<NodeId, Error> {
+    if signature.len() != 64 {
+        return Err(Error::InvalidNodeRecord);
+    }
+    let hash = keccak(content);
+    let mut sig = [0u8; 65];
+    sig[..64].copy_from_slice(signature);
+    for rec_id in 0..2 {
+        sig[64] = rec_id;
+        if let Ok(id) = recover(&H520::from(sig), &hash) {
+            if compress(&id)[..] == *key {
+                return Ok(id);
+            }
+        }
+    }
+    Err(Error::InvalidNodeRecord)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::enr::{compress, recover_signer, NodeRecord};
+    use crate::error::Error;
+    use crate::NodeEndpoint;
+    use common::{keccak, sign, KeyPair};
+    use rlp::{RLPStream, Rlp};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    /// A record signed by `key_pair` with the given ip keys, which must come sorted
+    fn record_with_ips(key_pair: &KeyPair, ips: &[(&str, &[u8])]) -> Vec<u8> {
+        let items = 1 + 2 * (4 + ips.len());
+        let append_content = |rlp: &mut RLPStream| {
+            rlp.append(&1u64);
+            rlp.append(&"id").append(&"v4");
+            for &(k, v) in ips {
+                rlp.append(&k).append(&v);
+            }
+            rlp.append(&"secp256k1").append(&&compress(key_pair.public())[..]);
+            rlp.append(&"tcp").append(&30303u16);
+            rlp.append(&"udp").append(&30303u16);
+        };
+        let mut content = RLPStream::new_list(items);
+        append_content(&mut content);
+        let signature = sign(key_pair.secret(), &keccak(content.as_bytes())).unwrap();
+
+        let mut rlp = RLPStream::new_list(items + 1);
+        rlp.append(&&signature[..64]);
+        append_content(&mut rlp);
+        rlp.out()
+    }
+
+    #[test]
+    fn node_record_works() {
+        let key_pair = KeyPair::random();
+        let record = NodeRecord::new(3, *key_pair.public(), NodeEndpoint::new("10.0.0.1", 30303));
+
+        let mut rlp = RLPStream::new();
+        record.to_rlp(&mut rlp, key_pair.secret()).unwrap();
+        let encoded = rlp.out();
+        assert_eq!(NodeRecord::from_rlp(&Rlp::new(&encoded)).unwrap(), record);
+
+        // a record signed by another key is rejected
+        let mut rlp = RLPStream::new();
+        record.to_rlp(&mut rlp, KeyPair::random().secret()).unwrap();
+        assert!(NodeRecord::from_rlp(&Rlp::new(&rlp.out())).is_err());
+    }
+
+    #[test]
+    fn signature_length_works() {
+        let key_pair = KeyPair::random();
+        let record = NodeRecord::new(3, *key_pair.public(), NodeEndpoint::new("10.0.0.1", 30303));
+        let mut rlp = RLPStream::new();
+        record.to_rlp(&mut rlp, key_pair.secret()).unwrap();
+        let encoded = rlp.out();
+        let decoded = Rlp::new(&encoded);
+        let signature = decoded.at(0).unwrap().data().unwrap();
+        let count = decoded.item_count().unwrap();
+
+        // a signature cut short or with a recovery id appended is an invalid record
+        for signature in [&signature[..63], &[signature, &[0][..]].concat()[..]] {
+            let mut rlp = RLPStream::new_list(count);
+            rlp.append(&signature);
+            for i in 1..count {
+                rlp.append_raw(decoded.at(i).unwrap().as_raw());
+            }
+            assert!(matches!(
+                NodeRecord::from_rlp(&Rlp::new(&rlp.out())),
+                Err(Error::InvalidNodeRecord)
+            ));
+        }
+        assert!(matches!(recover_signer(&signature[..63], &[], &[]), Err(Error::InvalidNodeRecord)));
+    }
+
+    #[test]
+    fn ip4_and_ip6_works() {
+        let key_pair = KeyPair::random();
+        let ip4 = Ipv4Addr::new(10, 0, 0, 1);
+        let ip6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let decode = |ips: &[(&str, &[u8])]| {
+            NodeRecord::from_rlp(&Rlp::new(&record_with_ips(&key_pair, ips)))
+                .ok()
+                .map(|r| r.endpoint.address.ip())
+        };
+
+        let both = decode(&[("ip", &ip4.octets()), ("ip6", &ip6.octets())]).unwrap();
+        assert_eq!(both, IpAddr::V4(ip4));
+        assert_eq!(decode(&[("ip6", &ip6.octets())]).unwrap(), IpAddr::V6(ip6));
+        // an IPv6 address under the IPv4 key is invalid
+        assert!(decode(&[("ip", &ip6.octets())]).is_none());
+    }
+}
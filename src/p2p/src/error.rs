@@ -11,10 +11,13 @@ pub enum Error {
 
     // ======== External Package Error========
     CommonError(common::Error),
+    SerializationError(common::SerializationError),
     RlpError(rlp::Error),
     TokioVecError(tokio::sync::mpsc::error::SendError<Vec<u8>>),
     TokioVecSocketError(tokio::sync::mpsc::error::SendError<(Vec<u8>, std::net::SocketAddr)>),
     TokioRequestError(tokio::sync::mpsc::error::SendError<Request>),
+    #[cfg(feature = "serialize")]
+    JsonError(serde_json::Error),
 
     // =========== Socket Related ==========
     SocketNotReady,
@@ -31,15 +34,31 @@ pub enum Error {
     InvalidPacket,
     PongExpired,
     NodeIsSelf,
+    /// The discovery service loop has exited
+    ServiceStopped,
+    /// The node record is malformed or its signature does not match
+    InvalidNodeRecord,
     NodeNotFoundInBucket {
         entry: NodeEntry,
         distance: usize,
     },
 
+    // =========== Dialer Related ==========
+    /// The peer failed recently and is still backed off
+    DialBackoff,
+    /// The peer did not accept the connection or finish the handshake in time
+    DialTimeout,
+    /// The peer already has a session or a dial to it is in flight
+    AlreadyConnected,
+
     // =========== Handshake Related ==========
     BadProtocol,
     PacketHashNotMatch,
     ExpectedReceivedSizeNotSet,
+    /// The MAC of a received frame does not match
+    FrameMacNotMatch,
+    /// The remote nonce was already used in a recent handshake
+    NonceReplayed,
 }
 
 impl From<common::Error> for Error {
@@ -48,6 +67,12 @@ impl From<common::Error> for Error {
     }
 }
 
+impl From<common::SerializationError> for Error {
+    fn from(e: common::SerializationError) -> Self {
+        Error::SerializationError(e)
+    }
+}
+
 impl From<rlp::Error> for Error {
     fn from(e: rlp::Error) -> Self {
         Error::RlpError(e)
@@ -71,3 +96,10 @@ impl From<tokio::sync::mpsc::error::SendError<Request>> for Error {
         Error::TokioRequestError(e)
     }
 }
+
+#[cfg(feature = "serialize")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+}
@@ -1,9 +1,11 @@
-use crate::connection::{Bytes, Connection};
+use crate::connection::{Bytes, Connection, FrameCodec, Secrets, FRAME_HEADER_SIZE};
 use crate::error::Error;
-use common::{agree, decrypt, encrypt, sign, KeyPair, Public, H256};
+use common::{agree, decrypt, encrypt, keccak_concat, random_nonce, sign, KeyPair, Public, H256};
 use rand::Rng;
 use rlp::{RLPStream, Rlp};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 // const V4_AUTH_PACKET_SIZE: usize = 307;
@@ -12,6 +14,8 @@ const V4_ACK_PACKET_SIZE: usize = 210;
 const PROTOCOL_VERSION: u64 = 4;
 // Amount of bytes added when encrypting with encryptECIES.
 const ECIES_OVERHEAD: usize = 113;
+/// How long a remote nonce is remembered to detect replays
+const NONCE_TTL: Duration = Duration::from_secs(300);
 
 /// The different states during a handshake
 #[derive(PartialEq, Eq, Debug)]
@@ -30,14 +34,51 @@ pub(crate) enum HandshakeState {
     StartSession,
 }
 
+/// The remote nonces seen in recent handshakes, shared between handshakes
+/// so a replayed ack or auth packet can be rejected
+pub struct SeenNonces {
+    seen: Mutex<HashMap<H256, Instant>>,
+}
+
+impl SeenNonces {
+    pub fn new() -> Self {
+        Self {
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the nonce, failing if it was already seen within `NONCE_TTL`
+    pub(crate) fn insert(&self, nonce: H256) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().expect("nonce lock is never poisoned");
+        seen.retain(|_, at| now.duration_since(*at) < NONCE_TTL);
+        if seen.contains_key(&nonce) {
+            return Err(Error::NonceReplayed);
+        }
+        seen.insert(nonce, now);
+        Ok(())
+    }
+}
+
+impl Default for SeenNonces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Struct to handle the handshake with other eth nodes
 pub struct Handshake {
     inner: Arc<RwLock<HandshakeInner>>,
 }
 
 impl Handshake {
-    pub fn new(remote_node_pub: Public, connection: Connection, nonce: H256) -> Self {
-        let inner = HandshakeInner::new(remote_node_pub, nonce, connection);
+    /// Create a handshake with a freshly generated local nonce
+    pub fn new(
+        remote_node_pub: Public,
+        connection: Connection,
+        seen_nonces: Arc<SeenNonces>,
+    ) -> Self {
+        let inner = HandshakeInner::new(remote_node_pub, random_nonce(), connection, seen_nonces);
 
         Self {
             inner: Arc::new(RwLock::new(inner)),
@@ -58,6 +99,61 @@ impl Handshake {
 
         Ok(())
     }
+
+    /// Run the originating side of the handshake to completion
+    pub async fn originate(&self) -> Result<(), Error> {
+        let mut handshake = self.inner.write().await;
+        handshake.write_auth().await?;
+        handshake.read_ack().await?;
+        match handshake.state {
+            HandshakeState::StartSession => Ok(()),
+            _ => Err(Error::BadProtocol),
+        }
+    }
+
+    /// Send `payload` as a frame of the session, once the handshake completed
+    pub async fn write_frame(&self, payload: &[u8]) -> Result<(), Error> {
+        self.inner.write().await.write_frame(payload).await
+    }
+
+    /// Read the next frame of the session, none if the remote closed the connection
+    pub async fn read_frame(&self) -> Result<Option<Bytes>, Error> {
+        self.inner.write().await.read_frame().await
+    }
+}
+
+/// Derive the frame secrets from the agreement of the ephemeral keys and the nonces and
+/// packets of the handshake, `originated` when the local side sent the auth packet
+pub(crate) fn derive_secrets(
+    ephemeral_shared: &H256,
+    local_nonce: &H256,
+    remote_nonce: &H256,
+    originated: bool,
+    auth: &[u8],
+    ack: &[u8],
+) -> Secrets {
+    let (initiator_nonce, recipient_nonce) = if originated {
+        (local_nonce, remote_nonce)
+    } else {
+        (remote_nonce, local_nonce)
+    };
+    let nonce_hash = keccak_concat(&[recipient_nonce.as_bytes(), initiator_nonce.as_bytes()]);
+    let shared = keccak_concat(&[ephemeral_shared.as_bytes(), nonce_hash.as_bytes()]);
+    let aes = keccak_concat(&[ephemeral_shared.as_bytes(), shared.as_bytes()]);
+    let mac = keccak_concat(&[ephemeral_shared.as_bytes(), aes.as_bytes()]);
+
+    let (sent, received) = if originated { (auth, ack) } else { (ack, auth) };
+    let mut egress_seed = (mac ^ *remote_nonce).as_bytes().to_vec();
+    egress_seed.extend_from_slice(sent);
+    let mut ingress_seed = (mac ^ *local_nonce).as_bytes().to_vec();
+    ingress_seed.extend_from_slice(received);
+
+    Secrets {
+        aes,
+        mac,
+        egress_seed,
+        ingress_seed,
+    }
 }
 
 /// The inner structure for Handshake
@@ -74,25 +170,35 @@ pub(crate) struct HandshakeInner {
     /// Remote `RLPx` protocol version.
     pub remote_version: u64,
     auth_cipher: Bytes,
-    // /// A copy of received encrypted ack packet
-    // ack_cipher: Bytes,
+    /// A copy of received encrypted ack packet
+    ack_cipher: Bytes,
     state: HandshakeState,
+    /// Frames the session once the handshake completed
+    codec: Option<FrameCodec>,
     connection: Connection,
+    seen_nonces: Arc<SeenNonces>,
 }
 
 impl HandshakeInner {
-    pub fn new(remote_node_pub: Public, nonce: H256, connection: Connection) -> Self {
+    pub fn new(
+        remote_node_pub: Public,
+        nonce: H256,
+        connection: Connection,
+        seen_nonces: Arc<SeenNonces>,
+    ) -> Self {
         Self {
             remote_node_pub,
             key_pair: KeyPair::random(),
             nonce,
             auth_cipher: Default::default(),
-            // ack_cipher: Default::default(),
+            ack_cipher: Default::default(),
             state: HandshakeState::New,
+            codec: None,
             remote_ephemeral: Public::default(),
             remote_nonce: H256::default(),
             remote_version: 0,
             connection,
+            seen_nonces,
         }
     }
 
@@ -177,11 +283,47 @@ impl HandshakeInner {
         self.remote_ephemeral = rlp.val_at(0)?;
         self.remote_nonce = rlp.val_at(1)?;
         self.remote_version = rlp.val_at(2)?;
+        self.seen_nonces.insert(self.remote_nonce)?;
+
+        self.ack_cipher.extend_from_slice(&auth_data);
+        self.ack_cipher.extend_from_slice(&encrypted);
+        let ephemeral_shared = agree(self.key_pair.secret(), &self.remote_ephemeral)?;
+        let secrets = derive_secrets(
+            ephemeral_shared.as_ref(),
+            &self.nonce,
+            &self.remote_nonce,
+            true,
+            &self.auth_cipher,
+            &self.ack_cipher,
+        );
+        self.codec = Some(FrameCodec::new(&secrets));
         self.state = HandshakeState::StartSession;
 
         Ok(())
     }
 
+    async fn write_frame(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let codec = self.codec.as_mut().ok_or(Error::BadProtocol)?;
+        let frame = codec.encode(payload)?;
+        self.connection.write(&frame).await
+    }
+
+    async fn read_frame(&mut self) -> Result<Option<Bytes>, Error> {
+        let codec = self.codec.as_mut().ok_or(Error::BadProtocol)?;
+        self.connection.expect(FRAME_HEADER_SIZE);
+        let header = match self.connection.readable().await? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let size = codec.decode_header(&header)?;
+        self.connection.expect(FrameCodec::body_size(size));
+        let body = match self.connection.readable().await? {
+            Some(v) => v,
+            None => return Err(Error::ConnectionResetByPeer),
+        };
+        codec.decode_body(&body, size).map(Some)
+    }
+
     // async fn read_auth(&mut self) -> Result<(), Error> {
     //     log::info!(
     //         "parsing reading auth from remote: {:?}",
@@ -219,10 +361,14 @@ impl HandshakeInner {
 
 #[cfg(test)]
 mod tests {
-    use crate::handshake::PROTOCOL_VERSION;
-    use common::{agree, sign, KeyPair, Public, Secret, H256};
+    use crate::connection::{Connection, FrameCodec, FRAME_HEADER_SIZE};
+    use crate::handshake::{derive_secrets, Handshake, SeenNonces, ECIES_OVERHEAD, PROTOCOL_VERSION};
+    use common::{agree, decrypt, encrypt, random_nonce, sign, KeyPair, Public, Secret, H256};
     use rlp::{RLPStream, Rlp};
     use std::str::FromStr;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
 
     /// Helper function to perform RLP encoding on the some of the auth data
     fn rlp_encode(
@@ -292,6 +438,57 @@ mod tests {
         println!("{:?}", ack.as_bytes().len());
     }
 
+    #[tokio::test]
+    async fn session_frames_works() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let key_pair = KeyPair::random();
+        let remote_pub = *key_pair.public();
+
+        // the recipient side, answering the auth then framing with its own secrets
+        let responder = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut prefix = [0u8; 2];
+            socket.read_exact(&mut prefix).await.unwrap();
+            let mut encrypted = vec![0u8; u16::from_be_bytes(prefix) as usize];
+            socket.read_exact(&mut encrypted).await.unwrap();
+            let auth = decrypt(key_pair.secret(), &prefix, &encrypted).unwrap();
+            let rlp = Rlp::new(&auth);
+            let initiator_pub: Public = rlp.val_at(1).unwrap();
+            let initiator_nonce: H256 = rlp.val_at(2).unwrap();
+            let auth_packet = [&prefix[..], &encrypted].concat();
+
+            let (ephemeral, nonce) = (KeyPair::random(), random_nonce());
+            let mut rlp = RLPStream::new_list(3);
+            rlp.append(ephemeral.public());
+            rlp.append(&nonce);
+            rlp.append(&PROTOCOL_VERSION);
+            let ack = rlp.out();
+            let prefix = ((ack.len() + ECIES_OVERHEAD) as u16).to_be_bytes();
+            let ack_packet = [&prefix[..], &encrypt(&initiator_pub, &prefix, &ack).unwrap()].concat();
+            socket.write_all(&ack_packet).await.unwrap();
+
+            let shared = agree(ephemeral.secret(), &initiator_pub).unwrap();
+            let secrets = derive_secrets(shared.as_ref(), &nonce, &initiator_nonce, false, &auth_packet, &ack_packet);
+            let mut codec = FrameCodec::new(&secrets);
+
+            let mut header = [0u8; FRAME_HEADER_SIZE];
+            socket.read_exact(&mut header).await.unwrap();
+            let size = codec.decode_header(&header).unwrap();
+            let mut body = vec![0u8; FrameCodec::body_size(size)];
+            socket.read_exact(&mut body).await.unwrap();
+            assert_eq!(codec.decode_body(&body, size).unwrap(), b"hello");
+            socket.write_all(&codec.encode(b"world").unwrap()).await.unwrap();
+        });
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let handshake = Handshake::new(remote_pub, Connection::new(stream), Arc::new(SeenNonces::new()));
+        handshake.originate().await.unwrap();
+        handshake.write_frame(b"hello").await.unwrap();
+        assert_eq!(handshake.read_frame().await.unwrap().unwrap(), b"world");
+        responder.await.unwrap();
+    }
+
     #[test]
     fn test_rlp_works() {
         let v = vec![
@@ -2,15 +2,19 @@
 #![feature(async_closure)]
 
 pub use config::{HostInfo, NetowkrConfig};
-pub use connection::Connection;
-pub use discovery::Discovery;
-pub use handshake::Handshake;
+pub use connection::{Connection, FrameCodec, Secrets};
+pub use dialer::Dialer;
+pub use discovery::{Discovery, DiscoveryConfig, DiscoveryStats};
+pub use enr::NodeRecord;
+pub use handshake::{Handshake, SeenNonces};
 pub use node::{NodeEndpoint, NodeEntry};
 pub use node_table::NodeTable;
 
 mod config;
 mod connection;
+mod dialer;
 mod discovery;
+mod enr;
 mod error;
 mod handshake;
 mod node;
@@ -10,6 +10,7 @@ pub type NodeId = H512;
 
 /// Node address info
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct NodeEndpoint {
     /// IP(V4 or V6) address
     pub address: SocketAddr,
@@ -104,6 +105,7 @@ impl NodeEndpoint {
 
 /// The node entry to store in database storage
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct NodeEntry {
     id: NodeId,
     endpoint: NodeEndpoint,
@@ -2,7 +2,14 @@ use crate::node::NodeId;
 use crate::{NodeEndpoint, NodeEntry};
 use kv_storage::{DBStorage, MemoryDB};
 use std::collections::HashMap;
-// use std::time::SystemTime;
+use std::time::SystemTime;
+#[cfg(feature = "serialize")]
+use {
+    crate::error::Error,
+    common::{from_vec_versioned, to_vec_versioned},
+    std::collections::HashSet,
+    std::time::{Duration, UNIX_EPOCH},
+};
 
 /// The different types of a Peer
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -15,7 +22,7 @@ pub(crate) enum PeerType {
 /// that was either a success or a failure.
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum NodeContact {
-    // Success(SystemTime),
+    Success(SystemTime),
     // Failure(SystemTime),
 }
 
@@ -35,6 +42,40 @@ impl Node {
             last_contact: None,
         }
     }
+
+    /// The time of the last successful contact with the node
+    pub fn last_seen(&self) -> Option<SystemTime> {
+        match self.last_contact {
+            Some(NodeContact::Success(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Seconds since unix epoch of the last successful contact
+    #[cfg(feature = "serialize")]
+    fn last_seen_secs(&self) -> Option<u64> {
+        self.last_seen()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+    }
+
+    #[cfg(feature = "serialize")]
+    fn with_last_seen_secs(entry: NodeEntry, last_seen: Option<u64>) -> Self {
+        let (id, endpoint) = entry.into();
+        let mut n = Node::new(id, endpoint);
+        n.last_contact = last_seen.map(|s| NodeContact::Success(UNIX_EPOCH + Duration::from_secs(s)));
+        n
+    }
+}
+
+/// The JSON representation of a node in the exported node list
+#[cfg(feature = "serialize")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NodeRecord {
+    #[serde(flatten)]
+    entry: NodeEntry,
+    /// Seconds since unix epoch of the last successful contact
+    last_seen: Option<u64>,
 }
 
 pub struct NodeTable {
@@ -55,7 +96,14 @@ impl NodeTable {
         Self::new(Box::new(inner))
     }
 
-    // pub fn remove(&mut self, nodes: Vec<NodeEntry>) {}
+    /// Remove the node, returns whether it was in the table
+    pub fn remove(&mut self, id: &NodeId) -> bool {
+        self.nodes.remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: &NodeId) -> bool {
+        self.nodes.contains_key(id)
+    }
 
     pub fn upsert(&mut self, entries: Vec<NodeEntry>) {
         for e in entries {
@@ -65,6 +113,127 @@ impl NodeTable {
         }
     }
 
-    /// Flush in memory nodes to db
-    pub fn flush(&mut self) {}
+    /// Import the nodes from a JSON node list produced by `export_json`
+    #[cfg(feature = "serialize")]
+    pub fn import_json(&mut self, json: &str) -> Result<(), Error> {
+        let records: Vec<NodeRecord> = serde_json::from_str(json)?;
+        for r in records {
+            let n = Node::with_last_seen_secs(r.entry, r.last_seen);
+            self.nodes.insert(n.id, n);
+        }
+        Ok(())
+    }
+
+    /// Export the nodes as a JSON node list, ordered by node id
+    #[cfg(feature = "serialize")]
+    pub fn export_json(&self) -> String {
+        let mut records = self
+            .nodes
+            .values()
+            .map(|n| NodeRecord {
+                entry: NodeEntry::new(n.id, n.endpoint.clone()),
+                last_seen: n.last_seen_secs(),
+            })
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| a.entry.id().cmp(b.entry.id()));
+        serde_json::to_string(&records).expect("node records are always serializable")
+    }
+
+    /// Load the nodes persisted to the storage by `flush`. The storage has to list its keys.
+    #[cfg(feature = "serialize")]
+    pub fn load(storage: Box<dyn DBStorage>) -> Result<Self, Error> {
+        let mut table = Self::new(storage);
+        for key in table.storage.keys().ok_or(Error::NotImplemented)? {
+            let raw = match table.storage.get(&key) {
+                Some(v) => v,
+                None => continue,
+            };
+            let (entry, last_seen): (NodeEntry, Option<u64>) = from_vec_versioned(&raw)?;
+            let n = Node::with_last_seen_secs(entry, last_seen);
+            table.nodes.insert(n.id, n);
+        }
+        Ok(table)
+    }
+
+    /// Flush in memory nodes to db, each node a versioned record keyed by its id. The
+    /// records of the nodes removed since are deleted, so the storage has to list its keys.
+    #[cfg(feature = "serialize")]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let stored = self.storage.keys().ok_or(Error::NotImplemented)?;
+        let mut inserts = Vec::with_capacity(self.nodes.len());
+        for n in self.nodes.values() {
+            let record = (NodeEntry::new(n.id, n.endpoint.clone()), n.last_seen_secs());
+            inserts.push((n.id.as_bytes().to_vec(), to_vec_versioned(&record)?));
+        }
+        let kept: HashSet<&[u8]> = inserts.iter().map(|(k, _)| k.as_slice()).collect();
+        let removals = stored.into_iter().filter(|k| !kept.contains(k.as_slice())).collect();
+        self.storage.write_batch(inserts, removals);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "serialize"))]
+mod tests {
+    use crate::error::Error;
+    use crate::node::NodeId;
+    use crate::node_table::NodeContact;
+    use crate::{NodeEndpoint, NodeEntry, NodeTable};
+    use common::SerializationError;
+    use kv_storage::{DBStorage, MemoryDB};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn json_roundtrip_works() {
+        let mut table = NodeTable::new_in_memory();
+        let seen = NodeId::random();
+        table.upsert(vec![
+            NodeEntry::new(seen, NodeEndpoint::new("127.0.0.1", 30303)),
+            NodeEntry::new(NodeId::random(), NodeEndpoint::new("10.0.0.1", 30304)),
+        ]);
+        table.nodes.get_mut(&seen).unwrap().last_contact = Some(NodeContact::Success(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+
+        let json = table.export_json();
+        let mut imported = NodeTable::new_in_memory();
+        imported.import_json(&json).unwrap();
+
+        assert_eq!(imported.nodes.len(), 2);
+        assert_eq!(
+            imported.nodes[&seen].last_seen(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+        );
+        assert_eq!(imported.export_json(), json);
+    }
+
+    #[test]
+    fn flush_load_works() {
+        let mut table = NodeTable::new_in_memory();
+        let (seen, removed) = (NodeId::random(), NodeId::random());
+        table.upsert(vec![
+            NodeEntry::new(seen, NodeEndpoint::new("127.0.0.1", 30303)),
+            NodeEntry::new(removed, NodeEndpoint::new("10.0.0.1", 30304)),
+        ]);
+        table.nodes.get_mut(&seen).unwrap().last_contact = Some(NodeContact::Success(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        table.flush().unwrap();
+
+        // a removed node is dropped from the storage on the next flush
+        table.remove(&removed);
+        table.flush().unwrap();
+        let storage = std::mem::replace(&mut table.storage, Box::new(MemoryDB::new()));
+        assert_eq!(storage.keys().unwrap().len(), 1);
+
+        let loaded = NodeTable::load(storage).unwrap();
+        assert_eq!(loaded.export_json(), table.export_json());
+
+        // a record of another format version is rejected
+        let mut storage = MemoryDB::new();
+        storage.insert(seen.as_bytes().to_vec(), vec![0]);
+        assert!(matches!(
+            NodeTable::load(Box::new(storage)),
+            Err(Error::SerializationError(SerializationError::UnsupportedVersion(0)))
+        ));
+    }
 }
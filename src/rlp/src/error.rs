@@ -1,13 +1,17 @@
+use core::fmt;
+
+/// Decoding errors. The `at` fields hold the byte offset, from the start of the
+/// decoded buffer, of the item that failed to decode.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Error {
     /// Data has additional bytes at the end of the valid RLP fragment.
     RlpIsTooBig,
     /// Data has too few bytes for valid RLP.
-    RlpIsTooShort,
+    RlpIsTooShort { at: usize },
     /// Expect an encoded list, RLP was something else.
-    RlpExpectedToBeList,
+    RlpExpectedToBeList { at: usize },
     /// Expect encoded data, RLP was something else.
-    RlpExpectedToBeData,
+    RlpExpectedToBeData { at: usize },
     /// Expected a different size list.
     RlpIncorrectListLen,
     /// Data length number has a prefixed zero byte, invalid for numbers.
@@ -17,9 +21,49 @@ pub enum Error {
     /// Non-canonical (longer than necessary) representation used for data or list.
     RlpInvalidIndirection,
     /// Declared length is inconsistent with data specified after.
-    RlpInconsistentLengthAndData,
+    RlpInconsistentLengthAndData { at: usize },
     /// Declared length is invalid and results in overflow
     RlpInvalidLength,
+    /// The input is not a valid hex string.
+    InvalidHex,
     /// Custom rlp decoding error.
     Custom(&'static str),
-}
\ No newline at end of file
+}
+impl Error {
+    /// Shift the offset of a positional error by `n` bytes, used when an error found
+    /// in a sub slice is reported relative to the enclosing buffer.
+    pub(crate) fn offset_by(self, n: usize) -> Self {
+        match self {
+            Error::RlpIsTooShort { at } => Error::RlpIsTooShort { at: at + n },
+            Error::RlpExpectedToBeList { at } => Error::RlpExpectedToBeList { at: at + n },
+            Error::RlpExpectedToBeData { at } => Error::RlpExpectedToBeData { at: at + n },
+            Error::RlpInconsistentLengthAndData { at } => {
+                Error::RlpInconsistentLengthAndData { at: at + n }
+            }
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RlpIsTooBig => write!(f, "rlp has extra bytes after the item"),
+            Error::RlpIsTooShort { at } => write!(f, "rlp is too short at byte {}", at),
+            Error::RlpExpectedToBeList { at } => write!(f, "expected an rlp list at byte {}", at),
+            Error::RlpExpectedToBeData { at } => write!(f, "expected rlp data at byte {}", at),
+            Error::RlpIncorrectListLen => write!(f, "rlp list has an unexpected length"),
+            Error::RlpDataLenWithZeroPrefix => write!(f, "rlp data length has a zero prefix"),
+            Error::RlpListLenWithZeroPrefix => write!(f, "rlp list length has a zero prefix"),
+            Error::RlpInvalidIndirection => write!(f, "non-canonical rlp encoding"),
+            Error::RlpInconsistentLengthAndData { at } => {
+                write!(f, "rlp length does not match the data at byte {}", at)
+            }
+            Error::RlpInvalidLength => write!(f, "rlp length overflows"),
+            Error::InvalidHex => write!(f, "invalid hex string"),
+            Error::Custom(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
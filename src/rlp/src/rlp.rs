@@ -92,7 +92,7 @@ impl RLPStream {
     /// ```
     /// use rlp::RLPStream;
     /// let mut stream = RLPStream::new_list(2);
-    /// stream.append_empty_data().append_empty_data();
+    /// stream.append_empty().append_empty();
     /// let out = stream.out();
     /// assert_eq!(out, vec![0xc2, 0x80, 0x80]);
     /// ```
@@ -108,6 +108,22 @@ impl RLPStream {
         self
     }
 
+    /// Appends already encoded RLP holding `item_count` items, e.g. a whole encoded
+    /// list nested as one item. The bytes are copied as is, not encoded as a string.
+    /// ```
+    /// use rlp::RLPStream;
+    /// let mut stream = RLPStream::new_list(2);
+    /// stream.append_rlp(&[0x83, 0x63, 0x61, 0x74, 0x80], 2);
+    /// assert_eq!(stream.out(), vec![0xc5, 0x83, 0x63, 0x61, 0x74, 0x80]);
+    /// ```
+    pub fn append_rlp(&mut self, raw: &[u8], item_count: usize) -> &mut Self {
+        self.data.extend_from_slice(raw);
+        if item_count > 0 {
+            self.list_appended(item_count);
+        }
+        self
+    }
+
     /// Write iterator into the stream. Should be invoked only by Encodable
     pub fn write_iter<I: Iterator<Item=u8>>(&mut self, mut iter: I) {
         let len = match iter.size_hint() {
@@ -120,7 +136,7 @@ impl RLPStream {
         // refer to https://eth.wiki/fundamentals/rlp
         match len {
             0 => self.data.push(STR_OFFSET),
-            1..55 => {
+            1..=55 => {
                 let first = iter.next().expect("invalid iter size");
                 if len == 1 && first < STR_OFFSET {
                     self.data.push(first);
@@ -163,7 +179,7 @@ fn to_binary(x: usize, data: &mut Vec<u8>) {
 
 fn encode_length(len: usize, offset: u8) -> Vec<u8> {
     match len {
-        0..55 => vec![len as u8 + offset],
+        0..=55 => vec![len as u8 + offset],
         _ => {
             let mut data = vec![];
             to_binary(len, &mut data);
@@ -250,6 +266,19 @@ mod tests {
         assert_eq!(stream.out(), vec![0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0 ]);
     }
 
+    #[test]
+    fn short_form_boundary_works() {
+        // 55 bytes is the longest payload that still uses the single byte header
+        let mut stream = RLPStream::new();
+        stream.write_iter([1u8; 55].into_iter());
+        assert_eq!(stream.out()[0], STR_OFFSET + 55);
+
+        let mut stream = RLPStream::new_list(1);
+        stream.append(&vec![1u8; 54]);
+        let out = stream.out();
+        assert_eq!(out[..2], [0xf7, 0xb6]);
+        assert_eq!(crate::Rlp::new(&out).item_count(), Ok(1));
+    }
 
     #[test]
     fn append_empty_works() {
@@ -258,4 +287,29 @@ mod tests {
         let out = stream.out();
         assert_eq!(out, vec![0xc2, 0x80, 0x80]);
     }
+
+    #[test]
+    fn append_rlp_works() {
+        let mut inner = RLPStream::new_list(2);
+        inner.append(&"dog").append(&"pig");
+        let inner = inner.out();
+
+        // [ "cat", [ "dog", "pig" ], "" ]
+        let mut stream = RLPStream::new_list(3);
+        stream.append(&"cat").append_rlp(&inner, 1).append_empty();
+        let out = stream.out();
+        let mut expected = vec![0xce, 0x83, 0x63, 0x61, 0x74];
+        expected.extend(&inner);
+        expected.push(0x80);
+        assert_eq!(out, expected);
+
+        let rlp = crate::Rlp::new(&out);
+        assert_eq!(rlp.item_count(), Ok(3));
+        assert_eq!(rlp.at(1).unwrap().item_count(), Ok(2));
+
+        // the items of a list can be spliced in without nesting them
+        let mut stream = RLPStream::new_list(2);
+        stream.append_rlp(&inner[1..], 2);
+        assert_eq!(stream.out(), inner);
+    }
 }
\ No newline at end of file
@@ -41,11 +41,11 @@ fn calculate_payload_info(header_bytes: &[u8], len_of_len: usize) -> Result<Payl
     let header_len = 1 + len_of_len;
     match header_bytes.get(1) {
         Some(&0) => return Err(Error::RlpDataLenWithZeroPrefix),
-        None => return Err(Error::RlpIsTooShort),
+        None => return Err(Error::RlpIsTooShort { at: 0 }),
         _ => (),
     }
     if header_bytes.len() < header_len {
-        return Err(Error::RlpIsTooShort);
+        return Err(Error::RlpIsTooShort { at: 0 });
     }
     let value_len = decode_usize(&header_bytes[1..header_len])?;
     if value_len <= 55 {
@@ -66,7 +66,7 @@ impl PayloadInfo {
 
     /// Create a new object from the given bytes RLP. The bytes
     pub fn from(header_bytes: &[u8]) -> Result<PayloadInfo, Error> {
-        let l = *header_bytes.first().ok_or_else(|| Error::RlpIsTooShort)?;
+        let l = *header_bytes.first().ok_or(Error::RlpIsTooShort { at: 0 })?;
         if l <= 0x7f {
             Ok(PayloadInfo::new(0, 1))
         } else if l <= 0xb7 {
@@ -92,6 +92,8 @@ impl PayloadInfo {
 #[derive(Debug, Clone)]
 pub struct Rlp<'a> {
     bytes: &'a [u8],
+    /// Position of `bytes` in the outermost decoded buffer, for error reporting
+    offset: usize,
     offset_cache: Cell<Option<OffsetCache>>,
     count_cache: Cell<Option<usize>>,
 }
@@ -116,7 +118,23 @@ pub struct Rlp<'a> {
 
 impl<'a> Rlp<'a> {
     pub const fn new(bytes: &'a [u8]) -> Rlp<'a> {
-        Rlp { bytes, offset_cache: Cell::new(None), count_cache: Cell::new(None) }
+        Self::new_at(bytes, 0)
+    }
+
+    const fn new_at(bytes: &'a [u8], offset: usize) -> Rlp<'a> {
+        Rlp { bytes, offset, offset_cache: Cell::new(None), count_cache: Cell::new(None) }
+    }
+
+    /// The byte offset of this item in the outermost decoded buffer
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Decode a hex string, with or without the `0x` prefix, into the raw bytes
+    /// to be viewed with `Rlp::new`.
+    pub fn from_hex(s: &str) -> Result<Vec<u8>, Error> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        hex::decode(s).map_err(|_| Error::InvalidHex)
     }
 
     pub fn as_raw<'view>(&'view self) -> &'a [u8]
@@ -138,14 +156,14 @@ impl<'a> Rlp<'a> {
     }
 
     pub fn payload_info(&self) -> Result<PayloadInfo, Error> {
-        BasicDecoder::payload_info(self.bytes)
+        BasicDecoder::payload_info(self.bytes).map_err(|e| e.offset_by(self.offset))
     }
 
     pub fn data<'view>(&'view self) -> Result<&'a [u8], Error>
         where
             'a: 'view,
     {
-        let pi = BasicDecoder::payload_info(self.bytes)?;
+        let pi = self.payload_info()?;
         Ok(&self.bytes[pi.header_len..(pi.header_len + pi.value_len)])
     }
 
@@ -160,7 +178,7 @@ impl<'a> Rlp<'a> {
                 }
             }
         } else {
-            Err(Error::RlpExpectedToBeList)
+            Err(Error::RlpExpectedToBeList { at: self.offset })
         }
     }
 
@@ -193,7 +211,7 @@ impl<'a> Rlp<'a> {
             'a: 'view,
     {
         if !self.is_list() {
-            return Err(Error::RlpExpectedToBeList);
+            return Err(Error::RlpExpectedToBeList { at: self.offset });
         }
 
         // move to cached position if its index is less or equal to
@@ -201,24 +219,30 @@ impl<'a> Rlp<'a> {
         let cache = self.offset_cache.get();
         let (bytes, indexes_to_skip, bytes_consumed) = match cache {
             Some(ref cache) if cache.index <= index => {
-                (Rlp::consume(self.bytes, cache.offset)?, index - cache.index, cache.offset)
+                let bytes = Rlp::consume(self.bytes, cache.offset)
+                    .map_err(|e| e.offset_by(self.offset))?;
+                (bytes, index - cache.index, cache.offset)
             }
             _ => {
-                let (bytes, consumed) = self.consume_list_payload()?;
+                let (bytes, consumed) = self
+                    .consume_list_payload()
+                    .map_err(|e| e.offset_by(self.offset))?;
                 (bytes, index, consumed)
             }
         };
 
         // skip up to x items
-        let (bytes, consumed) = Rlp::consume_items(bytes, indexes_to_skip)?;
+        let (bytes, consumed) = Rlp::consume_items(bytes, indexes_to_skip)
+            .map_err(|e| e.offset_by(self.offset + bytes_consumed))?;
 
         // update the cache
         let offset = bytes_consumed + consumed;
         self.offset_cache.set(Some(OffsetCache::new(index, offset)));
 
         // construct new rlp
-        let found = BasicDecoder::payload_info(bytes)?;
-        Ok((Rlp::new(&bytes[0..found.header_len + found.value_len]), offset))
+        let found = BasicDecoder::payload_info(bytes)
+            .map_err(|e| e.offset_by(self.offset + offset))?;
+        Ok((Rlp::new_at(&bytes[0..found.header_len + found.value_len], self.offset + offset), offset))
     }
 
     pub fn is_null(&self) -> bool {
@@ -253,10 +277,8 @@ impl<'a> Rlp<'a> {
         }
     }
 
-    pub fn iter<'view>(&'view self) -> RlpIterator<'a, 'view>
-        where
-            'a: 'view,
-    {
+    /// Iterate over the list items in one forward pass, yields nothing if this is not a list
+    pub fn iter(&self) -> RlpIterator<'a> {
         self.into_iter()
     }
 
@@ -289,26 +311,26 @@ impl<'a> Rlp<'a> {
     }
 
     pub fn decoder(&self) -> BasicDecoder {
-        BasicDecoder::new(self.bytes)
+        BasicDecoder { rlp: self.bytes, offset: self.offset }
     }
 
-    /// consumes first found prefix
+    /// consumes first found prefix, errors are relative to the start of `self.bytes`
     fn consume_list_payload(&self) -> Result<(&'a [u8], usize), Error> {
         let item = BasicDecoder::payload_info(self.bytes)?;
         if self.bytes.len() < (item.header_len + item.value_len) {
-            return Err(Error::RlpIsTooShort);
+            return Err(Error::RlpIsTooShort { at: 0 });
         }
         Ok((&self.bytes[item.header_len..item.header_len + item.value_len], item.header_len))
     }
 
-    /// consumes fixed number of items
+    /// consumes fixed number of items, errors are relative to the start of `bytes`
     fn consume_items(bytes: &'a [u8], items: usize) -> Result<(&'a [u8], usize), Error> {
         let mut result = bytes;
         let mut consumed = 0;
         for _ in 0..items {
-            let i = BasicDecoder::payload_info(result)?;
+            let i = BasicDecoder::payload_info(result).map_err(|e| e.offset_by(consumed))?;
             let to_consume = i.header_len + i.value_len;
-            result = Rlp::consume(result, to_consume)?;
+            result = Rlp::consume(result, to_consume).map_err(|e| e.offset_by(consumed))?;
             consumed += to_consume;
         }
         Ok((result, consumed))
@@ -319,58 +341,70 @@ impl<'a> Rlp<'a> {
         if bytes.len() >= len {
             Ok(&bytes[len..])
         } else {
-            Err(Error::RlpIsTooShort)
+            Err(Error::RlpIsTooShort { at: 0 })
         }
     }
 }
 
 /// Iterator over rlp-slice list elements.
-pub struct RlpIterator<'a, 'view>
-    where
-        'a: 'view,
-{
-    rlp: &'view Rlp<'a>,
-    index: usize,
+///
+/// Keeps a cursor into the list payload, so each item is parsed once.
+pub struct RlpIterator<'a> {
+    /// The payload not yet iterated over
+    bytes: &'a [u8],
+    /// Position of `bytes` in the outermost decoded buffer
+    offset: usize,
 }
 
-impl<'a, 'view> IntoIterator for &'view Rlp<'a>
-    where
-        'a: 'view,
-{
+impl<'a> IntoIterator for &Rlp<'a> {
     type Item = Rlp<'a>;
-    type IntoIter = RlpIterator<'a, 'view>;
+    type IntoIter = RlpIterator<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        RlpIterator { rlp: self, index: 0 }
+        match self.is_list().then(|| self.consume_list_payload()) {
+            Some(Ok((bytes, header_len))) => RlpIterator { bytes, offset: self.offset + header_len },
+            _ => RlpIterator { bytes: &[], offset: self.offset },
+        }
     }
 }
 
-impl<'a, 'view> Iterator for RlpIterator<'a, 'view> {
+impl<'a> Iterator for RlpIterator<'a> {
     type Item = Rlp<'a>;
 
     fn next(&mut self) -> Option<Rlp<'a>> {
-        let index = self.index;
-        let result = self.rlp.at(index).ok();
-        self.index += 1;
-        result
+        if self.bytes.is_empty() {
+            return None;
+        }
+        // a malformed item ends the iteration
+        let Ok(item) = BasicDecoder::payload_info(self.bytes) else {
+            self.bytes = &[];
+            return None;
+        };
+        let (found, rest) = self.bytes.split_at(item.total());
+        let rlp = Rlp::new_at(found, self.offset);
+        self.bytes = rest;
+        self.offset += found.len();
+        Some(rlp)
     }
 }
 
 pub struct BasicDecoder<'a> {
     rlp: &'a [u8],
+    /// Position of `rlp` in the outermost decoded buffer
+    offset: usize,
 }
 
 impl<'a> BasicDecoder<'a> {
     pub const fn new(rlp: &'a [u8]) -> BasicDecoder<'a> {
-        BasicDecoder { rlp }
+        BasicDecoder { rlp, offset: 0 }
     }
 
-    /// Return first item info.
+    /// Return first item info, errors are relative to the start of `bytes`.
     fn payload_info(bytes: &[u8]) -> Result<PayloadInfo, Error> {
         let item = PayloadInfo::from(bytes)?;
         match item.header_len.checked_add(item.value_len) {
             Some(x) if x <= bytes.len() => Ok(item),
-            _ => Err(Error::RlpIsTooShort),
+            _ => Err(Error::RlpIsTooShort { at: 0 }),
         }
     }
 
@@ -380,14 +414,15 @@ impl<'a> BasicDecoder<'a> {
     {
         let bytes = self.rlp;
 
-        let l = *bytes.first().ok_or_else(|| Error::RlpIsTooShort)?;
+        let at = self.offset;
+        let l = *bytes.first().ok_or(Error::RlpIsTooShort { at })?;
 
         if l <= 0x7f {
             Ok(f(&[l])?)
         } else if l <= 0xb7 {
             let last_index_of = 1 + l as usize - 0x80;
             if bytes.len() < last_index_of {
-                return Err(Error::RlpInconsistentLengthAndData);
+                return Err(Error::RlpInconsistentLengthAndData { at });
             }
             let d = &bytes[1..last_index_of];
             if l == 0x81 && d[0] < 0x80 {
@@ -398,17 +433,93 @@ impl<'a> BasicDecoder<'a> {
             let len_of_len = l as usize - 0xb7;
             let begin_of_value = 1 as usize + len_of_len;
             if bytes.len() < begin_of_value {
-                return Err(Error::RlpInconsistentLengthAndData);
+                return Err(Error::RlpInconsistentLengthAndData { at });
             }
             let len = decode_usize(&bytes[1..begin_of_value])?;
 
             let last_index_of_value = begin_of_value.checked_add(len).ok_or(Error::RlpInvalidLength)?;
             if bytes.len() < last_index_of_value {
-                return Err(Error::RlpInconsistentLengthAndData);
+                return Err(Error::RlpInconsistentLengthAndData { at });
             }
             Ok(f(&bytes[begin_of_value..last_index_of_value])?)
         } else {
-            Err(Error::RlpExpectedToBeData)
+            Err(Error::RlpExpectedToBeData { at })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, RLPStream, Rlp};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn from_hex_works() {
+        let bytes = Rlp::from_hex("0xc88363617483646f67").unwrap();
+        let rlp = Rlp::new(&bytes);
+        assert_eq!(rlp.item_count().unwrap(), 2);
+        assert_eq!(rlp.at(0).unwrap().data().unwrap(), b"cat");
+        assert_eq!(rlp.at(1).unwrap().data().unwrap(), b"dog");
+
+        let bytes = Rlp::from_hex("820400").unwrap();
+        let v: u64 = Rlp::new(&bytes).as_val().unwrap();
+        assert_eq!(v, 1024);
+
+        assert_eq!(Rlp::from_hex("0xzz"), Err(Error::InvalidHex));
+    }
+
+    #[test]
+    fn error_offset_works() {
+        // ["cat", <3 byte string with no data>]
+        let bytes = [0xc5, 0x83, b'c', b'a', b't', 0x83];
+        let rlp = Rlp::new(&bytes);
+        assert_eq!(rlp.at(1).unwrap_err(), Error::RlpIsTooShort { at: 5 });
+        assert_eq!(rlp.at(1).unwrap_err().to_string(), "rlp is too short at byte 5");
+
+        // [[<3 byte string with no data>]]
+        let bytes = [0xc2, 0xc1, 0x83];
+        let inner = Rlp::new(&bytes).at(0).unwrap();
+        assert_eq!(inner.offset(), 1);
+        assert_eq!(inner.at(0).unwrap_err(), Error::RlpIsTooShort { at: 2 });
+
+        // ["cat", ["dog"]] decoding the list as data
+        let bytes = Rlp::from_hex("0xc983636174c483646f67").unwrap();
+        let rlp = Rlp::new(&bytes);
+        assert_eq!(rlp.val_at::<u64>(1).unwrap_err(), Error::RlpExpectedToBeData { at: 5 });
+        assert_eq!(rlp.at(0).unwrap().item_count().unwrap_err(), Error::RlpExpectedToBeList { at: 1 });
+    }
+
+    #[test]
+    fn iter_works() {
+        let mut stream = RLPStream::new_list(16);
+        for i in 0..16u64 {
+            stream.append(&(i * 100));
+        }
+        let bytes = stream.out();
+        let rlp = Rlp::new(&bytes);
+
+        let items: Vec<u64> = rlp.iter().map(|r| r.as_val().unwrap()).collect();
+        assert_eq!(items, (0..16u64).map(|i| i * 100).collect::<Vec<_>>());
+
+        // a single pass over a long list is quick, rescanning the payload for every item
+        // would take billions of header reads
+        let count = 100_000;
+        let mut stream = RLPStream::new_list(count);
+        for i in 0..count as u64 {
+            stream.append(&i);
         }
+        let bytes = stream.out();
+        let start = Instant::now();
+        assert_eq!(Rlp::new(&bytes).iter().count(), count);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // offsets of the yielded items stay absolute
+        let bytes = Rlp::from_hex("0xc88363617483646f67").unwrap();
+        let offsets: Vec<usize> = Rlp::new(&bytes).iter().map(|r| r.offset()).collect();
+        assert_eq!(offsets, vec![1, 5]);
+
+        // data items and malformed lists yield nothing past the broken item
+        assert_eq!(Rlp::new(&[0x83, b'c', b'a', b't']).iter().count(), 0);
+        assert_eq!(Rlp::new(&[0xc5, 0x83, b'c', b'a', b't', 0x83]).iter().count(), 1);
     }
 }
@@ -12,7 +12,10 @@ pub trait IoHandler<Message>: Send + Sync
 where
     Message: Send + Sync + 'static,
 {
+    /// Called once the handler is registered with the `IOService`
     fn initialize(&self) {}
+    /// Called when the handler is deregistered or the `IOService` is dropped
+    fn deinitialize(&self) {}
     // /// Initialize the handler
     // fn initialize(&self, _io: &IoContext<Message>) {}
     // /// Timer function called after a timeout created with `HandlerIo::timeout`.
@@ -3,6 +3,9 @@ mod service;
 mod handler;
 mod error;
 
+pub use crate::handler::{HandlerId, IoHandler};
+pub use crate::service::IOService;
+
 #[cfg(test)]
 mod tests {
     #[test]
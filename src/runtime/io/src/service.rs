@@ -1,23 +1,70 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, TryRecvError};
 use std::thread::JoinHandle;
 use std::time::Duration;
 use mio::{event, Events, Interest, Poll, Token};
 use mio::event::Event;
 use mio::net::{TcpListener, TcpStream};
-use slab::Slab;
 use crate::error::Error;
-use crate::handler::IoHandler;
+use crate::handler::{HandlerId, IoHandler};
 use common::ensure;
 
 const MAX_TOKEN: usize = 1024;
 
 /// Dispatch and manages the IO handlers
-pub struct IOService {}
+pub struct IOService<Message: Send + Sync + 'static> {
+    handlers: RwLock<HashMap<HandlerId, Arc<dyn IoHandler<Message>>>>,
+    /// The id of the next handler, ids are never reused so a stale one matches no handler
+    next_id: AtomicUsize,
+}
+
+impl<Message: Send + Sync + 'static> IOService<Message> {
+    pub fn new() -> Self {
+        Self {
+            handlers: RwLock::new(HashMap::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Register the handler and initialize it, the id is used to deregister it
+    pub fn register_handler(&self, handler: Arc<dyn IoHandler<Message>>) -> HandlerId {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.handlers.write().unwrap().insert(id, Arc::clone(&handler));
+        handler.initialize();
+        id
+    }
+
+    /// Deregister the handler and deinitialize it, returns false if the id is unknown
+    pub fn deregister_handler(&self, id: HandlerId) -> bool {
+        let handler = self.handlers.write().unwrap().remove(&id);
+        match handler {
+            Some(h) => {
+                h.deinitialize();
+                true
+            }
+            None => false,
+        }
+    }
+}
 
-impl IOService {}
+impl<Message: Send + Sync + 'static> Default for IOService<Message> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Message: Send + Sync + 'static> Drop for IOService<Message> {
+    fn drop(&mut self) {
+        // still deinitialize the handlers if a thread panicked holding the lock
+        let handlers = self.handlers.get_mut().unwrap_or_else(|e| e.into_inner());
+        for handler in handlers.values() {
+            handler.deinitialize();
+        }
+        handlers.clear();
+    }
+}
 
 pub enum NetworkIOMessage<Message> {
     /// A message to handle for the event loop
@@ -95,6 +142,66 @@ impl From<std::io::Error> for Error {
 
 #[cfg(test)]
 mod tests {
+    use crate::handler::IoHandler;
+    use crate::service::IOService;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct CountingHandler {
+        initialized: AtomicUsize,
+        deinitialized: AtomicUsize,
+    }
+
+    impl IoHandler<()> for CountingHandler {
+        fn initialize(&self) {
+            self.initialized.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn deinitialize(&self) {
+            self.deinitialized.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn register_handler_works() {
+        let first = Arc::new(CountingHandler::default());
+        let second = Arc::new(CountingHandler::default());
+        let service = IOService::<()>::new();
+
+        let first_id = service.register_handler(first.clone());
+        let second_id = service.register_handler(second.clone());
+        assert_ne!(first_id, second_id);
+        assert_eq!(first.initialized.load(Ordering::SeqCst), 1);
+        assert_eq!(second.initialized.load(Ordering::SeqCst), 1);
+
+        assert!(service.deregister_handler(first_id));
+        assert!(!service.deregister_handler(first_id));
+        assert_eq!(first.deinitialized.load(Ordering::SeqCst), 1);
+        assert_eq!(second.deinitialized.load(Ordering::SeqCst), 0);
+
+        // the remaining handlers are deinitialized once on drop
+        drop(service);
+        assert_eq!(first.deinitialized.load(Ordering::SeqCst), 1);
+        assert_eq!(second.deinitialized.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn stale_handler_id_works() {
+        let first = Arc::new(CountingHandler::default());
+        let second = Arc::new(CountingHandler::default());
+        let service = IOService::<()>::new();
+
+        let first_id = service.register_handler(first.clone());
+        assert!(service.deregister_handler(first_id));
+        let second_id = service.register_handler(second.clone());
+        assert_ne!(first_id, second_id);
+
+        // the id of the removed handler does not deregister the new one
+        assert!(!service.deregister_handler(first_id));
+        assert_eq!(second.deinitialized.load(Ordering::SeqCst), 0);
+    }
+
     #[test]
     fn slab_works() {
         let mut s = slab::Slab::new();
@@ -75,13 +75,29 @@ pub fn decode_nibbles(nibbles: &[u8], lo: usize, hi: usize, bytes: &mut Vec<u8>)
     }
 }
 
+/// Inverse of `hex_to_compact`. The flag nibble of the first byte tells whether the key
+/// is terminated (leaf) and whether it has an odd number of nibbles.
+pub fn compact_to_hex(compact: &[u8]) -> Vec<u8> {
+    if compact.is_empty() {
+        return vec![];
+    }
+    let mut base = key_bytes_to_hex(compact);
+    // delete terminator flag
+    if base[0] < 2 {
+        base.truncate(base.len() - 1);
+    }
+    // apply odd flag
+    let chop = 2 - (base[0] & 1) as usize;
+    base.split_off(chop)
+}
+
 fn has_term(hex: &[u8]) -> bool {
     !hex.is_empty() && hex[hex.len() - 1] == TERMINAL
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::encoding::{hex_to_compact, key_bytes_to_hex};
+    use crate::encoding::{compact_to_hex, hex_to_compact, key_bytes_to_hex};
 
     #[test]
     fn key_bytes_to_hex_works() {
@@ -110,4 +126,42 @@ mod tests {
         assert_eq!(hex_to_compact(&[16]), vec![0x20]);
         assert_eq!(hex_to_compact(&[1, 2, 3, 4, 5]), vec![0x11, 0x23, 0x45]);
     }
+
+    #[test]
+    fn compact_to_hex_works() {
+        let vectors: [(&[u8], &[u8]); 6] = [
+            (&[], &[0x00]),
+            (&[16], &[0x20]),
+            // odd length, no terminator
+            (&[1, 2, 3, 4, 5], &[0x11, 0x23, 0x45]),
+            // even length, no terminator
+            (&[0, 1, 2, 3, 4, 5], &[0x00, 0x01, 0x23, 0x45]),
+            // odd length, terminator
+            (&[15, 1, 12, 11, 8, 16], &[0x3f, 0x1c, 0xb8]),
+            // even length, terminator
+            (&[0, 15, 1, 12, 11, 8, 16], &[0x20, 0x0f, 0x1c, 0xb8]),
+        ];
+        for (hex, compact) in vectors {
+            assert_eq!(hex_to_compact(hex), compact);
+            assert_eq!(compact_to_hex(compact), hex);
+        }
+        assert!(compact_to_hex(&[]).is_empty());
+    }
+
+    #[test]
+    fn compact_round_trip_works() {
+        for key in [&b""[..], b"f", b"foo", b"\x00\xff\x10"] {
+            // leaf keys carry the terminator
+            let leaf = key_bytes_to_hex(key);
+            assert_eq!(compact_to_hex(&hex_to_compact(&leaf)), leaf);
+
+            // extension keys do not, and may have an odd number of nibbles
+            let ext = &leaf[..leaf.len() - 1];
+            assert_eq!(compact_to_hex(&hex_to_compact(ext)), ext);
+            if !ext.is_empty() {
+                let odd = &ext[1..];
+                assert_eq!(compact_to_hex(&hex_to_compact(odd)), odd);
+            }
+        }
+    }
 }
@@ -10,4 +10,19 @@ pub enum Error {
     InvalidTrieState,
     /// The key is not found in the trie
     KeyNotExists,
+    /// The persisted bytes are not a valid node encoding
+    InvalidNodeEncoding,
+    /// A node referenced by hash is not in the db
+    NodeNotFound,
+    /// The proof nodes do not form a path from the root to the key
+    InvalidProof,
+    /// The operation reads the committed nodes but the trie has updates not committed yet
+    UncommittedChanges,
+    RlpError(rlp::Error),
+}
+
+impl From<rlp::Error> for Error {
+    fn from(e: rlp::Error) -> Self {
+        Error::RlpError(e)
+    }
 }
@@ -1,17 +1,30 @@
 use crate::encoding::hex_to_compact;
 use crate::node::{Node, CHILD_SIZE};
 use crate::storage::{Cache, MemorySlot, NodeLocation};
+use crate::trie::EMPTY_TRIE_ROOT;
 use common::{H256, Hasher, KeccakHasher};
 use kv_storage::DBStorage;
 use rlp::RLPStream;
 
 pub(crate) struct NodeHasher {
     hash_count: usize,
+    /// The encoded nodes held back for one `write_batch`, None writes them to the db as hashed
+    batch: Option<Vec<(Vec<u8>, Vec<u8>)>>,
 }
 
 impl NodeHasher {
     pub fn new() -> Self {
-        Self { hash_count: 0 }
+        Self { hash_count: 0, batch: None }
+    }
+
+    /// A hasher accumulating the nodes into a batch instead of inserting them one by one
+    pub fn new_batched() -> Self {
+        Self { hash_count: 0, batch: Some(vec![]) }
+    }
+
+    /// The nodes accumulated since the last call, empty if not batching
+    pub fn take_batch(&mut self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.batch.as_mut().map(std::mem::take).unwrap_or_default()
     }
 
     pub fn hash<H: DBStorage>(&mut self, node: Node, db: &mut H, cache: &mut Cache) -> H256 {
@@ -29,8 +42,7 @@ impl NodeHasher {
         cache: &mut Cache,
     ) -> ChildReference {
         match node {
-            // TODO: add empty node hash
-            Node::Empty => ChildReference::Hash(H256::default()),
+            Node::Empty => ChildReference::Hash(EMPTY_TRIE_ROOT),
             Node::Full { children } => self.hash_full_node_children(children, db, cache),
             Node::Short { key, val: node_loc } => {
                 let nd = self.take_node_loc(&node_loc, cache);
@@ -119,7 +131,10 @@ impl NodeHasher {
 
     fn insert_db_raw<H: DBStorage>(&mut self, encoded: Vec<u8>, db: &mut H) -> H256 {
         let hash = KeccakHasher::hash(&encoded);
-        db.insert(Vec::from(hash.as_bytes()), encoded);
+        match self.batch.as_mut() {
+            Some(batch) => batch.push((Vec::from(hash.as_bytes()), encoded)),
+            None => db.insert(Vec::from(hash.as_bytes()), encoded),
+        }
         self.hash_count += 1;
         hash
     }
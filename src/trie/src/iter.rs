@@ -0,0 +1,133 @@
+use crate::encoding::{decode_nibbles, TERMINAL};
+use crate::node::{Node, CHILD_SIZE};
+use crate::storage::{Cache, NodeLocation};
+use kv_storage::DBStorage;
+use std::cell::Ref;
+use std::rc::Rc;
+
+/// The cache memory locations are resolved in, the trie cache or the scratch
+/// cache holding the inline children of a node read from db
+#[derive(Clone)]
+enum CacheRef<'t> {
+    Trie(&'t Cache),
+    Scratch(Rc<Cache>),
+}
+
+impl CacheRef<'_> {
+    fn get_node(&self, index: usize) -> Node {
+        match self {
+            CacheRef::Trie(cache) => cache.get_node(index),
+            CacheRef::Scratch(cache) => cache.get_node(index),
+        }
+    }
+}
+
+/// Depth first iterator over the `(key, value)` pairs of a trie in key order
+pub struct TrieIterator<'t, H: DBStorage> {
+    db: Ref<'t, H>,
+    /// Nodes left to visit with the hex path leading to them, the next on top
+    stack: Vec<(NodeLocation, Vec<u8>, CacheRef<'t>)>,
+}
+
+impl<'t, H: DBStorage> TrieIterator<'t, H> {
+    pub(crate) fn new(db: Ref<'t, H>, cache: &'t Cache, root_loc: NodeLocation) -> Self {
+        Self {
+            db,
+            stack: vec![(root_loc, vec![], CacheRef::Trie(cache))],
+        }
+    }
+}
+
+impl<H: DBStorage> Iterator for TrieIterator<'_, H> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node_loc, path, cache)) = self.stack.pop() {
+            let (node, cache) = match node_loc {
+                NodeLocation::Persistence(h) => {
+                    let mut scratch = Cache::new();
+                    let node = match self.db.get(&h).map(|b| Node::decode(&b, &mut scratch)) {
+                        Some(Ok(node)) => node,
+                        Some(Err(_)) => {
+                            // the db is corrupted, nothing after this node can be trusted
+                            self.stack.clear();
+                            return None;
+                        }
+                        None => Node::Empty,
+                    };
+                    (node, CacheRef::Scratch(Rc::new(scratch)))
+                }
+                NodeLocation::Memory(i) => (cache.get_node(i), cache),
+                NodeLocation::None => continue,
+            };
+
+            match node {
+                Node::Empty => {}
+                Node::Short { key, val } => {
+                    let mut child_path = path;
+                    child_path.extend_from_slice(&key);
+                    self.stack.push((val, child_path, cache));
+                }
+                Node::Full { children } => {
+                    // the terminal holds the value of the shortest key, so it is visited first
+                    for i in (0..CHILD_SIZE).rev().skip(1).chain([CHILD_SIZE - 1]) {
+                        let mut child_path = path.clone();
+                        child_path.push(i as u8);
+                        self.stack.push((children[i], child_path, cache.clone()));
+                    }
+                }
+                Node::Value(val) => {
+                    let nibbles = path.strip_suffix(&[TERMINAL]).unwrap_or(&path);
+                    let mut key = Vec::with_capacity(nibbles.len() / 2);
+                    decode_nibbles(nibbles, 0, nibbles.len(), &mut key);
+                    return Some((key, val));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trie::Trie;
+    use kv_storage::MemoryDB;
+
+    #[test]
+    fn iter_works() {
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = [
+            (&b"doge"[..], &b"coin"[..]),
+            (b"do", b"verb"),
+            (b"horse", b"stallion"),
+            (b"dog", b"puppy"),
+            (b"fook", b"a value longer than thirty two bytes"),
+            (b"foo", b"bar"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+        .collect();
+
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        assert_eq!(trie.iter().next(), None);
+        for (k, v) in &entries {
+            trie.try_update(k, v).unwrap();
+        }
+        let uncommitted: Vec<_> = trie.iter().collect();
+        let root = trie.commit().unwrap();
+        let committed: Vec<_> = trie.iter().collect();
+
+        entries.sort();
+        assert_eq!(uncommitted, entries);
+        assert_eq!(committed, entries);
+
+        // partly loaded from db, partly updated in the cache
+        let mut trie = Trie::new_from_existing(&mut hash_db, root);
+        trie.try_update(b"cat", b"meow").unwrap();
+        trie.try_update(b"dog", b"hound").unwrap();
+        let keys: Vec<_> = trie.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys[0], b"cat".to_vec());
+        assert_eq!(keys[1..], entries.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>()[..]);
+        assert_eq!(trie.iter().find(|(k, _)| k == b"dog"), Some((b"dog".to_vec(), b"hound".to_vec())));
+    }
+}
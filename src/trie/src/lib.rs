@@ -1,16 +1,26 @@
 mod encoding;
 mod error;
 mod hasher;
+mod iter;
 mod node;
+mod proof;
+mod reader;
+mod refs;
+mod secure;
 mod storage;
 mod trie;
 
-pub use trie::Trie;
+pub use encoding::{compact_to_hex, hex_to_compact};
+pub use iter::TrieIterator;
+pub use proof::verify_proof;
+pub use reader::TrieReader;
+pub use secure::SecureTrie;
+pub use trie::{Trie, EMPTY_TRIE_ROOT};
 
 #[cfg(feature = "std")]
 mod rstd {
     pub use std::{
-        borrow, boxed, cmp, collections::VecDeque, convert, error::Error, fmt, hash, iter, marker,
+        borrow, boxed, cell, cmp, collections::VecDeque, convert, error::Error, fmt, hash, iter, marker,
         mem, ops, rc, result, vec,
     };
 }
@@ -1,19 +1,13 @@
-use crate::storage::NodeLocation;
-use common::{from_vec, to_vec, H256};
+use crate::encoding::{compact_to_hex, TERMINAL};
+use crate::error::Error;
+use crate::storage::{Cache, MemorySlot, NodeLocation};
+use common::{Hasher, KeccakHasher};
+use rlp::Rlp;
 use serde::{Deserialize, Serialize};
 
 // The length of children is 17 because of the termination symbol
 pub(crate) const CHILD_SIZE: usize = 17;
 
-/// The item to delete
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub(crate) enum DeleteItem {
-    /// It's a node type, need to hash first
-    Node(Node),
-    /// It's a hash, can delete from DB
-    Hash(H256),
-}
-
 /// The Node in the MPT.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
@@ -29,16 +23,107 @@ pub(crate) enum Node {
     Value(Vec<u8>),
 }
 
-#[cfg(any(feature = "std"))]
-impl From<Node> for Vec<u8> {
-    fn from(n: Node) -> Self {
-        to_vec(&n).unwrap()
+impl Node {
+    /// Decode a node persisted by the hasher. Inline children and values have no
+    /// location of their own, so they are inserted into `cache` as updated slots.
+    pub(crate) fn decode(data: &[u8], cache: &mut Cache) -> Result<Self, Error> {
+        let rlp = Rlp::new(data);
+        match rlp.item_count()? {
+            2 => {
+                let key = compact_to_hex(rlp.at(0)?.data()?);
+                let child = rlp.at(1)?;
+                let val = if key.last() == Some(&TERMINAL) {
+                    Self::value_loc(child.data()?, cache)
+                } else {
+                    Self::child_loc(&child, cache)?
+                };
+                Ok(Node::Short { key, val })
+            }
+            CHILD_SIZE => {
+                let mut children = [NodeLocation::None; CHILD_SIZE];
+                for (i, c) in children.iter_mut().enumerate().take(CHILD_SIZE - 1) {
+                    *c = Self::child_loc(&rlp.at(i)?, cache)?;
+                }
+                let terminal = rlp.at(CHILD_SIZE - 1)?;
+                if !terminal.is_empty() {
+                    children[CHILD_SIZE - 1] = Self::value_loc(terminal.data()?, cache);
+                }
+                Ok(Node::Full {
+                    children: Box::new(children),
+                })
+            }
+            _ => Err(Error::InvalidNodeEncoding),
+        }
+    }
+
+    fn value_loc(val: &[u8], cache: &mut Cache) -> NodeLocation {
+        NodeLocation::Memory(cache.insert(MemorySlot::Updated(Node::Value(val.to_vec()))))
+    }
+
+    /// A child is either empty, a hash reference or a node embedded in the parent
+    fn child_loc(child: &Rlp, cache: &mut Cache) -> Result<NodeLocation, Error> {
+        if child.is_list() {
+            let node = Self::decode(child.as_raw(), cache)?;
+            return Ok(NodeLocation::Memory(cache.insert(MemorySlot::Updated(node))));
+        }
+        match child.data()? {
+            [] => Ok(NodeLocation::None),
+            h if h.len() == KeccakHasher::LENGTH => {
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(h);
+                Ok(NodeLocation::Persistence(hash))
+            }
+            _ => Err(Error::InvalidNodeEncoding),
+        }
     }
 }
 
-#[cfg(any(feature = "std"))]
-impl From<Vec<u8>> for Node {
-    fn from(n: Vec<u8>) -> Self {
-        from_vec(&n).unwrap()
+#[cfg(test)]
+mod tests {
+    use crate::hasher::NodeHasher;
+    use crate::node::Node;
+    use crate::refs::node_keys;
+    use crate::storage::{Cache, NodeLocation};
+    use crate::trie::Trie;
+    use common::H256;
+    use kv_storage::{DBStorage, MemoryDB};
+
+    #[test]
+    fn decode_works() {
+        let mut db = MemoryDB::new();
+        let mut trie = Trie::new(&mut db);
+        for (k, v) in [
+            (&b"foo"[..], &b"bar"[..]),
+            (b"fook", b"barr"),
+            (b"fooo", b"a value longer than thirty two bytes"),
+            (b"dog", b"puppy"),
+            (b"do", b"verb"),
+        ] {
+            trie.try_update(k, v).unwrap();
+        }
+        let root = trie.commit().unwrap();
+
+        // every persisted node decodes and re-encodes to the same hash
+        for key in node_keys(&db) {
+            let bytes = db.get(&key).unwrap();
+            let mut cache = Cache::new();
+            let node = Node::decode(&bytes, &mut cache).unwrap();
+            let mut scratch = MemoryDB::new();
+            let hash = NodeHasher::new().hash(node, &mut scratch, &mut cache);
+            assert_eq!(hash, H256::from_slice(&key));
+            assert_eq!(scratch.get(&key), Some(bytes));
+        }
+
+        let mut root_loc = [0u8; 32];
+        root_loc.copy_from_slice(root.as_bytes());
+        let mut cache = Cache::new();
+        match Node::decode(&db.get(&root_loc).unwrap(), &mut cache).unwrap() {
+            // "do", "dog" and "foo*" share no prefix beyond the first nibble
+            Node::Short { key, val } => {
+                assert_eq!(key, vec![6]);
+                assert!(matches!(val, NodeLocation::Persistence(_)));
+            }
+            n => panic!("unexpected root {:?}", n),
+        }
     }
 }
@@ -0,0 +1,121 @@
+use crate::encoding::{key_bytes_to_hex, prefix_len};
+use crate::error::Error;
+use crate::node::Node;
+use crate::storage::{Cache, NodeLocation};
+use crate::trie::EMPTY_TRIE_ROOT;
+use common::{keccak, H256};
+use std::collections::HashMap;
+
+/// The encoded nodes on the path to a key and the value found at its end
+type Path = (Vec<Vec<u8>>, Option<Vec<u8>>);
+
+/// Collect the encoded nodes on the path to `key` in the trie with `root`. `resolve`
+/// maps a node hash to its encoding.
+pub(crate) fn prove_path(
+    resolve: &dyn Fn(&[u8]) -> Option<Vec<u8>>,
+    root: H256,
+    key: &[u8],
+) -> Result<Vec<Vec<u8>>, Error> {
+    walk_path(resolve, root, key).map(|(proof, _)| proof)
+}
+
+/// Check the proof of `key` against `root` without a db, returning the value proven
+/// or None if the proof shows the key is absent. Every node on the path must be in
+/// the proof and match the hash its parent references.
+pub fn verify_proof(root: H256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>, Error> {
+    let nodes: HashMap<H256, &Vec<u8>> = proof.iter().map(|n| (keccak(n), n)).collect();
+    let resolve = |h: &[u8]| nodes.get(&H256::from_slice(h)).map(|n| n.to_vec());
+    match walk_path(&resolve, root, key) {
+        Ok((_, value)) => Ok(value),
+        Err(Error::NodeNotFound) => Err(Error::InvalidProof),
+        Err(e) => Err(e),
+    }
+}
+
+fn walk_path(
+    resolve: &dyn Fn(&[u8]) -> Option<Vec<u8>>,
+    root: H256,
+    key: &[u8],
+) -> Result<Path, Error> {
+    let mut proof = vec![];
+    if root == EMPTY_TRIE_ROOT {
+        return Ok((proof, None));
+    }
+
+    let key = key_bytes_to_hex(key);
+    let mut pos = 0;
+    let mut scratch = Cache::new();
+    let mut node_loc = NodeLocation::Persistence(root.to_fixed_bytes());
+    loop {
+        let node = match node_loc {
+            NodeLocation::Persistence(h) => {
+                let bytes = resolve(&h).ok_or(Error::NodeNotFound)?;
+                let node = Node::decode(&bytes, &mut scratch)?;
+                proof.push(bytes);
+                node
+            }
+            NodeLocation::Memory(i) => scratch.get_node(i),
+            NodeLocation::None => return Ok((proof, None)),
+        };
+
+        match node {
+            Node::Short { key: nkey, val } if prefix_len(&nkey, &key[pos..]) == nkey.len() => {
+                pos += nkey.len();
+                node_loc = val;
+            }
+            Node::Full { children } if pos < key.len() => {
+                node_loc = children[key[pos] as usize];
+                pos += 1;
+            }
+            Node::Value(val) if pos == key.len() => return Ok((proof, Some(val))),
+            _ => return Ok((proof, None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::proof::verify_proof;
+    use crate::trie::{Trie, EMPTY_TRIE_ROOT};
+    use common::H256;
+    use kv_storage::MemoryDB;
+
+    #[test]
+    fn verify_proof_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"a value longer than thirty two bytes").unwrap();
+        trie.try_update(b"fooo", b"baz").unwrap();
+        trie.try_update(b"dog", b"puppy").unwrap();
+        let root = trie.commit().unwrap();
+
+        // inclusion
+        for (key, value) in [(&b"foo"[..], &b"bar"[..]), (b"fook", b"a value longer than thirty two bytes")] {
+            let proof = trie.prove(key).unwrap();
+            assert_eq!(verify_proof(root, key, &proof).unwrap(), Some(value.to_vec()));
+        }
+
+        // exclusion
+        let proof = trie.prove(b"fox").unwrap();
+        assert_eq!(verify_proof(root, b"fox", &proof).unwrap(), None);
+        assert_eq!(verify_proof(EMPTY_TRIE_ROOT, b"fox", &[]).unwrap(), None);
+
+        // wrong root
+        let proof = trie.prove(b"fook").unwrap();
+        assert!(matches!(verify_proof(H256::random(), b"fook", &proof), Err(Error::InvalidProof)));
+
+        // missing intermediate node
+        let mut missing = proof.clone();
+        missing.remove(1);
+        assert!(matches!(verify_proof(root, b"fook", &missing), Err(Error::InvalidProof)));
+
+        // tampered node breaks the hash chain
+        let mut tampered = proof.clone();
+        let last = tampered.last_mut().unwrap();
+        let len = last.len();
+        last[len - 1] ^= 1;
+        assert!(matches!(verify_proof(root, b"fook", &tampered), Err(Error::InvalidProof)));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::encoding::key_bytes_to_hex;
+use crate::error::Error;
+use crate::storage::Cache;
+use crate::proof::prove_path;
+use crate::trie::{lookup, root_location};
+use common::H256;
+use kv_storage::DBStorage;
+
+/// Read only view of a committed trie. Unlike `Trie` it only borrows the db
+/// immutably, so it can be shared across threads to read the same state.
+pub struct TrieReader<'a, H: DBStorage + Sync> {
+    db: &'a H,
+    root: H256,
+}
+
+impl<'a, H: DBStorage + Sync> TrieReader<'a, H> {
+    /// The reader of the trie committed with `root` into `db`
+    pub fn new(db: &'a H, root: H256) -> Self {
+        Self { db, root }
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    /// The bytes stored in the key, None if the key does not exist
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        lookup(self.db, &Cache::new(), &root_location(self.root), &key_bytes_to_hex(key), 0)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Proof of the key, see `Trie::prove`
+    pub fn prove(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        prove_path(&|h| self.db.get(h), self.root, key)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reader::TrieReader;
+    use crate::trie::{Trie, EMPTY_TRIE_ROOT};
+    use kv_storage::MemoryDB;
+    use std::thread;
+
+    #[test]
+    fn concurrent_reads_works() {
+        let mut db = MemoryDB::new();
+        let mut trie = Trie::new(&mut db);
+        for i in 0..64u32 {
+            trie.try_update(&i.to_be_bytes(), &(i * 2).to_be_bytes()).unwrap();
+        }
+        let root = trie.commit().unwrap();
+        let proof = trie.prove(&0u32.to_be_bytes()).unwrap();
+
+        let reader = TrieReader::new(&db, root);
+        thread::scope(|s| {
+            for t in 0..4u32 {
+                let (reader, proof) = (&reader, &proof);
+                s.spawn(move || {
+                    for i in (t..64).step_by(4) {
+                        assert_eq!(reader.get(&i.to_be_bytes()), Some((i * 2).to_be_bytes().to_vec()));
+                    }
+                    assert!(!reader.contains_key(&64u32.to_be_bytes()));
+                    assert_eq!(&reader.prove(&0u32.to_be_bytes()).unwrap(), proof);
+                });
+            }
+        });
+
+        let empty = TrieReader::new(&db, EMPTY_TRIE_ROOT);
+        assert!(!empty.contains_key(&1u32.to_be_bytes()));
+    }
+}
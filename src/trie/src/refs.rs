@@ -0,0 +1,135 @@
+use crate::error::Error;
+use crate::node::Node;
+use crate::storage::{Cache, NodeLocation};
+use common::H256;
+use kv_storage::DBStorage;
+use std::collections::HashMap;
+
+/// Prefix of the keys the reference counts are stored under, followed by the node hash
+const REF_PREFIX: &[u8] = b"ref:";
+
+/// The inserts and the removals of a `write_batch`
+type Batch = (Vec<(Vec<u8>, Vec<u8>)>, Vec<Vec<u8>>);
+
+/// Reference counts of the nodes in db, updated in memory through a commit and written
+/// back with its nodes in one batch. A node is referenced once by every stored node
+/// embedding its hash and once by every trie whose committed root it is. Identical
+/// nodes are stored once, so a node is only removed when its count drops to zero.
+pub(crate) struct NodeRefs<'d, H: DBStorage> {
+    db: &'d H,
+    /// The counts changed so far, zero for a node to remove
+    counts: HashMap<H256, u32>,
+    /// The nodes written by the commit that are not in db yet
+    added: HashMap<H256, Vec<u8>>,
+}
+
+impl<'d, H: DBStorage> NodeRefs<'d, H> {
+    pub fn new(db: &'d H) -> Self {
+        Self {
+            db,
+            counts: HashMap::new(),
+            added: HashMap::new(),
+        }
+    }
+
+    /// Store a node hashed by the commit. A node already stored references its
+    /// children already, only a new one adds references to them.
+    pub fn add(&mut self, hash: H256, encoded: Vec<u8>) -> Result<(), Error> {
+        if self.added.contains_key(&hash) || self.db.contains(hash.as_bytes()) {
+            return Ok(());
+        }
+        for child in child_hashes(&encoded)? {
+            self.reference(child);
+        }
+        self.added.insert(hash, encoded);
+        Ok(())
+    }
+
+    pub fn reference(&mut self, hash: H256) {
+        let count = self.count(&hash).unwrap_or(0) + 1;
+        self.counts.insert(hash, count);
+    }
+
+    /// Drop a reference to the node, removing it and releasing its children once it is
+    /// no longer referenced. Nodes without a count were not written by a commit and are
+    /// left alone.
+    pub fn release(&mut self, hash: H256) -> Result<(), Error> {
+        let mut pending = vec![hash];
+        while let Some(hash) = pending.pop() {
+            let count = match self.count(&hash) {
+                Some(count) if count > 0 => count - 1,
+                _ => continue,
+            };
+            self.counts.insert(hash, count);
+            if count == 0 {
+                if let Some(encoded) = self.added.remove(&hash).or_else(|| self.db.get(hash.as_bytes())) {
+                    pending.extend(child_hashes(&encoded)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The inserts and removals to apply to the db
+    pub fn into_batch(self) -> Batch {
+        let mut inserts = vec![];
+        let mut removals = vec![];
+        for (hash, encoded) in self.added {
+            inserts.push((hash.as_bytes().to_vec(), encoded));
+        }
+        for (hash, count) in self.counts {
+            if count == 0 {
+                removals.push(hash.as_bytes().to_vec());
+                removals.push(ref_key(&hash));
+            } else {
+                inserts.push((ref_key(&hash), count.to_be_bytes().to_vec()));
+            }
+        }
+        (inserts, removals)
+    }
+
+    fn count(&self, hash: &H256) -> Option<u32> {
+        if let Some(count) = self.counts.get(hash) {
+            return Some(*count);
+        }
+        let bytes = self.db.get(&ref_key(hash))?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+}
+
+fn ref_key(hash: &H256) -> Vec<u8> {
+    [REF_PREFIX, hash.as_bytes()].concat()
+}
+
+/// The keys of the nodes in db, leaving out the reference counts
+#[cfg(test)]
+pub(crate) fn node_keys<H: DBStorage>(db: &H) -> Vec<Vec<u8>> {
+    db.keys()
+        .expect("test backends list their keys")
+        .into_iter()
+        .filter(|k| !k.starts_with(REF_PREFIX))
+        .collect()
+}
+
+/// The hashes a node references. Children embedded in the node are not stored on
+/// their own, their references are the node's.
+fn child_hashes(encoded: &[u8]) -> Result<Vec<H256>, Error> {
+    let mut cache = Cache::new();
+    let mut nodes = vec![Node::decode(encoded, &mut cache)?];
+    let mut hashes = vec![];
+    while let Some(node) = nodes.pop() {
+        let children = match node {
+            Node::Full { children } => children.to_vec(),
+            Node::Short { val, .. } => vec![val],
+            Node::Empty | Node::Value(_) => vec![],
+        };
+        for child in children {
+            match child {
+                NodeLocation::Persistence(h) => hashes.push(H256::from(h)),
+                NodeLocation::Memory(i) => nodes.push(cache.get_node(i)),
+                NodeLocation::None => {}
+            }
+        }
+    }
+    Ok(hashes)
+}
@@ -0,0 +1,117 @@
+use crate::error::Error;
+use crate::trie::Trie;
+use common::{keccak, H256};
+use kv_storage::DBStorage;
+use std::collections::HashMap;
+
+/// A trie keyed by the keccak hash of the keys, as the Ethereum state trie is, so
+/// that it stays balanced whatever the keys are
+pub struct SecureTrie<'a, H: DBStorage> {
+    trie: Trie<'a, H>,
+    /// Original keys by their hash, only kept in memory when storing preimages
+    preimages: Option<HashMap<H256, Vec<u8>>>,
+}
+
+impl<'a, H: DBStorage> SecureTrie<'a, H> {
+    pub fn new(db: &'a mut H) -> Self {
+        Self {
+            trie: Trie::new(db),
+            preimages: None,
+        }
+    }
+
+    /// Like `new`, but keeps the original keys so `iter` can return them
+    pub fn new_with_preimages(db: &'a mut H) -> Self {
+        Self {
+            trie: Trie::new(db),
+            preimages: Some(HashMap::new()),
+        }
+    }
+
+    /// Reopen the secure trie committed with `root` into `db`
+    pub fn new_from_existing(db: &'a mut H, root: H256) -> Self {
+        Self {
+            trie: Trie::new_from_existing(db, root),
+            preimages: None,
+        }
+    }
+
+    pub fn try_get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.trie.try_get(keccak(key).as_bytes())
+    }
+
+    pub fn try_update(&mut self, key: &[u8], val: &[u8]) -> Result<(), Error> {
+        let hash = keccak(key);
+        self.trie.try_update(hash.as_bytes(), val)?;
+        if let Some(preimages) = self.preimages.as_mut() {
+            preimages.insert(hash, key.to_vec());
+        }
+        Ok(())
+    }
+
+    pub fn try_delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        let hash = keccak(key);
+        self.trie.try_delete(hash.as_bytes())?;
+        if let Some(preimages) = self.preimages.as_mut() {
+            preimages.remove(&hash);
+        }
+        Ok(())
+    }
+
+    pub fn commit(&mut self) -> Result<H256, Error> {
+        self.trie.commit()
+    }
+
+    /// Iterate over the `(key, value)` pairs in the order of the key hashes. The key is
+    /// the original one if its preimage is known, its hash otherwise.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        let preimages = self.preimages.as_ref();
+        self.trie.iter().map(move |(hash, val)| {
+            let preimage = preimages.and_then(|p| p.get(&H256::from_slice(&hash)));
+            (preimage.cloned().unwrap_or(hash), val)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secure::SecureTrie;
+    use crate::trie::Trie;
+    use common::keccak;
+    use kv_storage::MemoryDB;
+
+    const ENTRIES: [(&[u8], &[u8]); 4] = [
+        (b"foo", b"bar"),
+        (b"fook", b"barr"),
+        (b"dog", b"puppy"),
+        (b"horse", b"stallion"),
+    ];
+
+    #[test]
+    fn secure_trie_works() {
+        let mut plain_db = MemoryDB::new();
+        let mut plain = Trie::new(&mut plain_db);
+        let mut secure_db = MemoryDB::new();
+        let mut secure = SecureTrie::new_with_preimages(&mut secure_db);
+        for (k, v) in ENTRIES {
+            plain.try_update(keccak(k).as_bytes(), v).unwrap();
+            secure.try_update(k, v).unwrap();
+        }
+        assert_eq!(secure.try_get(b"dog"), Some(b"puppy".to_vec()));
+        secure.try_delete(b"horse").unwrap();
+        plain.try_delete(keccak(b"horse").as_bytes()).unwrap();
+        assert_eq!(secure.try_get(b"horse"), None);
+
+        let mut keys: Vec<_> = secure.iter().map(|(k, _)| k).collect();
+        keys.sort();
+        assert_eq!(keys, vec![b"dog".to_vec(), b"foo".to_vec(), b"fook".to_vec()]);
+
+        let root = secure.commit().unwrap();
+        assert_eq!(root, plain.commit().unwrap());
+
+        // without preimages the hashed keys are returned
+        let secure = SecureTrie::new_from_existing(&mut secure_db, root);
+        assert_eq!(secure.try_get(b"fook"), Some(b"barr".to_vec()));
+        assert!(secure.iter().any(|(k, _)| k == keccak(b"foo").as_bytes()));
+    }
+}
@@ -26,6 +26,7 @@ impl Default for NodeLocation {
 }
 
 /// The memory slot type for nodes stored in memory
+#[derive(Clone)]
 pub(crate) enum MemorySlot {
     /// The memory slot is updated, we need to flush it
     Updated(Node),
@@ -34,6 +35,7 @@ pub(crate) enum MemorySlot {
 }
 
 /// In memory storage location for nodes
+#[derive(Clone)]
 pub(crate) struct Cache {
     /// Data and references relationships of dirty trie nodes
     slots: Vec<MemorySlot>,
@@ -1,22 +1,34 @@
-use crate::encoding::{key_bytes_to_hex, prefix_len, TERMINAL};
+use crate::encoding::{decode_nibbles, key_bytes_to_hex, prefix_len, TERMINAL};
 use crate::error::Error;
 use crate::hasher::NodeHasher;
-use crate::node::{DeleteItem, Node, CHILD_SIZE};
+use crate::iter::TrieIterator;
+use crate::node::{Node, CHILD_SIZE};
+use crate::proof::prove_path;
+use crate::refs::NodeRefs;
+use crate::rstd::cell::{Ref, RefCell};
 use crate::rstd::mem;
+use crate::rstd::rc::Rc;
 use crate::storage::{Cache, CacheIndex, MemorySlot, NodeLocation};
-use common::{ensure, H256};
-use kv_storage::DBStorage;
+use common::{ensure, H256, KECCAK_EMPTY};
+use kv_storage::{DBStorage, MemoryDB};
 use log::debug;
-use std::collections::HashSet;
+
+/// Root hash of a trie with no entries, `keccak(rlp(""))`
+pub const EMPTY_TRIE_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
 
 type Prefix = Vec<u8>;
 
 /// The Trie data type for storage
 pub struct Trie<'a, H: DBStorage> {
-    db: &'a mut H,
+    /// Shared with the forks of the trie
+    db: Rc<RefCell<&'a mut H>>,
     root_loc: NodeLocation,
     cache: Cache,
-    delete_items: HashSet<DeleteItem>,
+    /// The root this trie holds a reference to in db, released on the next commit
+    committed_root: Option<H256>,
     unhashed: u32,
     node_hasher: NodeHasher,
 }
@@ -25,57 +37,54 @@ impl<'a, H: DBStorage> Trie<'a, H> {
     /// The root_hash needs to be the empty node hash
     pub fn new(db: &'a mut H) -> Self {
         Self {
-            db,
+            db: Rc::new(RefCell::new(db)),
             root_loc: NodeLocation::None,
             cache: Cache::new(),
-            delete_items: Default::default(),
+            committed_root: None,
             unhashed: 0,
-            node_hasher: NodeHasher::new(),
+            node_hasher: NodeHasher::new_batched(),
         }
     }
 
-    // // pub fn new_from_existing(db: &'db DB, root_hash: &[u8]) -> Self {
-    // //
-    // // }
-
-    /// Try to get the bytes stored in the key. If key does not exist, return None.
-    pub fn try_get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.get(&self.root_loc, &key_bytes_to_hex(key), 0)
+    /// Fork the trie for speculative updates. The fork shares the underlying db but
+    /// has its own copy of the cached nodes, so updates on the fork do not affect
+    /// this trie. The fork does not hold this trie's root, the roots it commits are
+    /// its own, so committing either trie keeps the nodes the other one still uses.
+    /// The last root the fork commits stays in db until the fork is discarded.
+    /// The db is borrowed as the tries use it, iterating one of them while the
+    /// other commits panics.
+    pub fn fork(&self) -> Trie<'a, H> {
+        Trie {
+            db: Rc::clone(&self.db),
+            root_loc: self.root_loc,
+            cache: self.cache.clone(),
+            committed_root: None,
+            unhashed: self.unhashed,
+            node_hasher: NodeHasher::new_batched(),
+        }
     }
 
-    fn get(&self, node_loc: &NodeLocation, key: &[u8], pos: usize) -> Option<Vec<u8>> {
-        if key.is_empty() {
-            return None;
+    /// Reopen the trie committed with `root` into `db`. The nodes are loaded from db
+    /// as they are read or updated. The trie takes over the reference to `root`, its
+    /// next commit releases it.
+    pub fn new_from_existing(db: &'a mut H, root: H256) -> Self {
+        let mut trie = Self::new(db);
+        trie.root_loc = root_location(root);
+        if let NodeLocation::Persistence(_) = trie.root_loc {
+            trie.committed_root = Some(root);
         }
+        trie
+    }
 
-        let node = match node_loc {
-            NodeLocation::Persistence(h) => match self.db.get(h) {
-                None => Node::Empty,
-                Some(bytes) => Node::from(bytes),
-            },
-            NodeLocation::Memory(cache_index) => self.cache.get_node(*cache_index),
-            NodeLocation::None => Node::Empty,
-        };
+    /// Try to get the bytes stored in the key. If key does not exist, return None.
+    pub fn try_get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        lookup(&**self.db.borrow(), &self.cache, &self.root_loc, &key_bytes_to_hex(key), 0)
+    }
 
-        match node {
-            Node::Empty => None,
-            Node::Short { key: nkey, val } => {
-                let matchlen = prefix_len(&nkey, &key[pos..]);
-                if matchlen != nkey.len() {
-                    None
-                } else {
-                    self.get(&val, key, pos + matchlen)
-                }
-            }
-            Node::Full { children } => self.get(&children[key[pos] as usize], key, pos + 1),
-            Node::Value(val) => {
-                if key.len() != pos {
-                    None
-                } else {
-                    Some(val)
-                }
-            }
-        }
+    /// Iterate over the `(key, value)` pairs in key order, reading the cached nodes
+    /// and loading the rest from db
+    pub fn iter(&self) -> TrieIterator<'_, H> {
+        TrieIterator::new(Ref::map(self.db.borrow(), |db| &**db), &self.cache, self.root_loc)
     }
 
     /// Try to delete the key, returns corresponding errors
@@ -87,8 +96,9 @@ impl<'a, H: DBStorage> Trie<'a, H> {
     }
 
     fn delete(&mut self, node_loc: NodeLocation, key: &[u8]) -> Result<NodeLocation, Error> {
-        // let (_, node) = self.take_node_loc(node_loc)?;
-        let (_, node) = self.get_node_loc_mut(&node_loc)?;
+        let (cache_index, node) = self.get_node_loc_mut(&node_loc)?;
+        // a node read from db is destroyed through its cache slot
+        let node_loc = NodeLocation::Memory(cache_index);
         match node {
             Node::Empty => Err(Error::KeyNotExists),
             Node::Full { children } => {
@@ -229,6 +239,11 @@ impl<'a, H: DBStorage> Trie<'a, H> {
                     self.cache.insert(MemorySlot::Updated(n)),
                 ))
             }
+            // the terminal child of a full node
+            Node::Value(_) if key.is_empty() => {
+                self.destroy(&node_loc)?;
+                Ok(NodeLocation::None)
+            }
             _ => panic!("invalid state"),
         }
     }
@@ -238,11 +253,7 @@ impl<'a, H: DBStorage> Trie<'a, H> {
             NodeLocation::None => Ok(()),
             NodeLocation::Persistence(_) => Err(Error::InvalidNodeLocation),
             NodeLocation::Memory(cache_index) => {
-                let d = match self.cache.take(*cache_index) {
-                    MemorySlot::Updated(n) => DeleteItem::Node(n),
-                    MemorySlot::Loaded(h, _) => DeleteItem::Hash(h),
-                };
-                self.delete_items.insert(d);
+                self.cache.take(*cache_index);
                 Ok(())
             }
         }
@@ -383,29 +394,136 @@ impl<'a, H: DBStorage> Trie<'a, H> {
     }
 
     /// Commit cached node changes to underlying database. Update trie hash as well.
+    /// The nodes in db are reference counted: the new root is referenced, the root of
+    /// the previous commit released, and the nodes no longer referenced by any stored
+    /// node or committed root are removed. Nodes shared with other keys or with the
+    /// roots of other tries on the same db are kept.
     pub fn commit(&mut self) -> Result<H256, Error> {
-        // TODO: remove items in self.delete_items in db
+        debug_assert!(
+            self.verify_value_keys().is_ok(),
+            "value node key does not match the path to it"
+        );
         let node_loc = self.root_loc();
-        let h = match node_loc {
-            NodeLocation::None => H256::default(),
+        let root = hash_root(node_loc, &mut self.node_hasher, &mut **self.db.borrow_mut(), &mut self.cache);
+
+        let db = self.db.borrow();
+        let mut refs = NodeRefs::new(&**db);
+        for (hash, encoded) in self.node_hasher.take_batch() {
+            refs.add(H256::from_slice(&hash), encoded)?;
+        }
+        let committed = match root_location(root) {
+            NodeLocation::Persistence(_) => Some(root),
+            _ => None,
+        };
+        if committed != self.committed_root {
+            // reference the new root before the old one is released, they share nodes
+            if let Some(root) = committed {
+                refs.reference(root);
+            }
+            if let Some(old) = self.committed_root {
+                refs.release(old)?;
+            }
+            self.committed_root = committed;
+        }
+        let (inserts, removals) = refs.into_batch();
+        drop(db);
+        self.db.borrow_mut().write_batch(inserts, removals);
+        // the cached nodes were taken by the hasher, read on from the committed root
+        self.root_loc = root_location(root);
+        Ok(root)
+    }
+
+    /// Drop the trie and release the root it committed, for a fork whose updates are
+    /// not kept. The nodes no other trie or stored node references are removed.
+    pub fn discard(self) -> Result<(), Error> {
+        let root = match self.committed_root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        let db = self.db.borrow();
+        let mut refs = NodeRefs::new(&**db);
+        refs.release(root)?;
+        let (inserts, removals) = refs.into_batch();
+        drop(db);
+        self.db.borrow_mut().write_batch(inserts, removals);
+        Ok(())
+    }
+
+    /// The root hash the next `commit` would return. The trie is left as is, the nodes
+    /// are hashed from a copy of the cache and written to a scratch db, so it is safe to
+    /// keep updating the trie afterwards.
+    pub fn root(&self) -> H256 {
+        let mut cache = self.cache.clone();
+        let mut scratch = MemoryDB::new();
+        hash_root(self.root_loc(), &mut NodeHasher::new(), &mut scratch, &mut cache)
+    }
+
+    /// Proof of the key, the encoded nodes on the path from the root to the key, root
+    /// first. Nodes embedded in their parent are not listed on their own. For an absent
+    /// key the path ends at the node where the key diverges, proving non-existence.
+    /// The nodes are read from db, so only the committed state can be proven, with
+    /// updates not committed yet it fails with `Error::UncommittedChanges`.
+    pub fn prove(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let root = match self.root_loc {
+            NodeLocation::None => EMPTY_TRIE_ROOT,
             NodeLocation::Persistence(h) => H256::from(h),
-            NodeLocation::Memory(x) => {
-                match self.cache.take(x) {
-                    MemorySlot::Updated(node) => {
-                        self.node_hasher.hash(node, self.db, &mut self.cache)
-                    }
-                    // If the slot is just loaded from DB and not updated,
-                    // we should not have the need to process it again.
-                    MemorySlot::Loaded(h, _) => h,
+            NodeLocation::Memory(_) => return Err(Error::UncommittedChanges),
+        };
+        prove_path(&|h| self.db.borrow().get(h), root, key)
+    }
+
+    /// The hasher takes the key of a value node from its parent, so a value node reached
+    /// through a malformed path would silently corrupt the root. Check that the path to
+    /// every cached value node is a valid hex key and `try_get` on it finds the value.
+    /// Nodes still in the db were checked when they were committed and are skipped.
+    pub(crate) fn verify_value_keys(&self) -> Result<(), Error> {
+        self.verify_value_keys_from(&self.root_loc, &mut vec![])
+    }
+
+    fn verify_value_keys_from(&self, node_loc: &NodeLocation, path: &mut Prefix) -> Result<(), Error> {
+        let cache_index = match node_loc {
+            NodeLocation::Memory(i) => *i,
+            _ => return Ok(()),
+        };
+
+        match self.cache.get_node(cache_index) {
+            Node::Empty => Ok(()),
+            Node::Short { key, val } => {
+                let len = path.len();
+                path.extend_from_slice(&key);
+                let result = self.verify_value_keys_from(&val, path);
+                path.truncate(len);
+                result
+            }
+            Node::Full { children } => {
+                for (i, child) in children.iter().enumerate() {
+                    path.push(i as u8);
+                    let result = self.verify_value_keys_from(child, path);
+                    path.pop();
+                    result?;
                 }
+                Ok(())
             }
-        };
-        Ok(h)
+            Node::Value(val) => {
+                // a hex key is an even number of nibbles followed by the terminal
+                let nibbles = match path.split_last() {
+                    Some((&TERMINAL, nibbles))
+                        if nibbles.len() % 2 == 0 && !nibbles.contains(&TERMINAL) =>
+                    {
+                        nibbles
+                    }
+                    _ => return Err(Error::InvalidTrieState),
+                };
+                let mut key = Vec::with_capacity(nibbles.len() / 2);
+                decode_nibbles(nibbles, 0, nibbles.len(), &mut key);
+                ensure!(self.try_get(&key) == Some(val), Error::InvalidTrieState)
+            }
+        }
     }
 
     fn extract_cache_index(&mut self, node_loc: &NodeLocation) -> Result<CacheIndex, Error> {
         match node_loc {
-            NodeLocation::Persistence(h) => Ok(self.load_to_cache(&H256::from_slice(h))),
+            NodeLocation::Persistence(h) => self.load_to_cache(&H256::from_slice(h)),
             NodeLocation::Memory(i) => Ok(*i),
             _ => Err(Error::InvalidNodeLocation),
         }
@@ -429,7 +547,7 @@ impl<'a, H: DBStorage> Trie<'a, H> {
     fn take_node_loc(&mut self, node_loc: NodeLocation) -> Result<(CacheIndex, Node), Error> {
         let cache_index = self.extract_cache_index(&node_loc)?;
 
-        // Always fetch the node from cache
+        // Always fetch the node from cache, a taken node is superseded by its update
         let node = match self.cache.take(cache_index) {
             MemorySlot::Updated(node) => node,
             MemorySlot::Loaded(_, node) => node,
@@ -438,12 +556,13 @@ impl<'a, H: DBStorage> Trie<'a, H> {
         Ok((cache_index, node))
     }
 
-    fn load_to_cache(&mut self, h: &H256) -> CacheIndex {
-        let node = match self.db.get(h.as_bytes()) {
+    fn load_to_cache(&mut self, h: &H256) -> Result<CacheIndex, Error> {
+        let bytes = self.db.borrow().get(h.as_bytes());
+        let node = match bytes {
             None => Node::Empty,
-            Some(bytes) => Node::from(bytes),
+            Some(bytes) => Node::decode(&bytes, &mut self.cache)?,
         };
-        self.cache.insert(MemorySlot::Loaded(*h, node))
+        Ok(self.cache.insert(MemorySlot::Loaded(*h, node)))
     }
 
     // a hack to get the root node's handle
@@ -457,12 +576,106 @@ impl<'a, H: DBStorage> Trie<'a, H> {
     }
 }
 
+/// Location of the root node of a committed trie, there is none for the empty trie
+pub(crate) fn root_location(root: H256) -> NodeLocation {
+    if root == EMPTY_TRIE_ROOT || root == KECCAK_EMPTY || root.is_zero() {
+        NodeLocation::None
+    } else {
+        NodeLocation::Persistence(root.to_fixed_bytes())
+    }
+}
+
+/// Look up the hex key from `node_loc`. Memory locations are resolved in `cache`, which is
+/// the trie cache, or a scratch cache holding the inline children of a node read from db.
+pub(crate) fn lookup<H: DBStorage>(
+    db: &H,
+    cache: &Cache,
+    node_loc: &NodeLocation,
+    key: &[u8],
+    pos: usize,
+) -> Option<Vec<u8>> {
+    if key.is_empty() {
+        return None;
+    }
+
+    match node_loc {
+        NodeLocation::Persistence(h) => {
+            let mut scratch = Cache::new();
+            let node = match db.get(h) {
+                None => Node::Empty,
+                Some(bytes) => Node::decode(&bytes, &mut scratch).ok()?,
+            };
+            lookup_in_node(db, &scratch, node, key, pos)
+        }
+        NodeLocation::Memory(cache_index) => {
+            lookup_in_node(db, cache, cache.get_node(*cache_index), key, pos)
+        }
+        NodeLocation::None => None,
+    }
+}
+
+fn lookup_in_node<H: DBStorage>(
+    db: &H,
+    cache: &Cache,
+    node: Node,
+    key: &[u8],
+    pos: usize,
+) -> Option<Vec<u8>> {
+    match node {
+        Node::Empty => None,
+        Node::Short { key: nkey, val } => {
+            let matchlen = prefix_len(&nkey, &key[pos..]);
+            if matchlen != nkey.len() {
+                None
+            } else {
+                lookup(db, cache, &val, key, pos + matchlen)
+            }
+        }
+        Node::Full { children } => lookup(db, cache, &children[key[pos] as usize], key, pos + 1),
+        Node::Value(val) => {
+            if key.len() != pos {
+                None
+            } else {
+                Some(val)
+            }
+        }
+    }
+}
+
+/// Hash the trie under `node_loc`, writing the hashed nodes to `db`.
+/// The updated nodes are taken out of `cache`.
+fn hash_root<H: DBStorage>(
+    node_loc: NodeLocation,
+    hasher: &mut NodeHasher,
+    db: &mut H,
+    cache: &mut Cache,
+) -> H256 {
+    match node_loc {
+        NodeLocation::None => EMPTY_TRIE_ROOT,
+        NodeLocation::Persistence(h) => H256::from(h),
+        NodeLocation::Memory(x) => {
+            match cache.take(x) {
+                MemorySlot::Updated(node) => hasher.hash(node, db, cache),
+                // If the slot is just loaded from DB and not updated,
+                // we should not have the need to process it again.
+                MemorySlot::Loaded(h, _) => h,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use common::H256;
-    use crate::storage::NodeLocation;
-    use crate::trie::Trie;
-    use kv_storage::MemoryDB;
+    use common::{keccak, BigEndianHash, H256, KECCAK_EMPTY, U256};
+    use rlp::Rlp;
+    use crate::error::Error;
+    use crate::node::Node;
+    use crate::reader::TrieReader;
+    use crate::refs::node_keys;
+    use crate::storage::{MemorySlot, NodeLocation};
+    use crate::trie::{Trie, EMPTY_TRIE_ROOT};
+    use kv_storage::{DBStorage, MemoryDB};
+    use rlp::RLPStream;
 
     const TEST_HASH: [u8; 32] = [
         0x65, 0x5a, 0x75, 0x4, 0xda, 0x98, 0xaa, 0xca, 0x39, 0xf2, 0x38, 0x85, 0xb2, 0xb2, 0x32,
@@ -543,6 +756,173 @@ mod tests {
         assert_eq!(trie.try_get(&vec![1, 2, 3, 5]), None);
     }
 
+    #[test]
+    fn fork_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"barr").unwrap();
+        trie.try_update(b"fooo", b"bar").unwrap();
+
+        {
+            let mut fork = trie.fork();
+            assert_eq!(fork.try_get(b"fook"), Some(b"barr".to_vec()));
+
+            fork.try_update(b"fook", b"changed").unwrap();
+            fork.try_delete(b"fooo").unwrap();
+            fork.try_update(b"bar", b"baz").unwrap();
+            assert_eq!(fork.try_get(b"fook"), Some(b"changed".to_vec()));
+            assert_ne!(fork.commit().unwrap(), H256::from(TEST_HASH));
+        }
+
+        assert_eq!(trie.try_get(b"fook"), Some(b"barr".to_vec()));
+        assert_eq!(trie.try_get(b"fooo"), Some(b"bar".to_vec()));
+        assert_eq!(trie.try_get(b"bar"), None);
+        assert_eq!(trie.commit().unwrap(), H256::from(TEST_HASH));
+    }
+
+    #[test]
+    fn get_from_db_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"barr").unwrap();
+        trie.try_update(b"fooo", b"bar").unwrap();
+        let root = trie.commit().unwrap();
+
+        let mut loaded = Trie::new(&mut hash_db);
+        loaded.root_loc = NodeLocation::Persistence(root.to_fixed_bytes());
+        assert_eq!(loaded.try_get(b"foo"), Some(b"bar".to_vec()));
+        assert_eq!(loaded.try_get(b"fook"), Some(b"barr".to_vec()));
+        assert_eq!(loaded.try_get(b"fooo"), Some(b"bar".to_vec()));
+        assert_eq!(loaded.try_get(b"fo"), None);
+
+        // updating loads the nodes into the cache and keeps the root stable
+        loaded.try_update(b"fook", b"barr").unwrap();
+        assert_eq!(loaded.commit().unwrap(), root);
+    }
+
+    #[test]
+    fn new_from_existing_works() {
+        let mut hash_db = MemoryDB::new();
+        let root = {
+            let mut trie = Trie::new(&mut hash_db);
+            trie.try_update(b"foo", b"bar").unwrap();
+            trie.try_update(b"fook", b"barr").unwrap();
+            trie.commit().unwrap()
+        };
+
+        let mut trie = Trie::new_from_existing(&mut hash_db, root);
+        assert_eq!(trie.try_get(b"fook"), Some(b"barr".to_vec()));
+        trie.try_update(b"fooo", b"bar").unwrap();
+        assert_eq!(trie.commit().unwrap(), H256::from(TEST_HASH));
+
+        for empty in [EMPTY_TRIE_ROOT, KECCAK_EMPTY, H256::zero()] {
+            let mut trie = Trie::new_from_existing(&mut hash_db, empty);
+            assert_eq!(trie.root_loc, NodeLocation::None);
+            assert_eq!(trie.try_get(b"foo"), None);
+            assert_eq!(trie.commit().unwrap(), EMPTY_TRIE_ROOT);
+        }
+    }
+
+    #[test]
+    fn commit_prunes_works() {
+        let mut hash_db = MemoryDB::new();
+        let (old_root, root) = {
+            let mut trie = Trie::new(&mut hash_db);
+            trie.try_update(b"foo", b"bar").unwrap();
+            trie.try_update(b"fook", b"barr").unwrap();
+            let old_root = trie.commit().unwrap();
+            trie.try_update(b"fook", b"changed").unwrap();
+            (old_root, trie.commit().unwrap())
+        };
+        assert!(!hash_db.contains(old_root.as_bytes()));
+        assert!(hash_db.contains(root.as_bytes()));
+
+        // deleting from a trie read from db prunes too, and leaves the rest readable
+        let old_root = root;
+        let root = {
+            let mut trie = Trie::new_from_existing(&mut hash_db, root);
+            trie.try_update(b"fooo", b"bar").unwrap();
+            trie.try_delete(b"fooo").unwrap();
+            assert_eq!(trie.commit().unwrap(), old_root);
+            trie.try_delete(b"foo").unwrap();
+            trie.commit().unwrap()
+        };
+        assert!(!hash_db.contains(old_root.as_bytes()));
+        assert_eq!(node_keys(&hash_db), vec![root.as_bytes().to_vec()]);
+        let trie = Trie::new_from_existing(&mut hash_db, root);
+        assert_eq!(trie.iter().collect::<Vec<_>>(), vec![(b"fook".to_vec(), b"changed".to_vec())]);
+    }
+
+    #[test]
+    fn commit_keeps_shared_nodes_works() {
+        // the leaves below the root differ only in the first nibble, which the root
+        // full node holds, so all three are the same node stored once
+        let keys = [0x10u8, 0x20, 0x30].map(|b| [b, 0, 0, 0, 0, 0, 0, 0]);
+        let val = vec![7u8; 40];
+        let mut hash_db = MemoryDB::new();
+        let root = {
+            let mut trie = Trie::new(&mut hash_db);
+            for key in &keys {
+                trie.try_update(key, &val).unwrap();
+            }
+            trie.commit().unwrap()
+        };
+        assert_eq!(node_keys(&hash_db).len(), 2);
+
+        let root = {
+            let mut trie = Trie::new_from_existing(&mut hash_db, root);
+            trie.try_delete(&keys[0]).unwrap();
+            trie.commit().unwrap()
+        };
+        let mut trie = Trie::new_from_existing(&mut hash_db, root);
+        assert_eq!(trie.try_get(&keys[0]), None);
+        assert_eq!(trie.try_get(&keys[1]), Some(val.clone()));
+        assert_eq!(trie.try_get(&keys[2]), Some(val.clone()));
+
+        // the last reference to the leaf goes with the last key using it
+        trie.try_delete(&keys[1]).unwrap();
+        trie.try_delete(&keys[2]).unwrap();
+        assert_eq!(trie.commit().unwrap(), EMPTY_TRIE_ROOT);
+        assert!(hash_db.keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fork_commit_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"a value longer than thirty two bytes").unwrap();
+        trie.try_update(b"dog", b"puppy").unwrap();
+        trie.commit().unwrap();
+
+        let mut fork = trie.fork();
+        fork.try_update(b"dog", b"hound").unwrap();
+        let fork_root = fork.commit().unwrap();
+
+        // the parent moving on releases its old root, not the nodes the fork uses
+        trie.try_delete(b"fook").unwrap();
+        trie.try_update(b"foo", b"baz").unwrap();
+        let root = trie.commit().unwrap();
+
+        // the fork reads its committed nodes back from db
+        assert_eq!(fork.try_get(b"foo"), Some(b"bar".to_vec()));
+        assert_eq!(fork.try_get(b"fook"), Some(b"a value longer than thirty two bytes".to_vec()));
+        assert_eq!(fork.try_get(b"dog"), Some(b"hound".to_vec()));
+
+        // discarding the fork releases its root and keeps the nodes the parent uses
+        fork.discard().unwrap();
+        assert!(!trie.db.borrow().contains(fork_root.as_bytes()));
+        drop(trie);
+        let parent = TrieReader::new(&hash_db, root);
+        assert_eq!(parent.get(b"foo"), Some(b"baz".to_vec()));
+        assert_eq!(parent.get(b"fook"), None);
+        assert_eq!(parent.get(b"dog"), Some(b"puppy".to_vec()));
+    }
+
     #[test]
     fn commit_works() {
         let mut hash_db = MemoryDB::new();
@@ -555,4 +935,117 @@ mod tests {
         let out = trie.commit().unwrap();
         assert_eq!(out, H256::from(TEST_HASH));
     }
+
+    #[test]
+    fn root_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        assert_eq!(trie.root(), EMPTY_TRIE_ROOT);
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"barr").unwrap();
+        let committed = trie.commit().unwrap();
+        assert_eq!(trie.root(), committed);
+
+        // part of the nodes in the db, part updated in the cache
+        let mut trie = Trie::new_from_existing(&mut hash_db, committed);
+        trie.try_update(b"fooo", b"bar").unwrap();
+        let root = trie.root();
+        assert_eq!(trie.root(), root);
+        assert_eq!(root, H256::from(TEST_HASH));
+
+        trie.try_update(b"food", b"baz").unwrap();
+        assert_eq!(trie.try_get(b"food"), Some(b"baz".to_vec()));
+        assert_ne!(trie.root(), root);
+        trie.try_delete(b"food").unwrap();
+        assert_eq!(trie.commit().unwrap(), root);
+    }
+
+    #[test]
+    fn verify_value_keys_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"barr").unwrap();
+        trie.try_update(b"fooo", b"bar").unwrap();
+        trie.try_delete(b"fooo").unwrap();
+        assert!(trie.verify_value_keys().is_ok());
+
+        // drop a nibble from the root short node, the value keys become odd length
+        let root = match trie.root_loc {
+            NodeLocation::Memory(i) => i,
+            _ => panic!("root should be cached"),
+        };
+        match trie.cache.get_mut(root) {
+            MemorySlot::Updated(Node::Short { key, .. }) => {
+                key.pop();
+            }
+            _ => panic!("root should be an updated short node"),
+        }
+        assert!(trie.verify_value_keys().is_err());
+    }
+
+    #[test]
+    fn insertion_order_works() {
+        let slots: Vec<(U256, u64)> = (1..20u64).map(|i| (U256::from(i * 7919), i)).collect();
+        let storage_root = |slots: &mut dyn Iterator<Item = &(U256, u64)>| {
+            let mut hash_db = MemoryDB::new();
+            let mut trie = Trie::new(&mut hash_db);
+            for (k, v) in slots {
+                let key = keccak(H256::from_uint(k).as_bytes());
+                let mut value = RLPStream::new();
+                value.append(v);
+                trie.try_update(key.as_bytes(), &value.out()).unwrap();
+            }
+            trie.commit().unwrap()
+        };
+
+        let root = storage_root(&mut slots.iter());
+        assert_eq!(storage_root(&mut slots.iter().rev()), root);
+        assert_eq!(storage_root(&mut slots.iter().step_by(2).chain(slots.iter().skip(1).step_by(2))), root);
+        assert_ne!(storage_root(&mut slots.iter().skip(1)), root);
+    }
+
+    #[test]
+    fn prove_works() {
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        assert!(trie.prove(b"foo").unwrap().is_empty());
+
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_update(b"fook", b"a value longer than thirty two bytes").unwrap();
+        trie.try_update(b"dog", b"puppy").unwrap();
+        assert!(matches!(trie.prove(b"fook"), Err(Error::UncommittedChanges)));
+        let root = trie.commit().unwrap();
+
+        // each node is referenced by hash from the one before it
+        let proof = trie.prove(b"fook").unwrap();
+        assert_eq!(keccak(&proof[0]), root);
+        for pair in proof.windows(2) {
+            let child = keccak(&pair[1]);
+            assert!(pair[0].windows(32).any(|w| w == child.as_bytes()));
+        }
+        let last = Rlp::new(proof.last().unwrap());
+        assert_eq!(last.at(1).unwrap().data().unwrap(), b"a value longer than thirty two bytes");
+
+        // the path for an absent key stops where it diverges from the stored keys
+        let absent = trie.prove(b"fox").unwrap();
+        assert!(!absent.is_empty());
+        assert_eq!(absent[0], proof[0]);
+        assert!(absent.len() <= proof.len());
+    }
+
+    #[test]
+    fn empty_root_works() {
+        assert_eq!(EMPTY_TRIE_ROOT, keccak(&[0x80]));
+
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        assert_eq!(trie.commit().unwrap(), EMPTY_TRIE_ROOT);
+
+        let mut hash_db = MemoryDB::new();
+        let mut trie = Trie::new(&mut hash_db);
+        trie.try_update(b"foo", b"bar").unwrap();
+        trie.try_delete(b"foo").unwrap();
+        assert_eq!(trie.commit().unwrap(), EMPTY_TRIE_ROOT);
+    }
 }